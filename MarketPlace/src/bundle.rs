@@ -0,0 +1,549 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+// bundle listings, several tokens sold as one lot
+// settlement moves every token to the market first, so a failed transfer can be rolled back
+
+pub const MAX_BUNDLE_ITEMS: usize = 5;
+const GAS_FOR_RESOLVE_BUNDLE_DELIVERY: Gas = BASE_GAS;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleItem {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: Option<u64>, // set from nft_on_approve
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bundle {
+    pub owner_id: AccountId,
+    pub items: Vec<BundleItem>,
+    pub ft_token_id: AccountId,
+    pub price: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleJson {
+    pub bundle_id: U64,
+    pub owner_id: AccountId,
+    pub items: Vec<BundleItem>,
+    pub ft_token_id: AccountId,
+    pub price: U128,
+    pub is_active: bool, // every token approved
+    pub transaction_fee: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleTokenArgs {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn create_bundle(&mut self, items: Vec<BundleTokenArgs>, ft_token_id: AccountId, price: U128) -> U64 {
+        let owner_id = env::predecessor_account_id();
+
+        assert!(
+            items.len() >= 2 && items.len() <= MAX_BUNDLE_ITEMS,
+            "Bundle must have between 2 and {} tokens",
+            MAX_BUNDLE_ITEMS
+        );
+        assert_eq!(ft_token_id.to_string(), NEAR, "NEAR support only");
+        assert!(
+            price.0 >= items.len() as u128 && price.0 < MAX_PRICE,
+            "price must be between {} and {}",
+            items.len(),
+            MAX_PRICE
+        );
+
+        let mut bundle_items: Vec<BundleItem> = Vec::new();
+        for item in items {
+            assert!(
+                self.approved_nft_contract_ids.contains(&item.nft_contract_id),
+                "nft_contract_id is not approved"
+            );
+            assert!(
+                !bundle_items.iter().any(|bundle_item| {
+                    bundle_item.nft_contract_id == item.nft_contract_id && bundle_item.token_id == item.token_id
+                }),
+                "Duplicated token in bundle"
+            );
+            bundle_items.push(BundleItem {
+                nft_contract_id: item.nft_contract_id,
+                token_id: item.token_id,
+                approval_id: None,
+            });
+        }
+
+        let storage_amount = self.storage_minimum_balance().0;
+        let owner_paid_storage = self.storage_deposits.get(&owner_id).unwrap_or(0);
+        let signer_storage_required =
+            (self.get_supply_by_owner_id(owner_id.clone()).0 + 1) as u128 * storage_amount;
+
+        assert!(
+            owner_paid_storage >= signer_storage_required,
+            "Insufficient storage paid: {}, for {} sales at {} rate of per sale",
+            owner_paid_storage,
+            signer_storage_required / storage_amount,
+            storage_amount,
+        );
+
+        let bundle_id = self.id_bundle;
+        self.id_bundle += 1;
+
+        let bundle = Bundle {
+            owner_id: owner_id.clone(),
+            items: bundle_items,
+            ft_token_id,
+            price,
+        };
+        self.bundles.insert(&bundle_id, &bundle);
+
+        let mut token_ids = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::ByOwnerIdInner {
+                    account_id_hash: hash_account_id(&owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        token_ids.insert(&make_key_owner_by_id_bundle(bundle_id));
+        self.by_owner_id.insert(&owner_id, &token_ids);
+
+        let mut bundle_ids = self.bundles_by_owner_id.get(&owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::BundlesByOwnerIdInner {
+                    account_id_hash: hash_account_id(&owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        bundle_ids.insert(&bundle_id);
+        self.bundles_by_owner_id.insert(&owner_id, &bundle_ids);
+        // fee policy of the first token's contract
+        self.internal_snapshot_transaction_fee(
+            &make_key_owner_by_id_bundle(bundle_id),
+            &bundle.items[0].nft_contract_id,
+            FeeMarketType::Sale,
+            Some(&owner_id),
+        );
+
+        env::log_str(
+            &json!({
+                "type": "create_bundle",
+                "params": {
+                    "bundle_id": U64(bundle_id),
+                    "owner_id": owner_id,
+                    "items": bundle.items,
+                    "ft_token_id": bundle.ft_token_id,
+                    "price": price,
+                }
+            })
+            .to_string(),
+        );
+
+        U64(bundle_id)
+    }
+
+    #[payable]
+    pub fn delete_bundle(&mut self, bundle_id: U64) {
+        let predecessor_account_id = env::predecessor_account_id();
+        if predecessor_account_id != self.owner_id {
+            assert_one_yocto();
+        }
+
+        let bundle = self.bundles.get(&bundle_id.0).expect("Bundle does not exist");
+        assert!(
+            [bundle.owner_id.clone(), self.owner_id.clone()].contains(&predecessor_account_id),
+            "Seller or owner only"
+        );
+
+        self.internal_remove_bundle(bundle_id.0, &bundle);
+
+        env::log_str(
+            &json!({
+                "type": "delete_bundle",
+                "params": {
+                    "bundle_id": bundle_id,
+                    "owner_id": bundle.owner_id,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    #[payable]
+    pub fn buy_bundle(&mut self, bundle_id: U64) -> Promise {
+        let bundle = self.bundles.get(&bundle_id.0).expect("Bundle does not exist");
+        let buyer_id = env::predecessor_account_id();
+
+        assert_ne!(buyer_id, bundle.owner_id, "Cannot buy your own sale");
+        assert!(
+            bundle.items.iter().all(|item| item.approval_id.is_some()),
+            "Bundle is not active, some tokens are not approved"
+        );
+        assert_eq!(
+            env::attached_deposit(),
+            bundle.price.0,
+            "The attached deposit should be exactly the price {}",
+            bundle.price.0
+        );
+
+        let transaction_fee = self.get_snapshot_transaction_fee(&make_key_owner_by_id_bundle(bundle_id.0));
+        self.internal_remove_bundle(bundle_id.0, &bundle);
+
+        // escrow every token in the market, each one reports the payout of its share
+        let shares = bundle_shares(bundle.price.0, bundle.items.len());
+        let mut transfers: Option<Promise> = None;
+        for (item, share) in bundle.items.iter().zip(shares.iter()) {
+            let transfer = ext_contract_transfer::ext(item.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer_payout(
+                    env::current_account_id(),
+                    item.token_id.clone(),
+                    item.approval_id,
+                    Some(U128(*share)),
+                    Some(50u32),
+                );
+            transfers = Some(match transfers {
+                Some(transfers) => transfers.and(transfer),
+                None => transfer,
+            });
+        }
+
+        transfers.unwrap().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(gas_for_resolve_bundle(bundle.items.len()))
+                .resolve_bundle_purchase(bundle_id, buyer_id, bundle, Some(U128(transaction_fee))),
+        )
+    }
+
+    #[private]
+    pub fn resolve_bundle_purchase(
+        &mut self,
+        bundle_id: U64,
+        buyer_id: AccountId,
+        bundle: Bundle,
+        transaction_fee: Option<U128>, // snapshot taken when the bundle was created
+    ) -> bool {
+        let shares = bundle_shares(bundle.price.0, bundle.items.len());
+        let results: Vec<Option<Vec<u8>>> = (0..env::promise_results_count())
+            .map(|index| match env::promise_result(index) {
+                PromiseResult::Successful(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+
+        if results.iter().any(|result| result.is_none()) {
+            // rollback, tokens already in escrow go back to the seller
+            for (item, result) in bundle.items.iter().zip(results.iter()) {
+                if result.is_some() {
+                    self.internal_deliver_bundle_item(bundle.owner_id.clone(), item.clone());
+                }
+            }
+            self.internal_credit_near(buyer_id.clone(), bundle.price.0);
+
+            env::log_str(
+                &json!({
+                    "type": "resolve_bundle_purchase_fail",
+                    "params": {
+                        "bundle_id": bundle_id,
+                        "owner_id": bundle.owner_id,
+                        "buyer_id": buyer_id,
+                        "price": bundle.price,
+                    }
+                })
+                .to_string(),
+            );
+            return false;
+        }
+
+        // aggregate the payouts of every token, a bad payout pays the whole share to the seller
+        let mut payout: HashMap<AccountId, u128> = HashMap::new();
+        for ((result, share), item) in results.iter().zip(shares.iter()).zip(bundle.items.iter()) {
+            let token_payout = parse_payout(result.as_ref().unwrap(), *share).unwrap_or_else(|| {
+                let mut token_payout = PayoutHashMap::new();
+                token_payout.insert(bundle.owner_id.clone(), U128(*share));
+                token_payout
+            });
+            for (receiver_id, amount) in token_payout {
+                *payout.entry(receiver_id).or_insert(0) += amount.0;
+            }
+
+            // the token is sold, its listing, offers and trades are closed
+            self.internal_clear_bundle_item(&bundle.owner_id, item);
+            self.internal_deliver_bundle_item(buyer_id.clone(), item.clone());
+        }
+
+        let transaction_fee = transaction_fee
+            .map(|transaction_fee| transaction_fee.0)
+            .unwrap_or_else(|| self.calculate_current_transaction_fee());
+        let treasury_fee = bundle.price.0 * transaction_fee / (MAX_TREASURY_PERCENTAGE as u128);
+        self.internal_add_seller_volume(&bundle.owner_id, bundle.price.0);
+        let mut treasury_fee_splits = vec![];
+        for (receiver_id, amount) in payout.iter() {
+            if receiver_id == &bundle.owner_id {
+                let amount_after = amount.saturating_sub(treasury_fee);
+                if amount_after > 0 {
+                    self.internal_credit_near(receiver_id.clone(), amount_after);
+                }
+                treasury_fee_splits = self.internal_credit_treasury_fee((*amount).min(treasury_fee));
+            } else {
+                self.internal_credit_near(receiver_id.clone(), *amount);
+            }
+        }
+
+        env::log_str(
+            &json!({
+                "type": "resolve_bundle_purchase",
+                "params": {
+                    "bundle_id": bundle_id,
+                    "owner_id": bundle.owner_id,
+                    "buyer_id": buyer_id,
+                    "items": bundle.items,
+                    "price": bundle.price,
+                    "payout": payout.iter().map(|(receiver_id, amount)| (receiver_id.to_string(), U128(*amount))).collect::<HashMap<String, U128>>(),
+                    "treasury_fee_splits": treasury_fee_splits,
+                }
+            })
+            .to_string(),
+        );
+
+        true
+    }
+
+    #[private]
+    pub fn resolve_bundle_delivery(&mut self, receiver_id: AccountId, item: BundleItem) -> bool {
+        if is_promise_success() {
+            return true;
+        }
+
+        // the token stays in the market until the receiver claims it
+        let contract_and_token_id = format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id);
+        self.bundle_deliveries.insert(&contract_and_token_id, &receiver_id);
+        env::log_str(
+            &json!({
+                "type": "bundle_delivery_fail",
+                "params": {
+                    "receiver_id": receiver_id,
+                    "nft_contract_id": item.nft_contract_id,
+                    "token_id": item.token_id,
+                }
+            })
+            .to_string(),
+        );
+        false
+    }
+
+    #[payable]
+    pub fn claim_bundle_item(&mut self, nft_contract_id: AccountId, token_id: TokenId) -> Promise {
+        assert_one_yocto();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let receiver_id = self
+            .bundle_deliveries
+            .get(&contract_and_token_id)
+            .expect("No pending delivery for this token");
+        assert_eq!(env::predecessor_account_id(), receiver_id, "Receiver only");
+
+        self.bundle_deliveries.remove(&contract_and_token_id);
+        self.internal_deliver_bundle_item(
+            receiver_id,
+            BundleItem {
+                nft_contract_id,
+                token_id,
+                approval_id: None,
+            },
+        )
+    }
+
+    pub fn get_bundle_delivery(&self, nft_contract_id: AccountId, token_id: TokenId) -> Option<AccountId> {
+        self.bundle_deliveries
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+    }
+
+    pub fn get_bundle(&self, bundle_id: U64) -> Option<BundleJson> {
+        self.bundles
+            .get(&bundle_id.0)
+            .map(|bundle| self.internal_bundle_json(bundle_id.0, bundle))
+    }
+
+    pub fn get_bundles_by_owner(
+        &self,
+        owner_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<BundleJson> {
+        self.bundles_by_owner_id
+            .get(&owner_id)
+            .map(|bundle_ids| {
+                let (start_index, limit) = pagination(from_index, limit, bundle_ids.len());
+                bundle_ids
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|bundle_id| self.internal_bundle_json(bundle_id, self.bundles.get(&bundle_id).unwrap()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_add_bundle_approval(
+        &mut self,
+        bundle_id: U64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) {
+        let mut bundle = self.bundles.get(&bundle_id.0).expect("Bundle does not exist");
+        assert_eq!(bundle.owner_id, owner_id, "Bundle owner only");
+
+        let item = bundle
+            .items
+            .iter_mut()
+            .find(|item| item.nft_contract_id == nft_contract_id && item.token_id == token_id)
+            .expect("Token is not in bundle");
+        item.approval_id = Some(approval_id);
+
+        let is_active = bundle.items.iter().all(|item| item.approval_id.is_some());
+        self.bundles.insert(&bundle_id.0, &bundle);
+
+        env::log_str(
+            &json!({
+                "type": "add_bundle_item",
+                "params": {
+                    "bundle_id": bundle_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "approval_id": approval_id,
+                    "is_active": is_active,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    fn internal_bundle_json(&self, bundle_id: u64, bundle: Bundle) -> BundleJson {
+        BundleJson {
+            bundle_id: U64(bundle_id),
+            is_active: bundle.items.iter().all(|item| item.approval_id.is_some()),
+            owner_id: bundle.owner_id,
+            items: bundle.items,
+            ft_token_id: bundle.ft_token_id,
+            price: bundle.price,
+            transaction_fee: U128(self.get_snapshot_transaction_fee(&make_key_owner_by_id_bundle(bundle_id))),
+        }
+    }
+
+    // escrow to receiver, a failed transfer is kept for claim_bundle_item
+    fn internal_deliver_bundle_item(&mut self, receiver_id: AccountId, item: BundleItem) -> Promise {
+        ext_contract_transfer::ext(item.nft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(receiver_id.clone(), item.token_id.clone(), None, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_BUNDLE_DELIVERY)
+                    .resolve_bundle_delivery(receiver_id, item),
+            )
+    }
+
+    fn internal_clear_bundle_item(&mut self, owner_id: &AccountId, item: &BundleItem) {
+        self.internal_delete_market_data(&item.nft_contract_id, &item.token_id);
+
+        let contract_and_token_id = format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id);
+        if let Some(by_token) = self.offers_by_token.get(&contract_and_token_id) {
+            for contract_account_id_token_id in by_token.to_vec() {
+                let buyer_id = triple_account_id(&contract_account_id_token_id);
+                if let Some(offer_data) =
+                    self.internal_delete_offer(item.nft_contract_id.clone(), buyer_id, item.token_id.clone())
+                {
+                    self.internal_credit_near(offer_data.buyer_id, offer_data.price);
+                }
+            }
+        }
+        if let Some(by_token) = self.trades_by_token.get(&contract_and_token_id) {
+            for (buyer_contract_account_id_token_id, contract_account_id_token_id) in by_token.to_vec() {
+                let buyer_id = triple_account_id(&buyer_contract_account_id_token_id);
+                if let Some(trade_data) = self.internal_delete_trade(
+                    buyer_id,
+                    buyer_contract_account_id_token_id.clone(),
+                    contract_account_id_token_id.clone(),
+                ) {
+                    self.internal_release_trade(&buyer_contract_account_id_token_id, &contract_account_id_token_id, &trade_data);
+                }
+            }
+        }
+        self.internal_remove_trade_list(&make_triple(&item.nft_contract_id, owner_id, &item.token_id));
+    }
+
+    fn internal_remove_bundle(&mut self, bundle_id: u64, bundle: &Bundle) {
+        self.bundles.remove(&bundle_id);
+        self.transaction_fee_snapshots.remove(&make_key_owner_by_id_bundle(bundle_id));
+
+        if let Some(mut by_owner_id) = self.by_owner_id.get(&bundle.owner_id) {
+            by_owner_id.remove(&make_key_owner_by_id_bundle(bundle_id));
+            if by_owner_id.is_empty() {
+                self.by_owner_id.remove(&bundle.owner_id);
+            } else {
+                self.by_owner_id.insert(&bundle.owner_id, &by_owner_id);
+            }
+        }
+
+        if let Some(mut bundle_ids) = self.bundles_by_owner_id.get(&bundle.owner_id) {
+            bundle_ids.remove(&bundle_id);
+            if bundle_ids.is_empty() {
+                self.bundles_by_owner_id.remove(&bundle.owner_id);
+            } else {
+                self.bundles_by_owner_id.insert(&bundle.owner_id, &bundle_ids);
+            }
+        }
+    }
+}
+
+// every token is delivered with its own callback
+fn gas_for_resolve_bundle(len: usize) -> Gas {
+    Gas((GAS_FOR_NFT_TRANSFER.0 + GAS_FOR_RESOLVE_BUNDLE_DELIVERY.0) * len as u64 + GAS_FOR_ROYALTIES.0)
+}
+
+// price split evenly between tokens, the remainder goes to the first one
+pub(crate) fn bundle_shares(price: u128, len: usize) -> Vec<u128> {
+    let share = price / len as u128;
+    let mut shares = vec![share; len];
+    shares[0] += price - share * len as u128;
+    shares
+}
+
+pub(crate) fn parse_payout(value: &[u8], balance: u128) -> Option<PayoutHashMap> {
+    let payout = near_sdk::serde_json::from_slice::<PayoutHashMap>(value)
+        .ok()
+        .or_else(|| {
+            near_sdk::serde_json::from_slice::<Payout>(value)
+                .ok()
+                .map(|payout| payout.payout)
+        })?;
+
+    let mut remainder = balance;
+    for value in payout.values() {
+        remainder = remainder.checked_sub(value.0)?;
+    }
+    if remainder <= 100 {
+        Some(payout)
+    } else {
+        None
+    }
+}
+
+fn make_key_owner_by_id_bundle(bundle_id: u64) -> String {
+    format!("{}{}bundle", bundle_id, DELIMETER)
+}
@@ -0,0 +1,557 @@
+use crate::*;
+
+// collection-wide and trait-based offers, any holder can fill them one token at a time
+
+pub const GAS_FOR_NFT_TOKEN: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_MATCH: Gas = Gas(GAS_FOR_NFT_TRANSFER.0 + GAS_FOR_ROYALTIES.0 + BASE_GAS.0 * 2);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum OfferScope {
+    Token(TokenId),
+    Series(TokenSeriesId),
+    Event(TokenSeriesId), // event series plus the objects that reference it
+    Contract,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CollectionOffer {
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub scope: OfferScope,
+    pub extra: Option<String>, // token metadata extra must contain it
+    pub ft_token_id: AccountId,
+    pub price: u128, // per token
+    pub quantity: u32,
+    pub filled: u32,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionOfferJson {
+    pub offer_id: U64,
+    pub buyer_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub scope: OfferScope,
+    pub extra: Option<String>,
+    pub ft_token_id: AccountId,
+    pub price: U128,
+    pub quantity: u32,
+    pub filled: u32,
+    pub expires_at: Option<U64>,
+    pub transaction_fee: U128,
+}
+
+// subset of nft_token used for matching
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchTokenMetadata {
+    pub extra: Option<String>,
+    pub reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchToken {
+    pub owner_id: AccountId,
+    pub metadata: Option<MatchTokenMetadata>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn add_collection_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        scope: OfferScope,
+        extra: Option<String>,
+        ft_token_id: AccountId,
+        price: U128,
+        quantity: u32,
+        expires_at: Option<U64>,
+    ) -> U64 {
+        assert!(quantity > 0, "quantity must be at least 1");
+        assert!(
+            price.0 > 0 && price.0 < MAX_PRICE,
+            "price must be between 0 and {}",
+            MAX_PRICE
+        );
+        assert_eq!(
+            env::attached_deposit(),
+            price.0 * quantity as u128,
+            "Attached deposit != price * quantity"
+        );
+        assert_eq!(
+            ft_token_id.to_string(),
+            "near",
+            "Only NEAR is supported"
+        );
+        assert!(
+            self.approved_nft_contract_ids.contains(&nft_contract_id),
+            "nft_contract_id is not approved"
+        );
+        if let OfferScope::Token(_) = scope {
+            assert_eq!(quantity, 1, "Token scope offers have a quantity of 1");
+        }
+        if let Some(expires_at) = expires_at {
+            assert!(
+                expires_at.0 > env::block_timestamp(),
+                "expires_at is less than current block_timestamp"
+            );
+        }
+
+        let buyer_id = env::predecessor_account_id();
+
+        let storage_amount = self.storage_minimum_balance().0;
+        let owner_paid_storage = self.storage_deposits.get(&buyer_id).unwrap_or(0);
+        let signer_storage_required =
+            (self.get_supply_by_owner_id(buyer_id.clone()).0 + 1) as u128 * storage_amount;
+
+        assert!(
+            owner_paid_storage >= signer_storage_required,
+            "Insufficient storage paid: {}, for {} offer at {} rate of per offer",
+            owner_paid_storage,
+            signer_storage_required / storage_amount,
+            storage_amount,
+        );
+
+        let offer_id = self.id_collection_offer;
+        self.id_collection_offer += 1;
+
+        self.collection_offers.insert(
+            &offer_id,
+            &CollectionOffer {
+                buyer_id: buyer_id.clone(),
+                nft_contract_id: nft_contract_id.clone(),
+                scope: scope.clone(),
+                extra: extra.clone(),
+                ft_token_id: ft_token_id.clone(),
+                price: price.0,
+                quantity,
+                filled: 0,
+                expires_at: expires_at.map(|x| x.0),
+            },
+        );
+
+        let mut token_ids = self.by_owner_id.get(&buyer_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::ByOwnerIdInner {
+                    account_id_hash: hash_account_id(&buyer_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        token_ids.insert(&make_key_owner_by_id_collection_offer(offer_id));
+        self.by_owner_id.insert(&buyer_id, &token_ids);
+
+        let mut offer_ids = self.collection_offers_by_nft_contract_id.get(&nft_contract_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::CollectionOffersByNftContractIdInner {
+                    account_id_hash: hash_account_id(&nft_contract_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        offer_ids.insert(&offer_id);
+        self.collection_offers_by_nft_contract_id.insert(&nft_contract_id, &offer_ids);
+        self.internal_snapshot_transaction_fee(
+            &make_key_owner_by_id_collection_offer(offer_id),
+            &nft_contract_id,
+            FeeMarketType::Offer,
+            None,
+        );
+
+        env::log_str(
+            &json!({
+                "type": "add_collection_offer",
+                "params": {
+                    "offer_id": U64(offer_id),
+                    "buyer_id": buyer_id,
+                    "nft_contract_id": nft_contract_id,
+                    "scope": scope,
+                    "extra": extra,
+                    "ft_token_id": ft_token_id,
+                    "price": price,
+                    "quantity": quantity,
+                    "expires_at": expires_at,
+                }
+            })
+            .to_string(),
+        );
+
+        U64(offer_id)
+    }
+
+    #[payable]
+    pub fn delete_collection_offer(&mut self, offer_id: U64) {
+        assert_one_yocto();
+        let collection_offer = self
+            .collection_offers
+            .get(&offer_id.0)
+            .expect("Offer does not exist");
+
+        assert_eq!(
+            collection_offer.buyer_id,
+            env::predecessor_account_id(),
+            "Caller not offer's buyer"
+        );
+
+        self.internal_delete_collection_offer(offer_id.0, false);
+    }
+
+    // permissionless, refunds expired collection offers within collection_offers[from_index..from_index + limit]
+    pub fn cleanup_expired_collection_offers(&mut self, from_index: Option<U128>, limit: Option<u64>) -> U64 {
+        let (start_index, limit) = pagination(from_index, limit, self.collection_offers.len());
+        let current_time = env::block_timestamp();
+
+        let expired_offer_ids: Vec<u64> = self
+            .collection_offers
+            .iter()
+            .skip(start_index)
+            .take(limit)
+            .filter(|(_, collection_offer)| {
+                collection_offer
+                    .expires_at
+                    .is_some_and(|expires_at| current_time >= expires_at)
+            })
+            .map(|(offer_id, _)| offer_id)
+            .collect();
+
+        for offer_id in expired_offer_ids.iter() {
+            self.internal_delete_collection_offer(*offer_id, true);
+        }
+
+        U64(expired_offer_ids.len() as u64)
+    }
+
+    pub fn get_collection_offer(&self, offer_id: U64) -> Option<CollectionOfferJson> {
+        self.collection_offers
+            .get(&offer_id.0)
+            .map(|collection_offer| self.internal_collection_offer_json(offer_id.0, collection_offer))
+    }
+
+    pub fn get_collection_offers(
+        &self,
+        nft_contract_id: Option<AccountId>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CollectionOfferJson> {
+        let nft_contract_id = match nft_contract_id {
+            Some(nft_contract_id) => nft_contract_id,
+            None => {
+                let (start_index, limit) = pagination(from_index, limit, self.collection_offers.len());
+                return self
+                    .collection_offers
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|(offer_id, collection_offer)| self.internal_collection_offer_json(offer_id, collection_offer))
+                    .collect();
+            }
+        };
+
+        self.collection_offers_by_nft_contract_id
+            .get(&nft_contract_id)
+            .map(|offer_ids| {
+                let (start_index, limit) = pagination(from_index, limit, offer_ids.len());
+                offer_ids
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|offer_id| {
+                        self.internal_collection_offer_json(offer_id, self.collection_offers.get(&offer_id).unwrap())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // offer_data carries the reserved unit, the offer itself may be gone by now
+    #[private]
+    pub fn resolve_collection_offer_match(
+        &mut self,
+        offer_id: U64,
+        scope: OfferScope,
+        extra: Option<String>,
+        offer_data: OfferData,
+        token_id: TokenId,
+        seller_id: AccountId,
+        approval_id: u64,
+        transaction_fee: Option<U128>,
+    ) -> PromiseOrValue<bool> {
+        let token: Option<MatchToken> = promise_result_as_success()
+            .and_then(|value| near_sdk::serde_json::from_slice::<Option<MatchToken>>(&value).ok())
+            .flatten();
+
+        let is_match = token.is_some_and(|token| {
+            token.owner_id == seller_id
+                && collection_offer_matches(&scope, &extra, &token_id, token.metadata.as_ref())
+        });
+
+        if !is_match {
+            match self.collection_offers.get(&offer_id.0) {
+                Some(mut collection_offer) => {
+                    collection_offer.filled -= 1;
+                    self.collection_offers.insert(&offer_id.0, &collection_offer);
+                }
+                None => self.internal_credit_near(offer_data.buyer_id.clone(), offer_data.price),
+            }
+            self.internal_update_approval_id(&approval_id, &offer_data.nft_contract_id, &seller_id, &token_id);
+            env::log_str("Token does not match offer");
+            return PromiseOrValue::Value(false);
+        }
+
+        let transaction_fee = transaction_fee
+            .map(|transaction_fee| transaction_fee.0)
+            .unwrap_or_else(|| self.calculate_current_transaction_fee());
+        self.internal_transfer_collection_offer_unit(offer_id.0, offer_data, token_id, seller_id, approval_id, transaction_fee)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_accept_collection_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        offer_id: U64,
+        token_id: TokenId,
+        seller_id: AccountId,
+        approval_id: u64,
+        price: u128,
+    ) -> PromiseOrValue<bool> {
+        let collection_offer = self.collection_offers.get(&offer_id.0);
+
+        let collection_offer = match collection_offer {
+            Some(collection_offer) => collection_offer,
+            None => {
+                self.internal_update_approval_id(&approval_id, &nft_contract_id, &seller_id, &token_id);
+                env::log_str("Offer does not exist");
+                return PromiseOrValue::Value(false);
+            }
+        };
+
+        if collection_offer
+            .expires_at
+            .is_some_and(|expires_at| env::block_timestamp() >= expires_at)
+        {
+            self.internal_update_approval_id(&approval_id, &nft_contract_id, &seller_id, &token_id);
+            env::log_str("Offer has expired");
+            return PromiseOrValue::Value(false);
+        }
+
+        assert_eq!(collection_offer.nft_contract_id, nft_contract_id, "Offer is for another nft_contract_id");
+        assert_eq!(collection_offer.price, price);
+        assert_ne!(collection_offer.buyer_id, seller_id, "Cannot accept your own offer");
+
+        let series_id = token_id.split(':').next().unwrap().to_string();
+        let needs_token = collection_offer.extra.is_some()
+            || matches!(&collection_offer.scope, OfferScope::Event(event_id) if event_id != &series_id);
+
+        if !needs_token && !collection_offer_matches(&collection_offer.scope, &None, &token_id, None) {
+            self.internal_update_approval_id(&approval_id, &nft_contract_id, &seller_id, &token_id);
+            env::log_str("Token does not match offer");
+            return PromiseOrValue::Value(false);
+        }
+
+        self.internal_delete_market_data(&nft_contract_id, &token_id);
+
+        // reserve one unit so parallel fills can't go over quantity
+        let transaction_fee = self.get_snapshot_transaction_fee(&make_key_owner_by_id_collection_offer(offer_id.0));
+        let mut collection_offer = collection_offer;
+        collection_offer.filled += 1;
+        if collection_offer.filled >= collection_offer.quantity {
+            self.internal_remove_collection_offer(offer_id.0, &collection_offer);
+        } else {
+            self.collection_offers.insert(&offer_id.0, &collection_offer);
+        }
+
+        // each fill settles like a single token offer
+        let offer_data = OfferData {
+            buyer_id: collection_offer.buyer_id.clone(),
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: Some(token_id.clone()),
+            token_series_id: None,
+            ft_token_id: collection_offer.ft_token_id.clone(),
+            price: collection_offer.price,
+        };
+
+        if !needs_token {
+            return self.internal_transfer_collection_offer_unit(
+                offer_id.0,
+                offer_data,
+                token_id,
+                seller_id,
+                approval_id,
+                transaction_fee,
+            );
+        }
+
+        PromiseOrValue::Promise(
+            ext_contract_transfer::ext(nft_contract_id.clone())
+                .with_static_gas(GAS_FOR_NFT_TOKEN)
+                .nft_token(token_id.clone())
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_MATCH)
+                        .resolve_collection_offer_match(
+                            offer_id,
+                            collection_offer.scope,
+                            collection_offer.extra,
+                            offer_data,
+                            token_id,
+                            seller_id,
+                            approval_id,
+                            Some(U128(transaction_fee)),
+                        ),
+                ),
+        )
+    }
+
+    fn internal_transfer_collection_offer_unit(
+        &mut self,
+        offer_id: u64,
+        offer_data: OfferData,
+        token_id: TokenId,
+        seller_id: AccountId,
+        approval_id: u64,
+        transaction_fee: u128,
+    ) -> PromiseOrValue<bool> {
+        env::log_str(
+            &json!({
+                "type": "accept_collection_offer",
+                "params": {
+                    "offer_id": U64(offer_id),
+                    "buyer_id": offer_data.buyer_id,
+                    "seller_id": seller_id,
+                    "nft_contract_id": offer_data.nft_contract_id,
+                    "token_id": token_id,
+                    "price": U128(offer_data.price),
+                }
+            })
+            .to_string(),
+        );
+
+        PromiseOrValue::Promise(
+            ext_contract_transfer::ext(offer_data.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer_payout(
+                    offer_data.buyer_id.clone(),
+                    token_id.clone(),
+                    Some(approval_id),
+                    Some(U128::from(offer_data.price)),
+                    Some(50u32),
+                )
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_ROYALTIES)
+                        .resolve_offer(seller_id, offer_data, token_id, Some(U128(transaction_fee))),
+                ),
+        )
+    }
+
+    // refunds the units that are not filled yet
+    fn internal_delete_collection_offer(&mut self, offer_id: u64, expired: bool) {
+        let collection_offer = self
+            .collection_offers
+            .get(&offer_id)
+            .expect("Offer does not exist");
+
+        self.internal_remove_collection_offer(offer_id, &collection_offer);
+
+        let refund = collection_offer.price * (collection_offer.quantity - collection_offer.filled) as u128;
+        if refund > 0 {
+            self.internal_credit_near(collection_offer.buyer_id.clone(), refund);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "delete_collection_offer",
+                "params": {
+                    "offer_id": U64(offer_id),
+                    "buyer_id": collection_offer.buyer_id,
+                    "nft_contract_id": collection_offer.nft_contract_id,
+                    "refund": U128(refund),
+                    "expired": expired,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    fn internal_collection_offer_json(&self, offer_id: u64, collection_offer: CollectionOffer) -> CollectionOfferJson {
+        CollectionOfferJson {
+            offer_id: U64(offer_id),
+            buyer_id: collection_offer.buyer_id,
+            nft_contract_id: collection_offer.nft_contract_id,
+            scope: collection_offer.scope,
+            extra: collection_offer.extra,
+            ft_token_id: collection_offer.ft_token_id,
+            price: U128(collection_offer.price),
+            quantity: collection_offer.quantity,
+            filled: collection_offer.filled,
+            expires_at: collection_offer.expires_at.map(U64),
+            transaction_fee: U128(self.get_snapshot_transaction_fee(&make_key_owner_by_id_collection_offer(offer_id))),
+        }
+    }
+
+    fn internal_remove_collection_offer(&mut self, offer_id: u64, collection_offer: &CollectionOffer) {
+        self.collection_offers.remove(&offer_id);
+        self.transaction_fee_snapshots.remove(&make_key_owner_by_id_collection_offer(offer_id));
+
+        if let Some(mut by_owner_id) = self.by_owner_id.get(&collection_offer.buyer_id) {
+            by_owner_id.remove(&make_key_owner_by_id_collection_offer(offer_id));
+            if by_owner_id.is_empty() {
+                self.by_owner_id.remove(&collection_offer.buyer_id);
+            } else {
+                self.by_owner_id.insert(&collection_offer.buyer_id, &by_owner_id);
+            }
+        }
+
+        if let Some(mut offer_ids) = self.collection_offers_by_nft_contract_id.get(&collection_offer.nft_contract_id) {
+            offer_ids.remove(&offer_id);
+            if offer_ids.is_empty() {
+                self.collection_offers_by_nft_contract_id.remove(&collection_offer.nft_contract_id);
+            } else {
+                self.collection_offers_by_nft_contract_id.insert(&collection_offer.nft_contract_id, &offer_ids);
+            }
+        }
+    }
+}
+
+pub(crate) fn collection_offer_matches(
+    scope: &OfferScope,
+    extra: &Option<String>,
+    token_id: &TokenId,
+    metadata: Option<&MatchTokenMetadata>,
+) -> bool {
+    let series_id = token_id.split(':').next().unwrap();
+
+    let scope_match = match scope {
+        OfferScope::Token(offer_token_id) => offer_token_id == token_id,
+        OfferScope::Series(token_series_id) => token_series_id == series_id,
+        OfferScope::Event(token_event_id) => {
+            token_event_id == series_id
+                || metadata
+                    .and_then(|metadata| metadata.reference.as_ref())
+                    .is_some_and(|reference| reference == token_event_id)
+        }
+        OfferScope::Contract => true,
+    };
+
+    let extra_match = match extra {
+        Some(extra) => metadata
+            .and_then(|metadata| metadata.extra.as_ref())
+            .is_some_and(|token_extra| token_extra.contains(extra.as_str())),
+        None => true,
+    };
+
+    scope_match && extra_match
+}
+
+fn make_key_owner_by_id_collection_offer(offer_id: u64) -> String {
+    format!("{}{}collection_offer", offer_id, DELIMETER)
+}
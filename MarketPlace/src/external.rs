@@ -13,4 +13,5 @@ trait ExtContractTransfer {
         max_len_payout: Option<u32>,
     );
     fn nft_transfer(&mut self, receiver_id: AccountId, token_id: TokenId, approval_id: Option<u64>, memo: Option<String>);
+    fn nft_token(&self, token_id: TokenId);
 }
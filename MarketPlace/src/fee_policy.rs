@@ -0,0 +1,185 @@
+use crate::*;
+
+// fee policies on top of the global transaction fee
+// lookup order: contract and market type, contract, market type, then the global fee
+// a promo window makes the fee zero, seller volume discounts reduce whatever fee applies
+
+const ANY: &str = "*";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FeeMarketType {
+    Sale,
+    Auction,
+    Offer,
+    Trade,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VolumeDiscountTier {
+    pub min_volume: U128,   // seller's settled volume in yoctoNEAR
+    pub discount_bps: u16, // share of the fee waived
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeePromo {
+    pub start_time: TimestampSec,
+    pub end_time: TimestampSec,
+    pub nft_contract_id: Option<AccountId>, // every contract when not set
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_fee_policy(
+        &mut self,
+        nft_contract_id: Option<AccountId>,
+        market_type: Option<FeeMarketType>,
+        fee: Option<u16>,
+    ) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        let key = fee_policy_key(nft_contract_id.as_ref(), market_type);
+        match fee {
+            Some(fee) => {
+                assert!(fee < MAX_TREASURY_PERCENTAGE, "fee is higher than {}", MAX_TREASURY_PERCENTAGE);
+                self.fee_policies.insert(&key, &fee);
+            }
+            None => {
+                self.fee_policies.remove(&key);
+            }
+        }
+    }
+
+    #[payable]
+    pub fn set_volume_discounts(&mut self, tiers: Vec<VolumeDiscountTier>) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        for (index, tier) in tiers.iter().enumerate() {
+            assert!(
+                tier.discount_bps <= MAX_TREASURY_PERCENTAGE,
+                "discount is higher than {}",
+                MAX_TREASURY_PERCENTAGE
+            );
+            if index > 0 {
+                assert!(
+                    tier.min_volume.0 > tiers[index - 1].min_volume.0,
+                    "tiers must be sorted by min_volume"
+                );
+            }
+        }
+        self.volume_discount_tiers = tiers;
+    }
+
+    #[payable]
+    pub fn set_fee_promo(&mut self, fee_promo: Option<FeePromo>) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        if let Some(fee_promo) = fee_promo.as_ref() {
+            assert!(fee_promo.end_time > fee_promo.start_time, "end_time is less than start_time");
+        }
+        self.fee_promo = fee_promo;
+    }
+
+    pub fn get_fee_policy(&self, nft_contract_id: Option<AccountId>, market_type: Option<FeeMarketType>) -> Option<u16> {
+        self.fee_policies.get(&fee_policy_key(nft_contract_id.as_ref(), market_type))
+    }
+
+    pub fn get_volume_discounts(&self) -> Vec<VolumeDiscountTier> {
+        self.volume_discount_tiers.clone()
+    }
+
+    pub fn get_fee_promo(&self) -> Option<FeePromo> {
+        self.fee_promo.clone()
+    }
+
+    pub fn get_seller_volume(&self, account_id: AccountId) -> U128 {
+        self.seller_volumes.get(&account_id).unwrap_or(0).into()
+    }
+
+    // fee a new listing, offer or trade would be charged right now
+    // the seller discount is frozen only where the seller is known at creation: listings, bundles
+    // and trades with a receiver, offers keep the undiscounted fee
+    pub fn get_transaction_fee_preview(
+        &self,
+        nft_contract_id: AccountId,
+        market_type: FeeMarketType,
+        seller_id: Option<AccountId>,
+    ) -> U128 {
+        let transaction_fee = self.internal_policy_transaction_fee(&nft_contract_id, market_type);
+        U128(match seller_id {
+            Some(seller_id) => self.internal_apply_volume_discount(transaction_fee, &seller_id),
+            None => transaction_fee,
+        })
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_policy_transaction_fee(&self, nft_contract_id: &AccountId, market_type: FeeMarketType) -> u128 {
+        let now = to_sec(env::block_timestamp());
+        let in_promo = self.fee_promo.as_ref().is_some_and(|fee_promo| {
+            now >= fee_promo.start_time
+                && now < fee_promo.end_time
+                && fee_promo.nft_contract_id.as_ref().is_none_or(|promo_contract_id| promo_contract_id == nft_contract_id)
+        });
+        if in_promo {
+            return 0;
+        }
+
+        let policy_fee = [
+            fee_policy_key(Some(nft_contract_id), Some(market_type)),
+            fee_policy_key(Some(nft_contract_id), None),
+            fee_policy_key(None, Some(market_type)),
+        ]
+        .iter()
+        .find_map(|key| self.fee_policies.get(key));
+        if let Some(policy_fee) = policy_fee {
+            return policy_fee as u128;
+        }
+
+        // global fee, a scheduled next_fee applies once its start_time has passed
+        match (self.transaction_fee.next_fee, self.transaction_fee.start_time) {
+            (Some(next_fee), Some(start_time)) if now >= start_time => next_fee as u128,
+            _ => self.transaction_fee.current_fee as u128,
+        }
+    }
+
+    pub(crate) fn internal_apply_volume_discount(&self, transaction_fee: u128, seller_id: &AccountId) -> u128 {
+        let volume = self.seller_volumes.get(seller_id).unwrap_or(0);
+        let discount_bps = self
+            .volume_discount_tiers
+            .iter()
+            .rev()
+            .find(|tier| volume >= tier.min_volume.0)
+            .map(|tier| tier.discount_bps)
+            .unwrap_or(0);
+        transaction_fee * (MAX_TREASURY_PERCENTAGE - discount_bps) as u128 / (MAX_TREASURY_PERCENTAGE as u128)
+    }
+
+    pub(crate) fn internal_add_seller_volume(&mut self, seller_id: &AccountId, amount: Balance) {
+        let volume = self.seller_volumes.get(seller_id).unwrap_or(0) + amount;
+        self.seller_volumes.insert(seller_id, &volume);
+    }
+}
+
+fn fee_policy_key(nft_contract_id: Option<&AccountId>, market_type: Option<FeeMarketType>) -> String {
+    let market_type = match market_type {
+        Some(FeeMarketType::Sale) => "sale",
+        Some(FeeMarketType::Auction) => "auction",
+        Some(FeeMarketType::Offer) => "offer",
+        Some(FeeMarketType::Trade) => "trade",
+        None => ANY,
+    };
+    format!(
+        "{}{}{}",
+        nft_contract_id.map(|nft_contract_id| nft_contract_id.as_str()).unwrap_or(ANY),
+        DELIMETER,
+        market_type
+    )
+}
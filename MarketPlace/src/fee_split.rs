@@ -0,0 +1,94 @@
+use crate::*;
+
+// treasury fees split among beneficiaries, shares in basis points summing to MAX_TREASURY_PERCENTAGE
+// without a split table the whole fee goes to treasury_id
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeShare {
+    pub account_id: AccountId,
+    pub bps: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSharePayout {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_treasury_fee_splits(&mut self, splits: Vec<FeeShare>) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        if !splits.is_empty() {
+            let mut total_bps: u32 = 0;
+            for (index, share) in splits.iter().enumerate() {
+                assert!(share.bps > 0, "share of {} is zero", share.account_id);
+                assert!(
+                    splits[..index].iter().all(|other| other.account_id != share.account_id),
+                    "{} is listed twice",
+                    share.account_id
+                );
+                total_bps += share.bps as u32;
+            }
+            assert_eq!(
+                total_bps, MAX_TREASURY_PERCENTAGE as u32,
+                "shares must sum to {}",
+                MAX_TREASURY_PERCENTAGE
+            );
+        }
+
+        env::log_str(
+            &json!({
+                "type": "set_treasury_fee_splits",
+                "params": {
+                    "splits": splits,
+                }
+            })
+            .to_string(),
+        );
+        self.treasury_fee_splits = splits;
+    }
+
+    pub fn get_treasury_fee_splits(&self) -> Vec<FeeShare> {
+        self.treasury_fee_splits.clone()
+    }
+}
+
+impl Contract {
+    // credits each beneficiary its share, rounding dust goes to the last one
+    pub(crate) fn internal_credit_treasury_fee(&mut self, treasury_fee: Balance) -> Vec<FeeSharePayout> {
+        if treasury_fee == 0 {
+            return vec![];
+        }
+        if self.treasury_fee_splits.is_empty() {
+            self.internal_credit_near(self.treasury_id.clone(), treasury_fee);
+            return vec![FeeSharePayout {
+                account_id: self.treasury_id.clone(),
+                amount: U128(treasury_fee),
+            }];
+        }
+
+        let splits = self.treasury_fee_splits.clone();
+        let mut remaining = treasury_fee;
+        let mut breakdown = Vec::with_capacity(splits.len());
+        for (index, share) in splits.iter().enumerate() {
+            let amount = if index == splits.len() - 1 {
+                remaining
+            } else {
+                treasury_fee * share.bps as u128 / (MAX_TREASURY_PERCENTAGE as u128)
+            };
+            remaining -= amount;
+            self.internal_credit_near(share.account_id.clone(), amount);
+            breakdown.push(FeeSharePayout {
+                account_id: share.account_id.clone(),
+                amount: U128(amount),
+            });
+        }
+        breakdown
+    }
+}
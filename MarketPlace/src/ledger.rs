@@ -0,0 +1,91 @@
+use crate::*;
+
+// pull-based withdrawals, proceeds and refunds are credited here instead of pushed from callbacks
+// a failed withdrawal transfer is credited back
+
+const GAS_FOR_RESOLVE_WITHDRAW: Gas = Gas(BASE_GAS.0 * 2);
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn withdraw(&mut self, amount: U128) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw(account_id, amount.0)
+    }
+
+    #[payable]
+    pub fn withdraw_all(&mut self) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.credits.get(&account_id).unwrap_or(0);
+        self.internal_withdraw(account_id, balance)
+    }
+
+    pub fn get_credit_balance(&self, account_id: AccountId) -> U128 {
+        self.credits.get(&account_id).unwrap_or(0).into()
+    }
+
+    #[private]
+    pub fn resolve_withdraw(&mut self, account_id: AccountId, amount: U128) -> bool {
+        if is_promise_success() {
+            return true;
+        }
+
+        self.internal_credit_near(account_id.clone(), amount.0);
+        env::log_str(
+            &json!({
+                "type": "withdraw_fail",
+                "params": {
+                    "account_id": account_id,
+                    "amount": amount,
+                }
+            })
+            .to_string(),
+        );
+        false
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_credit_near(&mut self, account_id: AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        let balance = self.credits.get(&account_id).unwrap_or(0) + amount;
+        self.credits.insert(&account_id, &balance);
+    }
+
+    pub(crate) fn internal_withdraw(&mut self, account_id: AccountId, amount: Balance) -> Promise {
+        let balance = self.credits.get(&account_id).unwrap_or(0);
+        assert!(
+            amount > 0 && amount <= balance,
+            "Insufficient credit balance: {}, requested {}",
+            balance,
+            amount
+        );
+
+        if balance == amount {
+            self.credits.remove(&account_id);
+        } else {
+            self.credits.insert(&account_id, &(balance - amount));
+        }
+
+        env::log_str(
+            &json!({
+                "type": "withdraw",
+                "params": {
+                    "account_id": account_id,
+                    "amount": U128(amount),
+                }
+            })
+            .to_string(),
+        );
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                .resolve_withdraw(account_id, U128(amount)),
+        )
+    }
+}
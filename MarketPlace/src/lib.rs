@@ -10,9 +10,24 @@ use near_sdk::{is_promise_success, promise_result_as_success, PromiseOrValue};
 use std::collections::HashMap;
 
 use crate::external::*;
-
+use crate::sealed_bid::*;
+use crate::collection_offer::*;
+use crate::bundle::*;
+use crate::fee_policy::*;
+use crate::fee_split::*;
+use crate::primary_sale::*;
+use crate::trade::*;
+
+mod bundle;
+mod collection_offer;
 mod external;
+mod fee_policy;
+mod fee_split;
+mod ledger;
 mod nft_callbacks;
+mod primary_sale;
+mod sealed_bid;
+mod trade;
 
 
 const GAS_FOR_NFT_TRANSFER: Gas = Gas(20_000_000_000_000);
@@ -26,6 +41,10 @@ const MAX_TREASURY_PERCENTAGE: u16 = 10_000;
 
 pub const STORAGE_ADD_MARKET_DATA: u128 = 8590000000000000000000;
 pub const FIVE_MINUTES: u64 = 300000000000;
+pub const ONE_HOUR: u64 = 12 * FIVE_MINUTES;
+pub const DEFAULT_MIN_INCREMENT_BPS: u16 = 500;
+pub const DEFAULT_MAX_BID_HISTORY: u32 = 100;
+pub const DEFAULT_KEEPER_TIP_BPS: u16 = 1_000; // share of the treasury fee
 
 pub type PayoutHashMap = HashMap<AccountId, U128>;
 pub type ContractAndTokenId = String;
@@ -57,6 +76,53 @@ pub struct Bid {
 
 pub type Bids = Vec<Bid>;
 
+// optional english auction settings, kept apart from MarketData
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionParams {
+    pub reserve_price: Option<U128>, // hidden until the auction ends
+    pub buy_now_price: Option<U128>,
+    pub min_increment: Option<U128>,   // absolute, exclusive with min_increment_bps
+    pub min_increment_bps: Option<u16>,
+    pub extension_window: Option<U64>, // bids within this window before ended_at extend the auction
+    pub extension_length: Option<U64>,
+    pub max_bid_history: Option<u32>,
+}
+
+// effective rules of an english auction, defaults filled in
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionRules {
+    pub min_increment: Option<U128>,
+    pub min_increment_bps: Option<u16>,
+    pub extension_window: U64,
+    pub extension_length: U64,
+    pub max_bid_history: u32,
+}
+
+// upper bounds for per listing auction rules, set by owner
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionLimits {
+    pub max_min_increment: U128,
+    pub max_min_increment_bps: u16,
+    pub max_extension_window: U64,
+    pub max_extension_length: U64,
+    pub max_bid_history: u32,
+}
+
+impl Default for AuctionLimits {
+    fn default() -> Self {
+        Self {
+            max_min_increment: U128(100 * 10u128.pow(24)),
+            max_min_increment_bps: 5_000,
+            max_extension_window: U64(ONE_HOUR),
+            max_extension_length: U64(ONE_HOUR),
+            max_bid_history: DEFAULT_MAX_BID_HISTORY,
+        }
+    }
+}
+
 fn near_account() -> AccountId {
     AccountId::new_unchecked("near".to_string())
 }
@@ -119,6 +185,8 @@ pub struct OfferDataJson {
     token_series_id: Option<TokenId>,
     ft_token_id: AccountId, // "near" for NEAR token
     price: U128,
+    expires_at: Option<U64>,
+    transaction_fee: U128,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
@@ -146,10 +214,30 @@ pub struct MarketDataJson {
     started_at: Option<U64>,
     ended_at: Option<U64>,
     end_price: Option<U128>, // dutch auction
+    current_price: U128, // live price for dutch auction, otherwise same as price
     is_auction: Option<bool>,
+    is_sealed_bid: bool,
+    has_reserve_price: bool,
+    reserve_price: Option<U128>, // only after the auction ends
+    buy_now_price: Option<U128>,
+    auction_rules: Option<AuctionRules>,
     transaction_fee: U128
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TradeDataJson {
+    buyer_id: AccountId,
+    buyer_nft_contract_id: AccountId,
+    buyer_token_id: TokenId,
+    nft_contract_id: AccountId,
+    token_id: Option<TokenId>,
+    token_series_id: Option<TokenSeriesId>,
+    receiver_id: Option<AccountId>,
+    expires_at: Option<U64>,
+    transaction_fee: U128,
+}
+
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct ContractV2 {
     pub owner_id: AccountId,
@@ -166,6 +254,23 @@ pub struct ContractV2 {
     pub trades: UnorderedMap<ContractAccountIdTokenId, TradeList>,
 }
 
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct ContractV3 {
+    pub owner_id: AccountId,
+    pub treasury_id: AccountId,
+    pub old_market: UnorderedMap<ContractAndTokenId, MarketDataV1>,
+    pub market: UnorderedMap<ContractAndTokenId, MarketData>,
+    pub approved_ft_token_ids: UnorderedSet<AccountId>,
+    pub approved_nft_contract_ids: UnorderedSet<AccountId>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    pub offers: UnorderedMap<ContractAccountIdTokenId, OfferData>,
+    pub paras_nft_contracts: UnorderedSet<AccountId>,
+    pub transaction_fee: TransactionFee,
+    pub trades: UnorderedMap<ContractAccountIdTokenId, TradeList>,
+    pub market_data_transaction_fee: MarketDataTransactionFee,
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct TradeList {
     pub approval_id: u64,
@@ -187,7 +292,40 @@ pub struct Contract {
     pub paras_nft_contracts: UnorderedSet<AccountId>,
     pub transaction_fee: TransactionFee,
     pub trades: UnorderedMap<ContractAccountIdTokenId, TradeList>,
-    pub market_data_transaction_fee: MarketDataTransactionFee
+    pub market_data_transaction_fee: MarketDataTransactionFee,
+    pub market_by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub market_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub offers_by_buyer_id: LookupMap<AccountId, UnorderedSet<ContractAccountIdTokenId>>,
+    pub offers_by_token: LookupMap<ContractAndTokenId, UnorderedSet<ContractAccountIdTokenId>>,
+    pub trades_by_token: LookupMap<ContractAndTokenId, UnorderedSet<(ContractAccountIdTokenId, ContractAccountIdTokenId)>>,
+    pub sealed_auctions: UnorderedMap<ContractAndTokenId, SealedAuction>,
+    pub unrevealed_bid_policy: UnrevealedBidPolicy,
+    pub auction_params: LookupMap<ContractAndTokenId, AuctionParams>,
+    pub auction_limits: AuctionLimits,
+    pub keeper_tip_bps: u16,
+    pub auction_keepers: LookupMap<ContractAndTokenId, AccountId>,
+    pub offer_expires_at: LookupMap<ContractAccountIdTokenId, u64>,
+    pub id_collection_offer: u64,
+    pub collection_offers: UnorderedMap<u64, CollectionOffer>,
+    pub id_bundle: u64,
+    pub bundles: UnorderedMap<u64, Bundle>,
+    pub trade_items: LookupMap<String, TradeItems>,
+    pub trade_expires_at: LookupMap<String, u64>,
+    pub trade_receivers: LookupMap<String, AccountId>,
+    pub credits: LookupMap<AccountId, Balance>,
+    pub transaction_fee_snapshots: LookupMap<String, u128>,
+    pub fee_policies: LookupMap<String, u16>,
+    pub volume_discount_tiers: Vec<VolumeDiscountTier>,
+    pub fee_promo: Option<FeePromo>,
+    pub seller_volumes: LookupMap<AccountId, Balance>,
+    pub treasury_fee_splits: Vec<FeeShare>,
+    pub primary_listings: UnorderedMap<ContractAndTokenId, PrimaryListing>,
+    pub bundle_deliveries: LookupMap<ContractAndTokenId, AccountId>,
+    pub trades_by_proposer: LookupMap<AccountId, UnorderedSet<(ContractAccountIdTokenId, ContractAccountIdTokenId)>>,
+    pub trades_by_receiver: LookupMap<AccountId, UnorderedSet<(ContractAccountIdTokenId, ContractAccountIdTokenId)>>,
+    pub expiring_trades: UnorderedSet<(ContractAccountIdTokenId, ContractAccountIdTokenId)>,
+    pub bundles_by_owner_id: LookupMap<AccountId, UnorderedSet<u64>>,
+    pub collection_offers_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<u64>>,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -207,7 +345,59 @@ pub enum StorageKey {
     OffersV2,
     ParasNFTContractIdsV2,
     Trade,
-    MarketDataTransactionFee
+    MarketDataTransactionFee,
+    MarketByOwnerId,
+    MarketByOwnerIdInner {
+        account_id_hash: CryptoHash,
+    },
+    MarketByNftContractId,
+    MarketByNftContractIdInner {
+        account_id_hash: CryptoHash,
+    },
+    OffersByBuyerId,
+    OffersByBuyerIdInner {
+        account_id_hash: CryptoHash,
+    },
+    OffersByToken,
+    OffersByTokenInner {
+        token_hash: CryptoHash,
+    },
+    TradesByToken,
+    TradesByTokenInner {
+        token_hash: CryptoHash,
+    },
+    SealedAuctions,
+    AuctionParams,
+    AuctionKeepers,
+    OfferExpiresAt,
+    CollectionOffers,
+    Bundles,
+    TradeItems,
+    TradeExpiresAt,
+    TradeReceivers,
+    Credits,
+    TransactionFeeSnapshots,
+    FeePolicies,
+    SellerVolumes,
+    PrimaryListings,
+    BundleDeliveries,
+    TradesByProposer,
+    TradesByProposerInner {
+        account_id_hash: CryptoHash,
+    },
+    TradesByReceiver,
+    TradesByReceiverInner {
+        account_id_hash: CryptoHash,
+    },
+    ExpiringTrades,
+    BundlesByOwnerId,
+    BundlesByOwnerIdInner {
+        account_id_hash: CryptoHash,
+    },
+    CollectionOffersByNftContractId,
+    CollectionOffersByNftContractIdInner {
+        account_id_hash: CryptoHash,
+    },
 }
 
 #[near_bindgen]
@@ -242,7 +432,40 @@ impl Contract {
             trades: UnorderedMap::new(StorageKey::Trade),
             market_data_transaction_fee: MarketDataTransactionFee{
                 transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee)
-            }
+            },
+            market_by_owner_id: LookupMap::new(StorageKey::MarketByOwnerId),
+            market_by_nft_contract_id: LookupMap::new(StorageKey::MarketByNftContractId),
+            offers_by_buyer_id: LookupMap::new(StorageKey::OffersByBuyerId),
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            trades_by_token: LookupMap::new(StorageKey::TradesByToken),
+            sealed_auctions: UnorderedMap::new(StorageKey::SealedAuctions),
+            unrevealed_bid_policy: UnrevealedBidPolicy::Refund,
+            auction_params: LookupMap::new(StorageKey::AuctionParams),
+            auction_limits: AuctionLimits::default(),
+            keeper_tip_bps: DEFAULT_KEEPER_TIP_BPS,
+            auction_keepers: LookupMap::new(StorageKey::AuctionKeepers),
+            offer_expires_at: LookupMap::new(StorageKey::OfferExpiresAt),
+            id_collection_offer: 0,
+            collection_offers: UnorderedMap::new(StorageKey::CollectionOffers),
+            id_bundle: 0,
+            bundles: UnorderedMap::new(StorageKey::Bundles),
+            trade_items: LookupMap::new(StorageKey::TradeItems),
+            trade_expires_at: LookupMap::new(StorageKey::TradeExpiresAt),
+            trade_receivers: LookupMap::new(StorageKey::TradeReceivers),
+            credits: LookupMap::new(StorageKey::Credits),
+            transaction_fee_snapshots: LookupMap::new(StorageKey::TransactionFeeSnapshots),
+            fee_policies: LookupMap::new(StorageKey::FeePolicies),
+            volume_discount_tiers: Vec::new(),
+            fee_promo: None,
+            seller_volumes: LookupMap::new(StorageKey::SellerVolumes),
+            treasury_fee_splits: Vec::new(),
+            primary_listings: UnorderedMap::new(StorageKey::PrimaryListings),
+            bundle_deliveries: LookupMap::new(StorageKey::BundleDeliveries),
+            trades_by_proposer: LookupMap::new(StorageKey::TradesByProposer),
+            trades_by_receiver: LookupMap::new(StorageKey::TradesByReceiver),
+            expiring_trades: UnorderedSet::new(StorageKey::ExpiringTrades),
+            bundles_by_owner_id: LookupMap::new(StorageKey::BundlesByOwnerId),
+            collection_offers_by_nft_contract_id: LookupMap::new(StorageKey::CollectionOffersByNftContractId),
         };
 
         this.approved_ft_token_ids.insert(&near_account());
@@ -259,14 +482,14 @@ impl Contract {
 
     #[init(ignore_state)]
     pub fn migrate() -> Self {
-        let prev: ContractV2 = env::state_read().expect("ERR_NOT_INITIALIZED");
+        let prev: ContractV3 = env::state_read().expect("ERR_NOT_INITIALIZED");
         assert_eq!(
             env::predecessor_account_id(),
             prev.owner_id,
             "Only owner"
         );
 
-        let this = Contract {
+        let mut this = Contract {
             owner_id: prev.owner_id,
             treasury_id: prev.treasury_id,
             old_market: prev.old_market,
@@ -279,15 +502,90 @@ impl Contract {
             paras_nft_contracts: prev.paras_nft_contracts,
             transaction_fee: prev.transaction_fee,
             trades: prev.trades,
-            market_data_transaction_fee: MarketDataTransactionFee{
-                transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee)
-            }
+            market_data_transaction_fee: prev.market_data_transaction_fee,
+            market_by_owner_id: LookupMap::new(StorageKey::MarketByOwnerId),
+            market_by_nft_contract_id: LookupMap::new(StorageKey::MarketByNftContractId),
+            offers_by_buyer_id: LookupMap::new(StorageKey::OffersByBuyerId),
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            trades_by_token: LookupMap::new(StorageKey::TradesByToken),
+            sealed_auctions: UnorderedMap::new(StorageKey::SealedAuctions),
+            unrevealed_bid_policy: UnrevealedBidPolicy::Refund,
+            auction_params: LookupMap::new(StorageKey::AuctionParams),
+            auction_limits: AuctionLimits::default(),
+            keeper_tip_bps: DEFAULT_KEEPER_TIP_BPS,
+            auction_keepers: LookupMap::new(StorageKey::AuctionKeepers),
+            offer_expires_at: LookupMap::new(StorageKey::OfferExpiresAt),
+            id_collection_offer: 0,
+            collection_offers: UnorderedMap::new(StorageKey::CollectionOffers),
+            id_bundle: 0,
+            bundles: UnorderedMap::new(StorageKey::Bundles),
+            trade_items: LookupMap::new(StorageKey::TradeItems),
+            trade_expires_at: LookupMap::new(StorageKey::TradeExpiresAt),
+            trade_receivers: LookupMap::new(StorageKey::TradeReceivers),
+            credits: LookupMap::new(StorageKey::Credits),
+            transaction_fee_snapshots: LookupMap::new(StorageKey::TransactionFeeSnapshots),
+            fee_policies: LookupMap::new(StorageKey::FeePolicies),
+            volume_discount_tiers: Vec::new(),
+            fee_promo: None,
+            seller_volumes: LookupMap::new(StorageKey::SellerVolumes),
+            treasury_fee_splits: Vec::new(),
+            primary_listings: UnorderedMap::new(StorageKey::PrimaryListings),
+            bundle_deliveries: LookupMap::new(StorageKey::BundleDeliveries),
+            trades_by_proposer: LookupMap::new(StorageKey::TradesByProposer),
+            trades_by_receiver: LookupMap::new(StorageKey::TradesByReceiver),
+            expiring_trades: UnorderedSet::new(StorageKey::ExpiringTrades),
+            bundles_by_owner_id: LookupMap::new(StorageKey::BundlesByOwnerId),
+            collection_offers_by_nft_contract_id: LookupMap::new(StorageKey::CollectionOffersByNftContractId),
         };
 
+        // backfill enumeration indexes for existing sales, offers and trades
+        for (contract_and_token_id, market_data) in this.market.to_vec() {
+            this.internal_add_market_index(&contract_and_token_id, &market_data.owner_id, &market_data.nft_contract_id);
+        }
+        for (contract_and_token_id, market_data) in this.old_market.to_vec() {
+            this.internal_add_market_index(&contract_and_token_id, &market_data.owner_id, &market_data.nft_contract_id);
+        }
+        // existing offers keep the fee in force at upgrade time
+        let current_transaction_fee = this.calculate_current_transaction_fee();
+        for (contract_account_id_token_id, offer_data) in this.offers.to_vec() {
+            this.internal_add_offer_index(&contract_account_id_token_id, &offer_data);
+            this.transaction_fee_snapshots.insert(&contract_account_id_token_id, &current_transaction_fee);
+        }
+        for (buyer_contract_account_id_token_id, trade_list) in this.trades.to_vec() {
+            for (contract_account_id_token_id, trade_data) in trade_list.trade_data.iter() {
+                this.internal_add_trade_index(&buyer_contract_account_id_token_id, contract_account_id_token_id, trade_data);
+            }
+        }
+
         this
     }
     // Changing treasury & ownership
 
+    #[payable]
+    pub fn set_auction_limits(&mut self, auction_limits: AuctionLimits) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(auction_limits.max_bid_history >= 2, "max_bid_history must be at least 2");
+        assert!(
+            auction_limits.max_min_increment_bps <= MAX_TREASURY_PERCENTAGE,
+            "max_min_increment_bps higher than {}",
+            MAX_TREASURY_PERCENTAGE
+        );
+        self.auction_limits = auction_limits;
+    }
+
+    #[payable]
+    pub fn set_keeper_tip(&mut self, keeper_tip_bps: u16) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(
+            keeper_tip_bps <= MAX_TREASURY_PERCENTAGE,
+            "keeper_tip_bps higher than {}",
+            MAX_TREASURY_PERCENTAGE
+        );
+        self.keeper_tip_bps = keeper_tip_bps;
+    }
+
     #[payable]
     pub fn set_treasury(&mut self, treasury_id: AccountId) {
         assert_one_yocto();
@@ -354,6 +652,30 @@ impl Contract {
         self.transaction_fee.current_fee as u128
     }
 
+    // fee frozen when an offer, trade or bundle is created
+    pub(crate) fn internal_snapshot_transaction_fee(
+        &mut self,
+        key: &String,
+        nft_contract_id: &AccountId,
+        market_type: FeeMarketType,
+        seller_id: Option<&AccountId>,
+    ) -> u128 {
+        // roll a scheduled fee change into current_fee
+        self.calculate_current_transaction_fee();
+        let mut transaction_fee = self.internal_policy_transaction_fee(nft_contract_id, market_type);
+        if let Some(seller_id) = seller_id {
+            transaction_fee = self.internal_apply_volume_discount(transaction_fee, seller_id);
+        }
+        self.transaction_fee_snapshots.insert(key, &transaction_fee);
+        transaction_fee
+    }
+
+    pub(crate) fn get_snapshot_transaction_fee(&self, key: &String) -> u128 {
+        self.transaction_fee_snapshots
+            .get(key)
+            .unwrap_or(self.transaction_fee.current_fee as u128)
+    }
+
     #[payable]
     pub fn transfer_ownership(&mut self, owner_id: AccountId) {
         assert_one_yocto();
@@ -440,7 +762,45 @@ impl Contract {
             "NEAR support only"
         );
 
-        assert_eq!(is_auction, false, "the NFT is on auction");
+        if is_auction {
+            let buy_now_price = self
+                .auction_params
+                .get(&contract_and_token_id)
+                .and_then(|params| params.buy_now_price)
+                .expect("the NFT is on auction");
+            let current_time = env::block_timestamp();
+
+            assert!(
+                current_time >= market_data.started_at.unwrap(),
+                "Sale has not started yet"
+            );
+            assert!(
+                current_time <= market_data.ended_at.unwrap(),
+                "Sale has ended"
+            );
+            assert_eq!(
+                env::attached_deposit(), buy_now_price.0,
+                "The attached deposit should be exactly the buy now price {}",
+                buy_now_price.0
+            );
+
+            env::log_str(
+                &json!({
+                    "type": "buy_now",
+                    "params": {
+                        "buyer_id": buyer_id,
+                        "nft_contract_id": nft_contract_id,
+                        "token_id": token_id,
+                        "price": buy_now_price,
+                    }
+                })
+                .to_string(),
+            );
+
+            // existing bids are refunded when the market data is deleted
+            self.internal_process_purchase(nft_contract_id, token_id, buyer_id, buy_now_price.0);
+            return;
+        }
 
         if ft_token_id.is_some() {
             assert_eq!(
@@ -448,6 +808,29 @@ impl Contract {
                 market_data.ft_token_id.to_string()
             )
         }
+        if market_data.end_price.is_some() {
+            let current_price = dutch_auction_price(&market_data, env::block_timestamp());
+
+            if let Some(price) = price {
+                assert!(price.0 >= current_price, "Price is lower than current price {}", current_price);
+            }
+
+            assert!(
+                env::attached_deposit() >= current_price,
+                "The attached deposit is less than the current price {}",
+                current_price
+            );
+
+            // refund the difference between the deposit and the current price
+            let refund = env::attached_deposit() - current_price;
+            if refund > 0 {
+                self.internal_credit_near(buyer_id.clone(), refund);
+            }
+
+            self.internal_process_purchase(nft_contract_id, token_id, buyer_id, current_price);
+            return;
+        }
+
         if price.is_some() {
             assert_eq!(price.unwrap().0, market_data.price);
         }
@@ -558,8 +941,10 @@ impl Contract {
             // leave function and return all FTs in ft_resolve_transfer
             if !is_promise_success() {
                 if market_data.ft_token_id == near_account() {
-                    self.internal_transfer_near(buyer_id.clone(), u128::from(price))
+                    self.internal_credit_near(buyer_id.clone(), u128::from(price))
                 }
+                let contract_and_token_id = format!("{}{}{}", &market_data.nft_contract_id, DELIMETER, &market_data.token_id);
+                self.auction_keepers.remove(&contract_and_token_id);
                 env::log_str(
                     &json!({
                     "type": "resolve_purchase_fail",
@@ -576,16 +961,15 @@ impl Contract {
                 );
             } else if market_data.ft_token_id == near_account() {
                 let treasury_fee = price.0 * self.calculate_market_data_transaction_fee(&market_data.nft_contract_id, &market_data.token_id) / (MAX_TREASURY_PERCENTAGE as u128);
+                self.internal_add_seller_volume(&market_data.owner_id, price.0);
                 let contract_and_token_id = format!("{}{}{}", &market_data.nft_contract_id, DELIMETER, &market_data.token_id);
                 self.market_data_transaction_fee.transaction_fee.remove(&contract_and_token_id);
 
                 let price_after = price.0.saturating_sub(treasury_fee);
                 if price_after > 0 {
-                    self.internal_transfer_near(market_data.owner_id.clone(), price_after);
-                }
-                if treasury_fee > 0 {
-                    self.internal_transfer_near(self.treasury_id.clone(), price.0.min(treasury_fee));
+                    self.internal_credit_near(market_data.owner_id.clone(), price_after);
                 }
+                let treasury_fee_splits = self.internal_pay_treasury_fee(&contract_and_token_id, price.0.min(treasury_fee));
 
                 env::log_str(
                     &json!({
@@ -597,6 +981,8 @@ impl Contract {
                         "ft_token_id": market_data.ft_token_id,
                         "price": price,
                         "buyer_id": buyer_id,
+                        "treasury_fee": U128(treasury_fee),
+                        "treasury_fee_splits": treasury_fee_splits,
                     }
                 })
                         .to_string(),
@@ -608,22 +994,22 @@ impl Contract {
         // Payout (transfer to royalties and seller)
         if market_data.ft_token_id == near_account() {
             let treasury_fee = (price.0 * self.calculate_market_data_transaction_fee(&market_data.nft_contract_id, &market_data.token_id)) / (MAX_TREASURY_PERCENTAGE as u128);
+            self.internal_add_seller_volume(&market_data.owner_id, price.0);
             let contract_and_token_id = format!("{}{}{}", &market_data.nft_contract_id, DELIMETER, &market_data.token_id);
             self.market_data_transaction_fee.transaction_fee.remove(&contract_and_token_id);
 
+            let mut treasury_fee_splits = vec![];
             for (receiver_id, amount) in payout {
                 if receiver_id == market_data.owner_id {
 
                     let amount_after = amount.0.saturating_sub(treasury_fee);
                     if amount_after > 0 {
-                        self.internal_transfer_near(receiver_id, amount_after);
+                        self.internal_credit_near(receiver_id, amount_after);
                     }
 
-                    if treasury_fee > 0 {
-                        self.internal_transfer_near(self.treasury_id.clone(), amount.0.min(treasury_fee));
-                    }
+                    treasury_fee_splits = self.internal_pay_treasury_fee(&contract_and_token_id, amount.0.min(treasury_fee));
                 } else {
-                    self.internal_transfer_near(receiver_id, amount.0);
+                    self.internal_credit_near(receiver_id, amount.0);
                 }
             }
             env::log_str(
@@ -636,6 +1022,8 @@ impl Contract {
                         "ft_token_id": market_data.ft_token_id,
                         "price": price,
                         "buyer_id": buyer_id,
+                        "treasury_fee": U128(treasury_fee),
+                        "treasury_fee_splits": treasury_fee_splits,
                     }
                 })
                 .to_string(),
@@ -646,7 +1034,7 @@ impl Contract {
                 &market_data.owner_id,
                 &market_data.token_id,
             );
-            self.trades.remove(&seller_contract_account_id_token_id);
+            self.internal_remove_trade_list(&seller_contract_account_id_token_id);
 
             return price;
         } else {
@@ -672,17 +1060,17 @@ impl Contract {
         };
 
         let contract_account_id_token_id = make_triple(&nft_contract_id, &buyer_id, &token);
-        self.offers.insert(
-            &contract_account_id_token_id,
-            &OfferData {
-                buyer_id: buyer_id.clone().into(),
-                nft_contract_id: nft_contract_id.into(),
-                token_id,
-                token_series_id,
-                ft_token_id: ft_token_id.into(),
-                price: price.into(),
-            },
-        );
+        let offer_data = OfferData {
+            buyer_id: buyer_id.clone().into(),
+            nft_contract_id: nft_contract_id.into(),
+            token_id,
+            token_series_id,
+            ft_token_id: ft_token_id.into(),
+            price: price.into(),
+        };
+        self.offers.insert(&contract_account_id_token_id, &offer_data);
+        self.internal_add_offer_index(&contract_account_id_token_id, &offer_data);
+        self.internal_snapshot_transaction_fee(&contract_account_id_token_id, &offer_data.nft_contract_id, FeeMarketType::Offer, None);
 
         let mut token_ids = self.by_owner_id.get(&buyer_id).unwrap_or_else(|| {
             UnorderedSet::new(
@@ -705,6 +1093,7 @@ impl Contract {
         token_series_id: Option<String>,
         ft_token_id: AccountId,
         price: U128,
+        expires_at: Option<U64>,
     ) {
         let token = if token_id.is_some() {
             token_id.as_ref().unwrap().to_string()
@@ -733,6 +1122,13 @@ impl Contract {
             "nft_contract_id is not approved"
         );
 
+        if let Some(expires_at) = expires_at {
+            assert!(
+                expires_at.0 > env::block_timestamp(),
+                "expires_at is less than current block_timestamp"
+            );
+        }
+
         let buyer_id = env::predecessor_account_id();
         let offer_data = self.internal_delete_offer(
             nft_contract_id.clone().into(),
@@ -742,7 +1138,7 @@ impl Contract {
 
         if let Some(offer) = offer_data{
             // refund previous offer
-            self.internal_transfer_near(buyer_id.clone(), offer.price);
+            self.internal_credit_near(buyer_id.clone(), offer.price);
         }
   
         let storage_amount = self.storage_minimum_balance().0;
@@ -767,6 +1163,11 @@ impl Contract {
             buyer_id.clone(),
         );
 
+        if let Some(expires_at) = expires_at {
+            let contract_account_id_token_id = make_triple(&nft_contract_id, &buyer_id, &token);
+            self.offer_expires_at.insert(&contract_account_id_token_id, &expires_at.0);
+        }
+
         env::log_str(
             &json!({
                 "type": "add_offer",
@@ -777,6 +1178,7 @@ impl Contract {
                     "token_series_id": token_series_id,
                     "ft_token_id": ft_token_id,
                     "price": price,
+                    "expires_at": expires_at,
                 }
             })
             .to_string(),
@@ -794,6 +1196,9 @@ impl Contract {
 
         match offer_data {
             Some(offer) => {
+                self.internal_remove_offer_index(&contract_account_id_token_id, &offer);
+                self.offer_expires_at.remove(&contract_account_id_token_id);
+                self.transaction_fee_snapshots.remove(&contract_account_id_token_id);
                 let by_owner_id = self
                     .by_owner_id
                     .get(&offer.buyer_id);
@@ -851,7 +1256,7 @@ impl Contract {
         )
         .expect("Offer not found");
 
-        self.internal_transfer_near(offer_data.buyer_id, offer_data.price);
+        self.internal_credit_near(offer_data.buyer_id, offer_data.price);
 
         env::log_str(
             &json!({
@@ -893,14 +1298,57 @@ impl Contract {
             assert_eq!(offer_data.token_series_id.as_ref().unwrap(), token);
         }
 
-        OfferDataJson {
-            buyer_id: offer_data.buyer_id,
-            nft_contract_id: offer_data.nft_contract_id,
-            token_id: offer_data.token_id,
-            token_series_id: offer_data.token_series_id,
-            ft_token_id: offer_data.ft_token_id,
-            price: U128(offer_data.price),
+        self.internal_offer_data_json(&contract_account_id_token_id, offer_data)
+    }
+
+    // permissionless, refunds expired offers within offers[from_index..from_index + limit]
+    pub fn cleanup_expired_offers(&mut self, from_index: Option<U128>, limit: Option<u64>) -> U64 {
+        let (start_index, limit) = pagination(from_index, limit, self.offers.len());
+        let current_time = env::block_timestamp();
+
+        let expired_offers: Vec<(ContractAccountIdTokenId, OfferData)> = self
+            .offers
+            .iter()
+            .skip(start_index)
+            .take(limit)
+            .filter(|(contract_account_id_token_id, _)| {
+                self.offer_expires_at
+                    .get(contract_account_id_token_id)
+                    .is_some_and(|expires_at| current_time >= expires_at)
+            })
+            .collect();
+
+        for (_, offer_data) in expired_offers.iter() {
+            let token = offer_data.token_id.as_ref().or(offer_data.token_series_id.as_ref()).unwrap();
+            self.internal_delete_offer(
+                offer_data.nft_contract_id.clone(),
+                offer_data.buyer_id.clone(),
+                token.clone(),
+            );
+            self.internal_credit_near(offer_data.buyer_id.clone(), offer_data.price);
+
+            env::log_str(
+                &json!({
+                    "type": "delete_offer",
+                    "params": {
+                        "nft_contract_id": offer_data.nft_contract_id,
+                        "buyer_id": offer_data.buyer_id,
+                        "token_id": offer_data.token_id,
+                        "token_series_id": offer_data.token_series_id,
+                        "expired": true,
+                    }
+                })
+                .to_string(),
+            );
         }
+
+        U64(expired_offers.len() as u64)
+    }
+
+    fn internal_offer_expired(&self, contract_account_id_token_id: &ContractAccountIdTokenId) -> bool {
+        self.offer_expires_at
+            .get(contract_account_id_token_id)
+            .is_some_and(|expires_at| env::block_timestamp() >= expires_at)
     }
 
     fn internal_update_approval_id(&mut self, approval_id: &u64, nft_contract_id: &AccountId, account_id: &AccountId, token_id: &TokenId){
@@ -936,6 +1384,12 @@ impl Contract {
             return PromiseOrValue::Value(false);
         }
 
+        if self.internal_offer_expired(&contract_account_id_token_id) {
+            self.internal_update_approval_id(&approval_id, &nft_contract_id, &seller_id, &token_id);
+            env::log_str("Offer has expired");
+            return PromiseOrValue::Value(false);
+        }
+
         self.internal_delete_market_data(&nft_contract_id, &token_id);
 
         let offer_data = offer_data_raw.unwrap();
@@ -943,6 +1397,8 @@ impl Contract {
         assert_eq!(offer_data.token_id.as_ref().unwrap(), &token_id);
         assert_eq!(offer_data.price, price);
 
+        // fee frozen when the offer was made, the seller was unknown so no volume discount applies
+        let transaction_fee = self.get_snapshot_transaction_fee(&contract_account_id_token_id);
         let offer_data = self
             .internal_delete_offer(
                 nft_contract_id.clone().into(),
@@ -968,6 +1424,7 @@ impl Contract {
                         seller_id,
                         offer_data,
                         token_id,
+                        Some(U128(transaction_fee)),
                     )
             )
         )
@@ -1015,6 +1472,12 @@ impl Contract {
             return PromiseOrValue::Value(false);
         }
 
+        if self.internal_offer_expired(&contract_account_id_token_id) {
+            self.internal_update_approval_id(&approval_id, &nft_contract_id, &seller_id, &token_id);
+            env::log_str("Offer has expired");
+            return PromiseOrValue::Value(false);
+        }
+
         self.internal_delete_market_data(&nft_contract_id, &token_id);
 
         let offer_data = offer_data_raw.unwrap(); 
@@ -1025,6 +1488,8 @@ impl Contract {
         );
         assert_eq!(offer_data.price, price);
 
+        // fee frozen when the offer was made, the seller was unknown so no volume discount applies
+        let transaction_fee = self.get_snapshot_transaction_fee(&contract_account_id_token_id);
         self.internal_delete_offer(
             nft_contract_id.clone().into(),
             buyer_id.clone(),
@@ -1049,6 +1514,7 @@ impl Contract {
                             seller_id,
                             offer_data,
                             token_id,
+                            Some(U128(transaction_fee)),
                         )
                 )
         )
@@ -1081,7 +1547,11 @@ impl Contract {
         seller_id: AccountId,
         offer_data: OfferData,
         token_id: TokenId,
+        transaction_fee: Option<U128>, // snapshot taken when the offer was made
     ) -> U128 {
+        let transaction_fee = transaction_fee
+            .map(|transaction_fee| transaction_fee.0)
+            .unwrap_or_else(|| self.calculate_current_transaction_fee());
         let payout_option = promise_result_as_success().and_then(|value| {
             // None means a bad payout from bad NFT contract
             let parsed_payout = near_sdk::serde_json::from_slice::<PayoutHashMap>(&value);
@@ -1119,7 +1589,7 @@ impl Contract {
         } else {
             if !is_promise_success() {
                 if offer_data.ft_token_id == near_account() {
-                    self.internal_transfer_near(offer_data.buyer_id.clone(), u128::from(offer_data.price));
+                    self.internal_credit_near(offer_data.buyer_id.clone(), u128::from(offer_data.price));
                     env::log_str(
                         &json!({
                     "type": "resolve_purchase_fail",
@@ -1138,16 +1608,15 @@ impl Contract {
                 }
             } else if offer_data.ft_token_id == near_account() {
                 let treasury_fee =
-                    offer_data.price as u128 * self.calculate_current_transaction_fee() / (MAX_TREASURY_PERCENTAGE as u128);
+                    offer_data.price as u128 * transaction_fee / (MAX_TREASURY_PERCENTAGE as u128);
+                self.internal_add_seller_volume(&seller_id, offer_data.price);
 
                 let amount_after = offer_data.price.saturating_sub(treasury_fee);
                 if amount_after > 0 {
-                    self.internal_transfer_near(seller_id.clone(), amount_after);
+                    self.internal_credit_near(seller_id.clone(), amount_after);
                 }
 
-                if treasury_fee > 0 {
-                    self.internal_transfer_near(self.treasury_id.clone(), offer_data.price.min(treasury_fee));
-                }
+                let treasury_fee_splits = self.internal_credit_treasury_fee(offer_data.price.min(treasury_fee));
 
                 env::log_str(
                     &json!({
@@ -1161,6 +1630,8 @@ impl Contract {
                         "price": offer_data.price.to_string(),
                         "buyer_id": offer_data.buyer_id,
                         "is_offer": true,
+                        "treasury_fee": U128(treasury_fee),
+                        "treasury_fee_splits": treasury_fee_splits,
                     }
                 })
                         .to_string(),
@@ -1173,19 +1644,19 @@ impl Contract {
         if offer_data.ft_token_id == near_account() {
             // 5% fee for treasury
             let treasury_fee =
-                offer_data.price as u128 * self.calculate_current_transaction_fee() / (MAX_TREASURY_PERCENTAGE as u128);
+                offer_data.price as u128 * transaction_fee / (MAX_TREASURY_PERCENTAGE as u128);
+            self.internal_add_seller_volume(&seller_id, offer_data.price);
 
+            let mut treasury_fee_splits = vec![];
             for (receiver_id, amount) in payout {
                 if receiver_id == seller_id {
                     let amount_after = amount.0.saturating_sub(treasury_fee);
                     if amount_after > 0 {
-                        self.internal_transfer_near(receiver_id, amount_after);
-                    }
-                    if treasury_fee > 0 {
-                        self.internal_transfer_near(self.treasury_id.clone(), amount.0.min(treasury_fee));
+                        self.internal_credit_near(receiver_id, amount_after);
                     }
+                    treasury_fee_splits = self.internal_credit_treasury_fee(amount.0.min(treasury_fee));
                 } else {
-                    self.internal_transfer_near(receiver_id, amount.0)
+                    self.internal_credit_near(receiver_id, amount.0)
                 }
             }
 
@@ -1201,6 +1672,8 @@ impl Contract {
                         "price": offer_data.price.to_string(),
                         "buyer_id": offer_data.buyer_id,
                         "is_offer": true,
+                        "treasury_fee": U128(treasury_fee),
+                        "treasury_fee_splits": treasury_fee_splits,
                     }
                 })
                 .to_string(),
@@ -1208,7 +1681,7 @@ impl Contract {
 
             let seller_contract_account_id_token_id =
                 make_triple(&offer_data.nft_contract_id, &seller_id, &token_id);
-            self.trades.remove(&seller_contract_account_id_token_id);
+            self.internal_remove_trade_list(&seller_contract_account_id_token_id);
 
             return offer_data.price.into();
         } else {
@@ -1226,8 +1699,11 @@ impl Contract {
         buyer_id: AccountId,
         buyer_token_id: Option<TokenId>,
         buyer_approval_id: u64,
+        trade_assets: TradeAssets,
+        receiver_id: Option<AccountId>,
+        expires_at: Option<U64>,
     ) {
-        self.internal_add_trade(
+        let trade_data = self.internal_add_trade(
             nft_contract_id.clone().into(),
             token_id.clone(),
             token_series_id.clone(),
@@ -1235,8 +1711,35 @@ impl Contract {
             buyer_token_id.clone(),
             buyer_id.clone(),
             buyer_approval_id.clone(),
+            trade_assets,
         );
 
+        let buyer_contract_account_id_token_id = make_triple(&buyer_nft_contract_id, &buyer_id, buyer_token_id.as_ref().unwrap());
+        let contract_account_id_token_id =
+            make_triple(&nft_contract_id, &buyer_id, token_id.as_ref().or(token_series_id.as_ref()).unwrap());
+        let key = trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id);
+        if let Some(expires_at) = expires_at {
+            assert!(expires_at.0 > env::block_timestamp(), "Trade expiry must be in the future");
+        }
+        self.internal_set_trade_expiry(
+            &buyer_contract_account_id_token_id,
+            &contract_account_id_token_id,
+            expires_at.map(|expires_at| expires_at.0),
+        );
+        // incoming trade for the given owner, or for the seller of a listed token
+        let receiver_id = receiver_id.or_else(|| {
+            token_id.as_ref().and_then(|token_id| {
+                self.market
+                    .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+                    .map(|market_data| market_data.owner_id)
+            })
+        });
+        self.internal_set_trade_receiver(&buyer_contract_account_id_token_id, &contract_account_id_token_id, receiver_id.as_ref());
+        // a known receiver freezes their volume discount into the snapshot
+        if receiver_id.is_some() {
+            self.internal_snapshot_transaction_fee(&key, &nft_contract_id, FeeMarketType::Trade, receiver_id.as_ref());
+        }
+
         env::log_str(
             &json!({
                 "type": "add_trade",
@@ -1247,7 +1750,13 @@ impl Contract {
                     "token_series_id": token_series_id,
                     "buyer_nft_contract_id": buyer_nft_contract_id,
                     "buyer_token_id": buyer_token_id,
-                    "buyer_approval_id": buyer_approval_id
+                    "buyer_approval_id": buyer_approval_id,
+                    "buyer_amount": trade_data.buyer_amount.map(U128),
+                    "seller_amount": trade_data.seller_amount.map(U128),
+                    "ft_token_id": trade_data.ft_token_id,
+                    "is_active": trade_data.is_active,
+                    "receiver_id": receiver_id,
+                    "expires_at": expires_at,
                 }
             })
             .to_string(),
@@ -1263,7 +1772,8 @@ impl Contract {
         buyer_token_id: Option<TokenId>,
         buyer_id: AccountId,
         buyer_approval_id: u64,
-    ) {
+        trade_assets: TradeAssets,
+    ) -> TradeData {
         let token = if token_id.is_some() {
             token_id.as_ref().unwrap().to_string()
         } else {
@@ -1283,15 +1793,6 @@ impl Contract {
                 .expect("Buyer token id is not specified"),
         );
 
-        let trade_data = TradeData {
-            buyer_amount: None,
-            seller_amount: None,
-            is_active: None,
-            ft_token_id: None,
-            nft_contract_id: nft_contract_id.into(),
-            token_id,
-            token_series_id,
-        };
         let mut buyer_trade_list = self
             .trades
             .get(&buyer_contract_account_id_token_id)
@@ -1301,10 +1802,46 @@ impl Contract {
                     trade_data: HashMap::new(),
                 }
             });
+
+        // proposing the same trade again releases the previous escrow
+        if let Some(old_trade_data) = buyer_trade_list.trade_data.get(&contract_account_id_token_id) {
+            self.internal_release_trade(&buyer_contract_account_id_token_id, &contract_account_id_token_id, old_trade_data);
+        }
+
+        let ft_token_id = if trade_assets.buyer_amount.is_some() || trade_assets.seller_amount.is_some() {
+            let ft_token_id = trade_assets.ft_token_id.unwrap_or_else(near_account);
+            assert_eq!(ft_token_id.to_string(), NEAR, "NEAR support only");
+            Some(ft_token_id.to_string())
+        } else {
+            None
+        };
+        let is_active = self.internal_add_trade_items(
+            &buyer_contract_account_id_token_id,
+            &contract_account_id_token_id,
+            trade_assets.buyer_tokens,
+            trade_assets.seller_tokens,
+        );
+
+        let trade_data = TradeData {
+            buyer_amount: self.internal_escrow_trade_amount(&buyer_id, trade_assets.buyer_amount),
+            seller_amount: trade_assets.seller_amount.map(|amount| amount.0).filter(|amount| *amount > 0),
+            is_active: Some(is_active),
+            ft_token_id,
+            nft_contract_id: nft_contract_id.into(),
+            token_id,
+            token_series_id,
+        };
         buyer_trade_list.approval_id = buyer_approval_id;
+        self.internal_add_trade_index(&buyer_contract_account_id_token_id, &contract_account_id_token_id, &trade_data);
+        self.internal_snapshot_transaction_fee(
+            &trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id),
+            &trade_data.nft_contract_id,
+            FeeMarketType::Trade,
+            None,
+        );
         buyer_trade_list
             .trade_data
-            .insert(contract_account_id_token_id.clone(), trade_data);
+            .insert(contract_account_id_token_id.clone(), trade_data.clone());
 
         self.trades
             .insert(&buyer_contract_account_id_token_id, &buyer_trade_list);
@@ -1321,6 +1858,8 @@ impl Contract {
 
         token_ids.insert(&make_key_owner_by_id_trade(contract_account_id_token_id));
         self.by_owner_id.insert(&buyer_id, &token_ids);
+
+        trade_data
     }
 
     #[payable]
@@ -1360,12 +1899,13 @@ impl Contract {
             assert_eq!(trade_data.clone().token_series_id.unwrap(), token)
         }
 
-        self.internal_delete_trade(
+        let trade_data = self.internal_delete_trade(
             buyer_id.clone(),
-            buyer_contract_account_id_token_id,
-            contract_account_id_token_id
+            buyer_contract_account_id_token_id.clone(),
+            contract_account_id_token_id.clone()
         )
         .expect("Trade not found");
+        self.internal_release_trade(&buyer_contract_account_id_token_id, &contract_account_id_token_id, &trade_data);
 
         env::log_str(
             &json!({
@@ -1395,6 +1935,10 @@ impl Contract {
             .expect("Trade list does not exist");
 
         let trade_data = trade_list.trade_data.remove(&contract_account_id_token_id).unwrap();
+        self.internal_remove_trade_index(&buyer_contract_account_id_token_id, &contract_account_id_token_id, &trade_data);
+        self.internal_set_trade_expiry(&buyer_contract_account_id_token_id, &contract_account_id_token_id, None);
+        self.internal_set_trade_receiver(&buyer_contract_account_id_token_id, &contract_account_id_token_id, None);
+        self.transaction_fee_snapshots.remove(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id));
 
         self.trades
             .insert(&buyer_contract_account_id_token_id, &trade_list);
@@ -1478,6 +2022,26 @@ impl Contract {
             .get(&contract_account_id_token_id)
             .expect("Trade data does not exist");
 
+        assert!(
+            !self.internal_trade_expired(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id)),
+            "Trade has expired"
+        );
+
+        // take the accepted trade out first, its escrow is settled instead of released
+        let transaction_fee =
+            self.get_snapshot_transaction_fee(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id));
+        let trade_items = self
+            .trade_items
+            .remove(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id))
+            .unwrap_or_default();
+        let trade_data = self
+            .internal_delete_trade(
+                buyer_id.clone(),
+                buyer_contract_account_id_token_id.clone(),
+                contract_account_id_token_id,
+            )
+            .unwrap();
+
         self.internal_delete_market_data(&nft_contract_id, &token_id);
         self.internal_delete_market_data(&buyer_nft_contract_id, &buyer_token_id);
 
@@ -1490,18 +2054,27 @@ impl Contract {
         if let Some(mut trades) = self.trades.get(&buyer_contract_account_id_token_id){
             trades.trade_data.clear();
         }
-        self.trades.remove(&seller_contract_account_id_token_id);
-        self.trades.remove(&buyer_contract_account_id_token_id);
+        self.internal_remove_trade_list(&seller_contract_account_id_token_id);
+        self.internal_remove_trade_list(&buyer_contract_account_id_token_id);
 
-        self.trade_swap_nft(
-            buyer_id,
-            buyer_nft_contract_id,
-            buyer_token_id,
-            trade_list.approval_id,
-            seller_id,
-            nft_contract_id,
-            token_id,
-            approval_id,
+        self.internal_settle_trade(
+            buyer_id.clone(),
+            TradeItem {
+                nft_contract_id: buyer_nft_contract_id,
+                token_id: buyer_token_id,
+                approval_id: Some(trade_list.approval_id),
+                owner_id: Some(buyer_id),
+            },
+            seller_id.clone(),
+            TradeItem {
+                nft_contract_id,
+                token_id,
+                approval_id: Some(approval_id),
+                owner_id: Some(seller_id),
+            },
+            trade_data,
+            trade_items,
+            transaction_fee,
         )
     }
 
@@ -1540,237 +2113,115 @@ impl Contract {
             &token_series_id
         );
 
+        assert!(
+            !self.internal_trade_expired(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id)),
+            "Trade has expired"
+        );
+
+        // take the accepted trade out first, its escrow is settled instead of released
+        let transaction_fee =
+            self.get_snapshot_transaction_fee(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id));
+        let trade_items = self
+            .trade_items
+            .remove(&trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id))
+            .unwrap_or_default();
+        let trade_data = self
+            .internal_delete_trade(
+                buyer_id.clone(),
+                buyer_contract_account_id_token_id.clone(),
+                contract_account_id_token_id,
+            )
+            .unwrap();
+
         self.internal_delete_market_data(&nft_contract_id, &token_id);
         self.internal_delete_market_data(&buyer_nft_contract_id, &buyer_token_id);
 
         let seller_contract_account_id_token_id =
             make_triple(&nft_contract_id, &seller_id, &token_id);
-        self.trades.remove(&seller_contract_account_id_token_id);
-        self.trades.remove(&buyer_contract_account_id_token_id);
+        self.internal_remove_trade_list(&seller_contract_account_id_token_id);
+        self.internal_remove_trade_list(&buyer_contract_account_id_token_id);
 
-        self.trade_swap_nft(
-            buyer_id,
-            buyer_nft_contract_id,
-            buyer_token_id,
-            trade_list.approval_id,
-            seller_id,
-            nft_contract_id,
-            token_id,
-            approval_id,
+        self.internal_settle_trade(
+            buyer_id.clone(),
+            TradeItem {
+                nft_contract_id: buyer_nft_contract_id,
+                token_id: buyer_token_id,
+                approval_id: Some(trade_list.approval_id),
+                owner_id: Some(buyer_id),
+            },
+            seller_id.clone(),
+            TradeItem {
+                nft_contract_id,
+                token_id,
+                approval_id: Some(approval_id),
+                owner_id: Some(seller_id),
+            },
+            trade_data,
+            trade_items,
+            transaction_fee,
         )
     }
 
-    fn trade_swap_nft(
-        &mut self,
-        buyer_id: AccountId,
-        buyer_nft_contract_id: AccountId,
-        buyer_token_id: TokenId,
-        buyer_approval_id: u64,
-        seller_id: AccountId,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
-        seller_approval_id: u64,
-    ) -> Promise {
-        // 1. transfer buyer & seller NFT to marketplace
-        // 2. verify that those NFTs is valid and has approval_id
-        // 3. if those NFTs is valid then swap token to buyer & seller
-        // 4. if failed then rollback the NFT to buyer or seller
+    fn trade_swap_nft(&mut self, settlement: TradeSettlement) -> Promise {
+        // 1. transfer buyer's NFTs to marketplace
+        // 2. transfer seller's NFTs to marketplace once every buyer's NFT is there
+        // 3. if those NFTs are valid then swap them and pay the escrowed amounts
+        // 4. if failed then rollback the NFTs and refund the escrowed amounts
+
+        let (callback_first_gas, _) = trade_callback_gas(&settlement);
+        trade_escrow_tokens(&settlement.buyer_tokens).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(callback_first_gas)
+                .callback_first_trade(settlement)
+        )
+    }
 
-        
-        ext_contract_transfer::ext(buyer_nft_contract_id.clone())
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_NFT_TRANSFER)
-            .nft_transfer(
-                env::current_account_id(),
-                buyer_token_id.clone(),
-                Some(buyer_approval_id),
-                None,
-            ).then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_CALLBACK_FIRST_TRADE)
-                    .callback_first_trade(
-                        seller_nft_contract_id.clone(),
-                        seller_token_id.clone(),
-                        seller_approval_id,
-                    )
-            ).then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_CALLBACK_SECOND_TRADE)
-                    .callback_second_trade(
-                        buyer_id,
-                        buyer_nft_contract_id.clone(),
-                        buyer_token_id.clone(),
-                        seller_id,
-                        seller_nft_contract_id.clone(),
-                        seller_token_id.clone(),
-                    )
-            )
-        
-
-        /*ext_contract_transfer::nft_transfer(
-            env::current_account_id(),
-            buyer_token_id.clone(),
-            Some(buyer_approval_id),
-            None,
-            buyer_nft_contract_id.clone(),
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        )
-        .then(ext_self::callback_first_trade(
-            seller_nft_contract_id.clone(),
-            seller_token_id.clone(),
-            seller_approval_id,
-            env::current_account_id(),
-            NO_DEPOSIT,
-            GAS_FOR_CALLBACK_FIRST_TRADE,
-        ))
-        .then(ext_self::callback_second_trade(
-            buyer_id,
-            buyer_nft_contract_id.clone(),
-            buyer_token_id.clone(),
-            seller_id,
-            seller_nft_contract_id.clone(),
-            seller_token_id.clone(),
-            env::current_account_id(),
-            NO_DEPOSIT,
-            GAS_FOR_CALLBACK_SECOND_TRADE,
-        ))*/
-    }
-
-    #[private]
-    pub fn callback_first_trade(
-        &mut self,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
-        seller_approval_id: u64,
-    ) -> Promise {
-        if !is_promise_success() {
-            env::panic_str(&"buyer's nft failed to trade");
-        } else {
-            return ext_contract_transfer::ext(seller_nft_contract_id.clone())
+    fn internal_swap_nft(&mut self, settlement: TradeSettlement) {
+        for item in settlement.buyer_tokens.iter() {
+            ext_contract_transfer::ext(item.nft_contract_id.clone())
                 .with_attached_deposit(1)
                 .with_static_gas(GAS_FOR_NFT_TRANSFER)
-                .nft_transfer(
-                    env::current_account_id(),
-                    seller_token_id.clone(),
-                    Some(seller_approval_id),
-                    None,
-                );
-
-
-            /*return ext_contract_transfer::nft_transfer(
-                env::current_account_id(),
-                seller_token_id.clone(),
-                Some(seller_approval_id),
-                None,
-                seller_nft_contract_id.clone(),
-                1,
-                GAS_FOR_NFT_TRANSFER,
-            );*/
+                .nft_transfer(settlement.seller_id.clone(), item.token_id.clone(), None, None);
         }
-    }
-
-    #[private]
-    pub fn callback_second_trade(
-        &mut self,
-        buyer_id: AccountId,
-        buyer_nft_contract_id: AccountId,
-        buyer_token_id: TokenId,
-        seller_id: AccountId,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
-    ) {
-        if !is_promise_success() {
-            ext_contract_transfer::ext(buyer_nft_contract_id.clone())
+        for item in settlement.seller_tokens.iter() {
+            ext_contract_transfer::ext(item.nft_contract_id.clone())
                 .with_attached_deposit(1)
                 .with_static_gas(GAS_FOR_NFT_TRANSFER)
-                .nft_transfer(
-                    buyer_id,
-                    buyer_token_id,
-                    None,
-                    None,
-                );
-
-            /*ext_contract_transfer::nft_transfer(
-                buyer_id,
-                buyer_token_id,
-                None,
-                None,
-                buyer_nft_contract_id,
-                1,
-                GAS_FOR_NFT_TRANSFER,
-            );*/
-
-            env::panic_str(&"seller's nft failed to trade, rollback buyer's nft");
-        } else {
-            self.internal_swap_nft(
-                buyer_id,
-                buyer_nft_contract_id,
-                buyer_token_id,
-                seller_id,
-                seller_nft_contract_id,
-                seller_token_id,
-            );
+                .nft_transfer(settlement.buyer_id.clone(), item.token_id.clone(), None, None);
         }
-    }
 
-    fn internal_swap_nft(
-        &mut self,
-        buyer_id: AccountId,
-        buyer_nft_contract_id: AccountId,
-        buyer_token_id: TokenId,
-        seller_id: AccountId,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
-    ) {
-        ext_contract_transfer::ext(buyer_nft_contract_id.clone())
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_NFT_TRANSFER)
-            .nft_transfer(
-                seller_id.clone(),
-                buyer_token_id.clone(),
-                None,
-                None,
-            )
-            .then(ext_contract_transfer::ext(seller_nft_contract_id.clone())
-                .with_attached_deposit(1)
-                .with_static_gas(GAS_FOR_NFT_TRANSFER)
-                .nft_transfer(
-                    buyer_id.clone(),
-                    seller_token_id.clone(),
-                    None,
-                    None,
-            ));
-
-        /*ext_contract_transfer::nft_transfer(
-            seller_id.clone(),
-            buyer_token_id.clone(),
-            None,
-            None,
-            buyer_nft_contract_id.clone(),
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        )
-        .then(ext_contract_transfer::nft_transfer(
-            buyer_id.clone(),
-            seller_token_id.clone(),
-            None,
-            None,
-            seller_nft_contract_id.clone(),
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        ));*/
+        // top-ups are charged the fee snapshotted when the trade was proposed
+        let mut treasury_fee = 0;
+        if let Some(buyer_amount) = settlement.buyer_amount {
+            let fee = buyer_amount.0 * settlement.transaction_fee.0 / (MAX_TREASURY_PERCENTAGE as u128);
+            self.internal_credit_near(settlement.seller_id.clone(), buyer_amount.0 - fee);
+            treasury_fee += fee;
+        }
+        if let Some(seller_amount) = settlement.seller_amount {
+            let fee = seller_amount.0 * settlement.transaction_fee.0 / (MAX_TREASURY_PERCENTAGE as u128);
+            self.internal_credit_near(settlement.buyer_id.clone(), seller_amount.0 - fee);
+            treasury_fee += fee;
+        }
+        let treasury_fee_splits = self.internal_credit_treasury_fee(treasury_fee);
 
+        let buyer_token = &settlement.buyer_tokens[0];
+        let seller_token = &settlement.seller_tokens[0];
         env::log_str(
             &json!({
                 "type": "accept_trade",
                 "params": {
-                    "sender_id": seller_id,
-                    "buyer_id": buyer_id,
-                    "nft_contract_id": seller_nft_contract_id,
-                    "token_id": seller_token_id,
-                    "buyer_nft_contract_id": buyer_nft_contract_id,
-                    "buyer_token_id": buyer_token_id,
+                    "sender_id": settlement.seller_id,
+                    "buyer_id": settlement.buyer_id,
+                    "nft_contract_id": seller_token.nft_contract_id,
+                    "token_id": seller_token.token_id,
+                    "buyer_nft_contract_id": buyer_token.nft_contract_id,
+                    "buyer_token_id": buyer_token.token_id,
+                    "buyer_tokens": settlement.buyer_tokens[1..],
+                    "seller_tokens": settlement.seller_tokens[1..],
+                    "buyer_amount": settlement.buyer_amount,
+                    "seller_amount": settlement.seller_amount,
+                    "treasury_fee": U128(treasury_fee),
+                    "treasury_fee_splits": treasury_fee_splits,
                 }
             })
             .to_string(),
@@ -1793,6 +2244,10 @@ impl Contract {
             .expect("Token id does not exist");
 
         assert_eq!(market_data.is_auction.unwrap(), true, "not auction");
+        assert!(
+            !self.internal_is_sealed_auction(&contract_and_token_id),
+            "Sealed-bid auction, use commit_sealed_bid"
+        );
 
         let bidder_id = env::predecessor_account_id();
         let current_time = env::block_timestamp();
@@ -1807,9 +2262,11 @@ impl Contract {
             "Sale has ended"
         );
 
+        let auction_rules = self.internal_auction_rules(&contract_and_token_id);
+
         let remaining_time = market_data.ended_at.unwrap() - current_time;
-        if remaining_time <= FIVE_MINUTES {
-          let extended_ended_at = market_data.ended_at.unwrap() + FIVE_MINUTES;
+        if remaining_time <= auction_rules.extension_window.0 && auction_rules.extension_length.0 > 0 {
+          let extended_ended_at = market_data.ended_at.unwrap() + auction_rules.extension_length.0;
           market_data.ended_at = Some(extended_ended_at);
 
           env::log_str(
@@ -1834,6 +2291,19 @@ impl Contract {
 
         assert_eq!(ft_token_id.to_string(), "near", "Only support NEAR");
 
+        // bids stay below buy now so buying outright never undercuts the top bid
+        if let Some(buy_now_price) = self
+            .auction_params
+            .get(&contract_and_token_id)
+            .and_then(|params| params.buy_now_price)
+        {
+            assert!(
+                amount.0 < buy_now_price.0,
+                "Bid must be lower than the buy now price {}, use buy instead",
+                buy_now_price.0
+            );
+        }
+
         let new_bid = Bid {
             bidder_id: bidder_id.clone(),
             price: amount.into(),
@@ -1843,11 +2313,15 @@ impl Contract {
 
         if !bids.is_empty() {
             let current_bid = &bids[bids.len() - 1];
+            let min_increment = match auction_rules.min_increment {
+              Some(min_increment) => min_increment.0,
+              None => current_bid.price.0 * auction_rules.min_increment_bps.unwrap_or(0) as u128 / 10_000,
+            };
 
             assert!(
-              amount.0 >= current_bid.price.0 + (current_bid.price.0 * 5 / 100),
-              "Can't pay less than or equal to current bid price + 5% : {:?}",
-              current_bid.price.0 + (current_bid.price.0 * 5 / 100)
+              amount.0 >= current_bid.price.0 + min_increment,
+              "Can't pay less than current bid price + minimum increment : {:?}",
+              current_bid.price.0 + min_increment
             );
 
             assert!(
@@ -1860,7 +2334,7 @@ impl Contract {
             bids.retain(|bid| {
               if bid.bidder_id == bidder_id {
                 // refund
-                self.internal_transfer_near(bid.bidder_id.clone(), bid.price.0);
+                self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
               }
 
               bid.bidder_id != bidder_id
@@ -1877,9 +2351,9 @@ impl Contract {
         market_data.bids = Some(bids);
         self.market.insert(&contract_and_token_id, &market_data);
 
-        // Remove first element if bids.length >= 100
+        // Remove first element if bids.length >= max_bid_history
         let updated_bids = market_data.bids.unwrap_or(Vec::new());
-        if updated_bids.len() >= 100 {
+        if updated_bids.len() >= auction_rules.max_bid_history as usize {
           self.internal_cancel_bid(nft_contract_id.clone(), token_id.clone(), updated_bids[0].bidder_id.clone())
         }
 
@@ -1916,7 +2390,7 @@ impl Contract {
       bids.retain(|bid| {
         if bid.bidder_id == account_id {
           // refund
-            self.internal_transfer_near(bid.bidder_id.clone(), bid.price.0);
+            self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
         }
 
         bid.bidder_id != account_id
@@ -1978,6 +2452,10 @@ impl Contract {
             .expect("Token id does not exist");
 
         assert_eq!(market_data.is_auction.unwrap(), true, "not auction");
+        assert!(
+            !self.internal_is_sealed_auction(&contract_and_token_id),
+            "Sealed-bid auction, use settle_sealed_auction"
+        );
         let current_time: u64 = env::block_timestamp();
 
         assert!(
@@ -1997,12 +2475,21 @@ impl Contract {
 
         assert!(!bids.is_empty(), "Cannot accept bid with empty bid");
 
+        if !self.internal_reserve_price_met(&contract_and_token_id, bids[bids.len() - 1].price.0) {
+            assert!(
+                current_time >= market_data.ended_at.unwrap(),
+                "Reserve price not met"
+            );
+            self.internal_cancel_auction_below_reserve(&nft_contract_id, &token_id);
+            return;
+        }
+
         let selected_bid = bids.remove(bids.len() - 1);
 
         // refund all except selected bids
         for bid in &bids {
           // refund
-            self.internal_transfer_near(bid.bidder_id.clone(), bid.price.0);
+            self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
         }
         bids.clear();
 
@@ -2017,6 +2504,85 @@ impl Contract {
         );
     }
 
+    // permissionless, anyone can close an english auction once ended_at has passed
+    pub fn settle_auction(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        let keeper_id = env::predecessor_account_id();
+        let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, &token_id);
+        let mut market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Market data does not exist");
+
+        assert_eq!(market_data.is_auction, Some(true), "not auction");
+        assert!(
+            !self.internal_is_sealed_auction(&contract_and_token_id),
+            "Sealed-bid auction, use settle_sealed_auction"
+        );
+        assert!(
+            env::block_timestamp() >= market_data.ended_at.unwrap(),
+            "Auction has not ended yet"
+        );
+
+        let mut bids = market_data.bids.unwrap_or_default();
+
+        if bids.is_empty() {
+            self.internal_delete_market_data(&nft_contract_id, &token_id);
+
+            env::log_str(
+                &json!({
+                    "type": "delete_market_data",
+                    "params": {
+                        "owner_id": market_data.owner_id,
+                        "nft_contract_id": nft_contract_id,
+                        "token_id": token_id,
+                    }
+                })
+                .to_string(),
+            );
+            return;
+        }
+
+        if !self.internal_reserve_price_met(&contract_and_token_id, bids[bids.len() - 1].price.0) {
+            self.internal_cancel_auction_below_reserve(&nft_contract_id, &token_id);
+            return;
+        }
+
+        let selected_bid = bids.remove(bids.len() - 1);
+
+        // refund all except selected bids
+        for bid in &bids {
+            self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
+        }
+        bids.clear();
+
+        market_data.bids = Some(bids);
+        self.market.insert(&contract_and_token_id, &market_data);
+
+        // keeper tip is paid out of the treasury fee in resolve_purchase
+        self.auction_keepers.insert(&contract_and_token_id, &keeper_id);
+
+        env::log_str(
+            &json!({
+                "type": "settle_auction",
+                "params": {
+                    "keeper_id": keeper_id,
+                    "bidder_id": selected_bid.bidder_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "price": selected_bid.price,
+                }
+            })
+            .to_string(),
+        );
+
+        self.internal_process_purchase(
+            nft_contract_id,
+            token_id,
+            selected_bid.bidder_id.clone(),
+            selected_bid.price.0
+        );
+    }
+
     #[payable]
     pub fn end_auction(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
       let predecessor_account_id = env::predecessor_account_id();
@@ -2032,6 +2598,10 @@ impl Contract {
           .expect("Market data does not exist");
 
       assert_eq!(market_data.is_auction.unwrap(), true, "not auction");
+      assert!(
+        !self.internal_is_sealed_auction(&contract_and_token_id),
+        "Sealed-bid auction, use settle_sealed_auction"
+      );
       assert!(
         [market_data.owner_id.clone(), self.owner_id.clone()]
           .contains(&predecessor_account_id),
@@ -2061,12 +2631,18 @@ impl Contract {
             })
             .to_string(),
         );
+      } else if !self.internal_reserve_price_met(&contract_and_token_id, bids[bids.len() - 1].price.0) {
+        assert!(
+          current_time >= market_data.ended_at.unwrap(),
+          "Reserve price not met"
+        );
+        self.internal_cancel_auction_below_reserve(&nft_contract_id, &token_id);
       } else {
         let selected_bid = bids.remove(bids.len() - 1);
 
         // refund all except selected bids
         for bid in &bids {
-            self.internal_transfer_near(bid.bidder_id.clone(), bid.price.0);
+            self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
         }
 
         bids.clear();
@@ -2133,6 +2709,21 @@ impl Contract {
             }
         }
 
+        // dutch auction, price decays linearly from price to end_price between started_at and ended_at
+        if let Some(end_price) = end_price {
+            assert_ne!(is_auction, Some(true), "Dutch auction cannot be an english auction");
+            assert!(end_price.0 < price.0, "end_price must be lower than price");
+
+            if let Some(started_at) = started_at {
+                assert!(started_at.0 >= current_time, "started_at is less than current block_timestamp");
+            } else {
+                started_at = Some(U64(current_time));
+            }
+
+            let ended_at = ended_at.expect("Ended at is none");
+            assert!(started_at.unwrap().0 < ended_at.0, "ended_at must be after started_at");
+        }
+
         assert!(
             price.0 < MAX_PRICE,
             "price higher than {}",
@@ -2180,6 +2771,7 @@ impl Contract {
         token_ids.insert(&contract_and_token_id);
 
         self.by_owner_id.insert(&owner_id, &token_ids);
+        self.internal_add_market_index(&contract_and_token_id, &owner_id, &nft_contract_id);
 
         // update offer trade approval_id
         let owner_contract_account_id_token_id =
@@ -2193,7 +2785,16 @@ impl Contract {
 
 
         // set market data transaction fee
-        let current_transaction_fee = self.calculate_current_transaction_fee();
+        let market_type = if is_auction == Some(true) {
+            FeeMarketType::Auction
+        } else {
+            FeeMarketType::Sale
+        };
+        self.calculate_current_transaction_fee();
+        let current_transaction_fee = self.internal_apply_volume_discount(
+            self.internal_policy_transaction_fee(&nft_contract_id, market_type),
+            &owner_id,
+        );
         self.market_data_transaction_fee.transaction_fee.insert(&contract_and_token_id, &current_transaction_fee);
 
         env::log_str(
@@ -2247,9 +2848,11 @@ impl Contract {
 
                 if let Some(ref bids) = market_data.bids {
                     for bid in bids {
-                        self.internal_transfer_near(bid.bidder_id.clone(), bid.price.0);
+                        self.internal_credit_near(bid.bidder_id.clone(), bid.price.0);
                     }
                 };
+                self.internal_remove_sealed_auction(&contract_and_token_id);
+                self.auction_params.remove(&contract_and_token_id);
 
                 Some(market_data)
             } else {
@@ -2257,6 +2860,7 @@ impl Contract {
             };
 
         market_data.map(|market_data| {
+            self.internal_remove_market_index(&contract_and_token_id, &market_data.owner_id, &market_data.nft_contract_id);
             let by_owner_id = self
                 .by_owner_id
                 .get(&market_data.owner_id);
@@ -2272,6 +2876,155 @@ impl Contract {
         })
     }
 
+    fn internal_add_auction_params(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        auction_params: AuctionParams,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Token id does not exist");
+
+        assert_eq!(market_data.is_auction, Some(true), "Reserve and buy now price are for auctions only");
+        assert!(
+            !self.internal_is_sealed_auction(&contract_and_token_id),
+            "Reserve and buy now price are for english auctions only"
+        );
+
+        let reserve_price = auction_params.reserve_price;
+        let buy_now_price = auction_params.buy_now_price;
+
+        if let Some(reserve_price) = reserve_price {
+            assert!(
+                reserve_price.0 >= market_data.price,
+                "reserve_price must be at least the starting price"
+            );
+        }
+        if let Some(buy_now_price) = buy_now_price {
+            assert!(
+                buy_now_price.0 > reserve_price.map_or(market_data.price, |reserve_price| reserve_price.0),
+                "buy_now_price must be higher than starting and reserve price"
+            );
+            assert!(
+                buy_now_price.0 < MAX_PRICE,
+                "price higher than {}",
+                MAX_PRICE
+            );
+        }
+
+        let limits = &self.auction_limits;
+        assert!(
+            auction_params.min_increment.is_none() || auction_params.min_increment_bps.is_none(),
+            "min_increment and min_increment_bps are exclusive"
+        );
+        if let Some(min_increment) = auction_params.min_increment {
+            assert!(
+                min_increment.0 <= limits.max_min_increment.0,
+                "min_increment higher than {}",
+                limits.max_min_increment.0
+            );
+        }
+        if let Some(min_increment_bps) = auction_params.min_increment_bps {
+            assert!(
+                min_increment_bps <= limits.max_min_increment_bps,
+                "min_increment_bps higher than {}",
+                limits.max_min_increment_bps
+            );
+        }
+        if let Some(extension_window) = auction_params.extension_window {
+            assert!(
+                extension_window.0 <= limits.max_extension_window.0,
+                "extension_window higher than {}",
+                limits.max_extension_window.0
+            );
+        }
+        if let Some(extension_length) = auction_params.extension_length {
+            assert!(
+                extension_length.0 <= limits.max_extension_length.0,
+                "extension_length higher than {}",
+                limits.max_extension_length.0
+            );
+        }
+        if let Some(max_bid_history) = auction_params.max_bid_history {
+            assert!(
+                max_bid_history >= 2 && max_bid_history <= limits.max_bid_history,
+                "max_bid_history must be between 2 and {}",
+                limits.max_bid_history
+            );
+        }
+
+        self.auction_params.insert(&contract_and_token_id, &auction_params);
+
+        env::log_str(
+            &json!({
+                "type": "add_auction_params",
+                "params": {
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "has_reserve_price": reserve_price.is_some(),
+                    "buy_now_price": buy_now_price,
+                    "min_increment": auction_params.min_increment,
+                    "min_increment_bps": auction_params.min_increment_bps,
+                    "extension_window": auction_params.extension_window,
+                    "extension_length": auction_params.extension_length,
+                    "max_bid_history": auction_params.max_bid_history,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    fn internal_auction_rules(&self, contract_and_token_id: &ContractAndTokenId) -> AuctionRules {
+        let auction_params = self.auction_params.get(contract_and_token_id).unwrap_or_default();
+
+        AuctionRules {
+            min_increment: auction_params.min_increment,
+            min_increment_bps: if auction_params.min_increment.is_some() {
+                None
+            } else {
+                Some(auction_params.min_increment_bps.unwrap_or(DEFAULT_MIN_INCREMENT_BPS))
+            },
+            extension_window: auction_params.extension_window.unwrap_or(U64(FIVE_MINUTES)),
+            extension_length: auction_params.extension_length.unwrap_or(U64(FIVE_MINUTES)),
+            max_bid_history: auction_params.max_bid_history.unwrap_or(DEFAULT_MAX_BID_HISTORY),
+        }
+    }
+
+    fn internal_reserve_price_met(&self, contract_and_token_id: &ContractAndTokenId, price: u128) -> bool {
+        self.auction_params
+            .get(contract_and_token_id)
+            .and_then(|params| params.reserve_price)
+            .is_none_or(|reserve_price| price >= reserve_price.0)
+    }
+
+    // closes the auction without a winner, every bid is refunded on delete
+    fn internal_cancel_auction_below_reserve(&mut self, nft_contract_id: &AccountId, token_id: &TokenId) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let reserve_price = self
+            .auction_params
+            .get(&contract_and_token_id)
+            .and_then(|params| params.reserve_price);
+        let market_data = self
+            .internal_delete_market_data(nft_contract_id, token_id)
+            .expect("Market data does not exist");
+
+        env::log_str(
+            &json!({
+                "type": "reserve_price_not_met",
+                "params": {
+                    "owner_id": market_data.owner_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "reserve_price": reserve_price,
+                }
+            })
+            .to_string(),
+        );
+    }
+
     #[payable]
     pub fn delete_market_data(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
         let predecessor_account_id = env::predecessor_account_id();
@@ -2320,6 +3073,14 @@ impl Contract {
           );
         }
 
+        // seller cannot walk away once bidding closed with committed bids, settle_sealed_auction closes it
+        if let Some(sealed_auction) = self.sealed_auctions.get(&contract_and_token_id) {
+          assert!(
+            sealed_auction.bids.is_empty() || current_time <= market_data.ended_at.unwrap(),
+            "Cannot cancel a sealed-bid auction after bidding closed, use settle_sealed_auction"
+          );
+        }
+
         self.internal_delete_market_data(&nft_contract_id, &token_id);
 
         env::log_str(
@@ -2408,7 +3169,191 @@ impl Contract {
 
         let market_data = market_data.expect("Market data does not exist");
 
+        self.internal_market_data_json(market_data)
+    }
+
+    pub fn get_market_data_all(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<MarketDataJson> {
+        let (start_index, limit) = pagination(from_index, limit, self.old_market.len() + self.market.len());
+
+        self.old_market
+            .keys()
+            .chain(self.market.keys())
+            .skip(start_index)
+            .take(limit)
+            .map(|contract_and_token_id| self.internal_get_market_data_json(&contract_and_token_id))
+            .collect()
+    }
+
+    pub fn get_market_data_supply(&self) -> U64 {
+        (self.old_market.len() + self.market.len()).into()
+    }
+
+    pub fn get_market_data_by_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<MarketDataJson> {
+        self.market_by_owner_id
+            .get(&account_id)
+            .map(|contract_and_token_ids| {
+                let (start_index, limit) = pagination(from_index, limit, contract_and_token_ids.len());
+                contract_and_token_ids
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|contract_and_token_id| self.internal_get_market_data_json(&contract_and_token_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_market_data_supply_by_owner(&self, account_id: AccountId) -> U64 {
+        self.market_by_owner_id
+            .get(&account_id)
+            .map_or(0, |contract_and_token_ids| contract_and_token_ids.len())
+            .into()
+    }
+
+    pub fn get_market_data_by_nft_contract(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<MarketDataJson> {
+        self.market_by_nft_contract_id
+            .get(&nft_contract_id)
+            .map(|contract_and_token_ids| {
+                let (start_index, limit) = pagination(from_index, limit, contract_and_token_ids.len());
+                contract_and_token_ids
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|contract_and_token_id| self.internal_get_market_data_json(&contract_and_token_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_market_data_supply_by_nft_contract(&self, nft_contract_id: AccountId) -> U64 {
+        self.market_by_nft_contract_id
+            .get(&nft_contract_id)
+            .map_or(0, |contract_and_token_ids| contract_and_token_ids.len())
+            .into()
+    }
+
+    pub fn get_offers_by_buyer(
+        &self,
+        buyer_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<OfferDataJson> {
+        self.offers_by_buyer_id
+            .get(&buyer_id)
+            .map(|contract_account_id_token_ids| {
+                let (start_index, limit) = pagination(from_index, limit, contract_account_id_token_ids.len());
+                contract_account_id_token_ids
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|contract_account_id_token_id| {
+                        self.internal_offer_data_json(
+                            &contract_account_id_token_id,
+                            self.offers.get(&contract_account_id_token_id).unwrap(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // offers for the token itself followed by offers for its series
+    pub fn get_offers_for_token(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<OfferDataJson> {
+        let offer_sets: Vec<UnorderedSet<ContractAccountIdTokenId>> = token_and_series_keys(&nft_contract_id, &token_id)
+            .iter()
+            .filter_map(|contract_and_token_id| self.offers_by_token.get(contract_and_token_id))
+            .collect();
+        let (start_index, limit) = pagination(from_index, limit, offer_sets.iter().map(|offers| offers.len()).sum());
+
+        offer_sets
+            .iter()
+            .flat_map(|contract_account_id_token_ids| contract_account_id_token_ids.iter())
+            .skip(start_index)
+            .take(limit)
+            .map(|contract_account_id_token_id| {
+                self.internal_offer_data_json(
+                    &contract_account_id_token_id,
+                    self.offers.get(&contract_account_id_token_id).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    // trades proposed for the token itself followed by trades proposed for its series
+    pub fn get_trades_for_token(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TradeDataJson> {
+        let trade_sets: Vec<UnorderedSet<(ContractAccountIdTokenId, ContractAccountIdTokenId)>> =
+            token_and_series_keys(&nft_contract_id, &token_id)
+                .iter()
+                .filter_map(|contract_and_token_id| self.trades_by_token.get(contract_and_token_id))
+                .collect();
+        let (start_index, limit) = pagination(from_index, limit, trade_sets.iter().map(|trades| trades.len()).sum());
+
+        trade_sets
+            .iter()
+            .flat_map(|trade_keys| trade_keys.iter())
+            .skip(start_index)
+            .take(limit)
+            .map(|(buyer_contract_account_id_token_id, contract_account_id_token_id)| {
+                self.internal_trade_data_json(&buyer_contract_account_id_token_id, &contract_account_id_token_id)
+            })
+            .collect()
+    }
+
+    fn internal_get_market_data_json(&self, contract_and_token_id: &ContractAndTokenId) -> MarketDataJson {
+        let market_data: MarketData =
+            if let Some(market_data) = self.old_market.get(contract_and_token_id) {
+                MarketData {
+                    owner_id: market_data.owner_id,
+                    approval_id: market_data.approval_id,
+                    nft_contract_id: market_data.nft_contract_id,
+                    token_id: market_data.token_id,
+                    ft_token_id: market_data.ft_token_id,
+                    price: market_data.price,
+                    bids: None,
+                    started_at: None,
+                    ended_at: None,
+                    end_price: None,
+                    accept_nft_contract_id: None,
+                    accept_token_id: None,
+                    is_auction: None,
+                }
+            } else {
+                self.market.get(contract_and_token_id).expect("Market data does not exist")
+            };
+
+        self.internal_market_data_json(market_data)
+    }
+
+    fn internal_market_data_json(&self, market_data: MarketData) -> MarketDataJson {
         let price = market_data.price;
+        let current_price = dutch_auction_price(&market_data, env::block_timestamp());
+        let contract_and_token_id = format!("{}{}{}", &market_data.nft_contract_id, DELIMETER, &market_data.token_id);
+        let is_sealed_bid = self.internal_is_sealed_auction(&contract_and_token_id);
+        let auction_params = self.auction_params.get(&contract_and_token_id);
+        let reserve_price = auction_params.as_ref().and_then(|params| params.reserve_price);
+        let auction_ended = market_data.ended_at.is_some_and(|ended_at| env::block_timestamp() >= ended_at);
 
         let current_transaction_fee = self.get_market_data_transaction_fee(&market_data.nft_contract_id, &market_data.token_id);
 
@@ -2423,7 +3368,17 @@ impl Contract {
             started_at: market_data.started_at.map(|x| x.into()),
             ended_at: market_data.ended_at.map(|x| x.into()),
             end_price: market_data.end_price.map(|x| x.into()),
+            current_price: current_price.into(),
             is_auction: market_data.is_auction,
+            is_sealed_bid,
+            has_reserve_price: reserve_price.is_some(),
+            reserve_price: if auction_ended { reserve_price } else { None },
+            buy_now_price: auction_params.and_then(|params| params.buy_now_price),
+            auction_rules: if market_data.is_auction == Some(true) && !is_sealed_bid {
+                Some(self.internal_auction_rules(&contract_and_token_id))
+            } else {
+                None
+            },
             transaction_fee: current_transaction_fee.into()
         }
     }
@@ -2440,6 +3395,14 @@ impl Contract {
         self.treasury_id.clone()
     }
 
+    pub fn get_keeper_tip(&self) -> u16 {
+        self.keeper_tip_bps
+    }
+
+    pub fn get_auction_limits(&self) -> AuctionLimits {
+        self.auction_limits.clone()
+    }
+
     pub fn get_supply_by_owner_id(&self, account_id: AccountId) -> U64 {
         self.by_owner_id
             .get(&account_id)
@@ -2449,6 +3412,294 @@ impl Contract {
 
     // private fn
 
+    fn internal_offer_data_json(
+        &self,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        offer_data: OfferData,
+    ) -> OfferDataJson {
+        OfferDataJson {
+            buyer_id: offer_data.buyer_id,
+            nft_contract_id: offer_data.nft_contract_id,
+            token_id: offer_data.token_id,
+            token_series_id: offer_data.token_series_id,
+            ft_token_id: offer_data.ft_token_id,
+            price: U128(offer_data.price),
+            expires_at: self.offer_expires_at.get(contract_account_id_token_id).map(U64),
+            transaction_fee: U128(self.get_snapshot_transaction_fee(contract_account_id_token_id)),
+        }
+    }
+
+    fn internal_trade_data_json(
+        &self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+    ) -> TradeDataJson {
+        let trade_data = self
+            .trades
+            .get(buyer_contract_account_id_token_id)
+            .and_then(|trade_list| trade_list.trade_data.get(contract_account_id_token_id).cloned())
+            .expect("Trade data does not exist");
+
+        let mut buyer_iter = buyer_contract_account_id_token_id.splitn(3, DELIMETER);
+        let buyer_nft_contract_id = AccountId::new_unchecked(buyer_iter.next().unwrap().to_string());
+        let buyer_id = AccountId::new_unchecked(buyer_iter.next().unwrap().to_string());
+        let buyer_token_id = buyer_iter.next().unwrap().to_string();
+
+        TradeDataJson {
+            buyer_id,
+            buyer_nft_contract_id,
+            buyer_token_id,
+            nft_contract_id: trade_data.nft_contract_id,
+            token_id: trade_data.token_id,
+            token_series_id: trade_data.token_series_id,
+            receiver_id: self.trade_receivers.get(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id)),
+            expires_at: self.trade_expires_at.get(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id)).map(U64),
+            transaction_fee: U128(self.get_snapshot_transaction_fee(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id))),
+        }
+    }
+
+    fn internal_add_market_index(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        owner_id: &AccountId,
+        nft_contract_id: &AccountId,
+    ) {
+        let mut by_owner_id = self.market_by_owner_id.get(owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::MarketByOwnerIdInner {
+                    account_id_hash: hash_account_id(owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_owner_id.insert(contract_and_token_id);
+        self.market_by_owner_id.insert(owner_id, &by_owner_id);
+
+        let mut by_nft_contract_id = self.market_by_nft_contract_id.get(nft_contract_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::MarketByNftContractIdInner {
+                    account_id_hash: hash_account_id(nft_contract_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_nft_contract_id.insert(contract_and_token_id);
+        self.market_by_nft_contract_id.insert(nft_contract_id, &by_nft_contract_id);
+    }
+
+    fn internal_remove_market_index(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        owner_id: &AccountId,
+        nft_contract_id: &AccountId,
+    ) {
+        if let Some(mut by_owner_id) = self.market_by_owner_id.get(owner_id) {
+            by_owner_id.remove(contract_and_token_id);
+            if by_owner_id.is_empty() {
+                self.market_by_owner_id.remove(owner_id);
+            } else {
+                self.market_by_owner_id.insert(owner_id, &by_owner_id);
+            }
+        }
+
+        if let Some(mut by_nft_contract_id) = self.market_by_nft_contract_id.get(nft_contract_id) {
+            by_nft_contract_id.remove(contract_and_token_id);
+            if by_nft_contract_id.is_empty() {
+                self.market_by_nft_contract_id.remove(nft_contract_id);
+            } else {
+                self.market_by_nft_contract_id.insert(nft_contract_id, &by_nft_contract_id);
+            }
+        }
+    }
+
+    fn internal_add_offer_index(
+        &mut self,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        offer_data: &OfferData,
+    ) {
+        let mut by_buyer_id = self.offers_by_buyer_id.get(&offer_data.buyer_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::OffersByBuyerIdInner {
+                    account_id_hash: hash_account_id(&offer_data.buyer_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_buyer_id.insert(contract_account_id_token_id);
+        self.offers_by_buyer_id.insert(&offer_data.buyer_id, &by_buyer_id);
+
+        let contract_and_token_id = offer_token_key(offer_data);
+        let mut by_token = self.offers_by_token.get(&contract_and_token_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::OffersByTokenInner {
+                    token_hash: hash_contract_account_id_token_id(&contract_and_token_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_token.insert(contract_account_id_token_id);
+        self.offers_by_token.insert(&contract_and_token_id, &by_token);
+    }
+
+    fn internal_remove_offer_index(
+        &mut self,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        offer_data: &OfferData,
+    ) {
+        if let Some(mut by_buyer_id) = self.offers_by_buyer_id.get(&offer_data.buyer_id) {
+            by_buyer_id.remove(contract_account_id_token_id);
+            if by_buyer_id.is_empty() {
+                self.offers_by_buyer_id.remove(&offer_data.buyer_id);
+            } else {
+                self.offers_by_buyer_id.insert(&offer_data.buyer_id, &by_buyer_id);
+            }
+        }
+
+        let contract_and_token_id = offer_token_key(offer_data);
+        if let Some(mut by_token) = self.offers_by_token.get(&contract_and_token_id) {
+            by_token.remove(contract_account_id_token_id);
+            if by_token.is_empty() {
+                self.offers_by_token.remove(&contract_and_token_id);
+            } else {
+                self.offers_by_token.insert(&contract_and_token_id, &by_token);
+            }
+        }
+    }
+
+    fn internal_add_trade_index(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        trade_data: &TradeData,
+    ) {
+        let contract_and_token_id = trade_token_key(trade_data);
+        let mut by_token = self.trades_by_token.get(&contract_and_token_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::TradesByTokenInner {
+                    token_hash: hash_contract_account_id_token_id(&contract_and_token_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_token.insert(&(buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone()));
+        self.trades_by_token.insert(&contract_and_token_id, &by_token);
+
+        let proposer_id = triple_account_id(buyer_contract_account_id_token_id);
+        let mut by_proposer = self.trades_by_proposer.get(&proposer_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::TradesByProposerInner {
+                    account_id_hash: hash_account_id(&proposer_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_proposer.insert(&(buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone()));
+        self.trades_by_proposer.insert(&proposer_id, &by_proposer);
+    }
+
+    fn internal_remove_trade_index(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        trade_data: &TradeData,
+    ) {
+        let contract_and_token_id = trade_token_key(trade_data);
+        if let Some(mut by_token) = self.trades_by_token.get(&contract_and_token_id) {
+            by_token.remove(&(buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone()));
+            if by_token.is_empty() {
+                self.trades_by_token.remove(&contract_and_token_id);
+            } else {
+                self.trades_by_token.insert(&contract_and_token_id, &by_token);
+            }
+        }
+
+        let proposer_id = triple_account_id(buyer_contract_account_id_token_id);
+        if let Some(mut by_proposer) = self.trades_by_proposer.get(&proposer_id) {
+            by_proposer.remove(&(buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone()));
+            if by_proposer.is_empty() {
+                self.trades_by_proposer.remove(&proposer_id);
+            } else {
+                self.trades_by_proposer.insert(&proposer_id, &by_proposer);
+            }
+        }
+    }
+
+    // expiry of a trade, kept in sync with the expiring_trades index walked by cleanup_expired_trades
+    fn internal_set_trade_expiry(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        expires_at: Option<u64>,
+    ) {
+        let key = trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id);
+        let trade = (buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone());
+        match expires_at {
+            Some(expires_at) => {
+                self.trade_expires_at.insert(&key, &expires_at);
+                self.expiring_trades.insert(&trade);
+            }
+            None => {
+                self.trade_expires_at.remove(&key);
+                self.expiring_trades.remove(&trade);
+            }
+        }
+    }
+
+    // receiver of an incoming trade, kept in sync with the trades_by_receiver index
+    fn internal_set_trade_receiver(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        receiver_id: Option<&AccountId>,
+    ) {
+        let key = trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id);
+        let trade = (buyer_contract_account_id_token_id.clone(), contract_account_id_token_id.clone());
+
+        if let Some(previous_receiver_id) = self.trade_receivers.remove(&key) {
+            if let Some(mut by_receiver) = self.trades_by_receiver.get(&previous_receiver_id) {
+                by_receiver.remove(&trade);
+                if by_receiver.is_empty() {
+                    self.trades_by_receiver.remove(&previous_receiver_id);
+                } else {
+                    self.trades_by_receiver.insert(&previous_receiver_id, &by_receiver);
+                }
+            }
+        }
+
+        if let Some(receiver_id) = receiver_id {
+            self.trade_receivers.insert(&key, receiver_id);
+            let mut by_receiver = self.trades_by_receiver.get(receiver_id).unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::TradesByReceiverInner {
+                        account_id_hash: hash_account_id(receiver_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+            by_receiver.insert(&trade);
+            self.trades_by_receiver.insert(receiver_id, &by_receiver);
+        }
+    }
+
+    fn internal_remove_trade_list(&mut self, buyer_contract_account_id_token_id: &ContractAccountIdTokenId) {
+        if let Some(trade_list) = self.trades.remove(buyer_contract_account_id_token_id) {
+            for (contract_account_id_token_id, trade_data) in trade_list.trade_data.iter() {
+                self.internal_remove_trade_index(buyer_contract_account_id_token_id, contract_account_id_token_id, trade_data);
+                self.internal_release_trade(buyer_contract_account_id_token_id, contract_account_id_token_id, trade_data);
+                self.internal_set_trade_expiry(buyer_contract_account_id_token_id, contract_account_id_token_id, None);
+                self.internal_set_trade_receiver(buyer_contract_account_id_token_id, contract_account_id_token_id, None);
+                self.transaction_fee_snapshots.remove(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id));
+            }
+        }
+    }
+
     fn assert_owner(&self) {
         assert_eq!(
             env::predecessor_account_id(),
@@ -2457,6 +3708,21 @@ impl Contract {
         )
     }
 
+    fn internal_pay_treasury_fee(&mut self, contract_and_token_id: &ContractAndTokenId, treasury_fee: Balance) -> Vec<FeeSharePayout> {
+        let keeper_tip = match self.auction_keepers.remove(contract_and_token_id) {
+            Some(keeper_id) => {
+                let keeper_tip = treasury_fee * self.keeper_tip_bps as u128 / (MAX_TREASURY_PERCENTAGE as u128);
+                if keeper_tip > 0 {
+                    self.internal_credit_near(keeper_id, keeper_tip);
+                }
+                keeper_tip
+            }
+            None => 0,
+        };
+
+        self.internal_credit_treasury_fee(treasury_fee - keeper_tip)
+    }
+
     fn internal_transfer_near(&self, account_id: AccountId, amount: Balance){
         let balance = env::account_balance();
         if balance < amount {
@@ -2498,24 +3764,52 @@ trait ExtSelf {
         seller_id: AccountId,
         offer_data: OfferData,
         token_id: TokenId,
+        transaction_fee: Option<U128>,
     ) -> Promise;
 
-    fn callback_first_trade(
+    fn resolve_collection_offer_match(
         &mut self,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
-        seller_approval_id: u64,
+        offer_id: U64,
+        scope: OfferScope,
+        extra: Option<String>,
+        offer_data: OfferData,
+        token_id: TokenId,
+        seller_id: AccountId,
+        approval_id: u64,
+        transaction_fee: Option<U128>,
     ) -> Promise;
 
-    fn callback_second_trade(
+    fn resolve_bundle_purchase(
         &mut self,
+        bundle_id: U64,
         buyer_id: AccountId,
-        buyer_nft_contract_id: AccountId,
-        buyer_token_id: TokenId,
-        seller_id: AccountId,
-        seller_nft_contract_id: AccountId,
-        seller_token_id: TokenId,
+        bundle: Bundle,
+        transaction_fee: Option<U128>,
+    ) -> Promise;
+
+    fn resolve_bundle_delivery(&mut self, receiver_id: AccountId, item: BundleItem) -> Promise;
+
+    fn resolve_withdraw(&mut self, account_id: AccountId, amount: U128) -> Promise;
+
+    fn resolve_primary_purchase(
+        &mut self,
+        buyer_id: AccountId,
+        listing: PrimaryListing,
+        storage_deposit: U128,
+        transaction_fee: U128,
+    ) -> Promise;
+
+    fn resolve_reject_trade(
+        &mut self,
+        buyer_id: AccountId,
+        buyer_contract_account_id_token_id: ContractAccountIdTokenId,
+        contract_account_id_token_id: ContractAccountIdTokenId,
+        owner_id: AccountId,
     ) -> Promise;
+
+    fn callback_first_trade(&mut self, settlement: TradeSettlement) -> Promise;
+
+    fn callback_second_trade(&mut self, settlement: TradeSettlement) -> Promise;
 }
 
 fn add_accounts(accounts: Option<Vec<AccountId>>, set: &mut UnorderedSet<AccountId>) {
@@ -2541,6 +3835,58 @@ fn make_triple(nft_contract_id: &AccountId, buyer_id: &AccountId, token: &str) -
     )
 }
 
+// linear interpolation between price and end_price, price itself for non dutch sales
+fn dutch_auction_price(market_data: &MarketData, current_time: u64) -> u128 {
+    let end_price = match market_data.end_price {
+        Some(end_price) => end_price,
+        None => return market_data.price,
+    };
+    let started_at = market_data.started_at.unwrap_or(0);
+    let ended_at = market_data.ended_at.unwrap_or(started_at);
+
+    if current_time <= started_at {
+        market_data.price
+    } else if current_time >= ended_at {
+        end_price
+    } else {
+        let elapsed = (current_time - started_at) as u128;
+        let duration = (ended_at - started_at) as u128;
+        market_data.price - (market_data.price - end_price) * elapsed / duration
+    }
+}
+
+fn offer_token_key(offer_data: &OfferData) -> ContractAndTokenId {
+    let token = offer_data.token_id.as_ref().or(offer_data.token_series_id.as_ref()).unwrap();
+    format!("{}{}{}", offer_data.nft_contract_id, DELIMETER, token)
+}
+
+fn trade_token_key(trade_data: &TradeData) -> ContractAndTokenId {
+    let token = trade_data.token_id.as_ref().or(trade_data.token_series_id.as_ref()).unwrap();
+    format!("{}{}{}", trade_data.nft_contract_id, DELIMETER, token)
+}
+
+// keys for a token and, when the token belongs to a series, for the series as well
+fn token_and_series_keys(nft_contract_id: &AccountId, token_id: &TokenId) -> Vec<ContractAndTokenId> {
+    let mut keys = vec![format!("{}{}{}", nft_contract_id, DELIMETER, token_id)];
+    let token_series_id = token_id.split(':').next().unwrap();
+    if token_series_id != token_id {
+        keys.push(format!("{}{}{}", nft_contract_id, DELIMETER, token_series_id));
+    }
+    keys
+}
+
+// len is the size of the collection being paged, from_index 0 is always accepted
+fn pagination(from_index: Option<U128>, limit: Option<u64>, len: u64) -> (usize, usize) {
+    let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+    assert!(
+        start_index == 0 || (len as u128) > start_index,
+        "Out of bounds, please use a smaller from_index."
+    );
+    let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+    assert_ne!(limit, 0, "Cannot provide limit of 0.");
+    (start_index as usize, limit)
+}
+
 fn make_key_owner_by_id_trade(contract_account_id_token_id: String) -> String {
     format!("{}{}trade", contract_account_id_token_id, DELIMETER)
 }
@@ -2575,17 +3921,64 @@ mod tests {
     }
 
     #[test]
-    fn test_new() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let contract = Contract::new(
-            accounts(0),
-            accounts(1),
-            None,
-            Some(vec![accounts(2)]),
-            Some(vec![accounts(2)]),
-            500,
-        );
+    fn test_migrate_from_v3() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+
+        let mut prev = ContractV3 {
+            owner_id: accounts(0),
+            treasury_id: accounts(1),
+            old_market: UnorderedMap::new(StorageKey::Market),
+            market: UnorderedMap::new(StorageKey::MarketV2),
+            approved_ft_token_ids: UnorderedSet::new(StorageKey::FTTokenIds),
+            approved_nft_contract_ids: UnorderedSet::new(StorageKey::NFTContractIds),
+            storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            paras_nft_contracts: UnorderedSet::new(StorageKey::ParasNFTContractIds),
+            transaction_fee: TransactionFee {
+                next_fee: None,
+                start_time: None,
+                current_fee: 500,
+            },
+            trades: UnorderedMap::new(StorageKey::Trade),
+            market_data_transaction_fee: MarketDataTransactionFee {
+                transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee),
+            },
+        };
+        let contract_and_token_id = format!("{}{}{}", accounts(2), DELIMETER, "1:1");
+        prev.old_market.insert(
+            &contract_and_token_id,
+            &MarketDataV1 {
+                owner_id: accounts(3),
+                approval_id: 1,
+                nft_contract_id: accounts(2),
+                token_id: "1:1".to_string(),
+                ft_token_id: near_account(),
+                price: 10u128.pow(24),
+            },
+        );
+        prev.market_data_transaction_fee.transaction_fee.insert(&contract_and_token_id, &300);
+        env::state_write(&prev);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.market_data_transaction_fee.transaction_fee.get(&contract_and_token_id), Some(300));
+        assert_eq!(contract.get_market_data_by_owner(accounts(3), None, None).len(), 1);
+        assert_eq!(contract.get_market_data_by_nft_contract(accounts(2), None, None).len(), 1);
+    }
+
+    #[test]
+    fn test_new() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new(
+            accounts(0),
+            accounts(1),
+            None,
+            Some(vec![accounts(2)]),
+            Some(vec![accounts(2)]),
+            500,
+        );
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.get_owner(), accounts(0));
         assert_eq!(contract.get_treasury(), accounts(1));
@@ -2858,6 +4251,7 @@ mod tests {
             Some("1:2".to_string()),
             accounts(2),
             1,
+            TradeAssets::default(),
         );
 
         let trade_data = contract.get_trade(
@@ -2893,6 +4287,7 @@ mod tests {
             Some("1:1".to_string()),
             accounts(2),
             1,
+            TradeAssets::default(),
         );
 
         testing_env!(context
@@ -3122,6 +4517,1362 @@ mod tests {
         assert_eq!(market_data_transaction_fee, 500);
     }
 
+    #[test]
+    fn test_get_market_data_enumeration() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+
+        for (owner_id, nft_contract_id, token_id) in [
+            (accounts(3), accounts(2), "1:1"),
+            (accounts(3), accounts(5), "1:2"),
+            (accounts(4), accounts(2), "1:3"),
+        ] {
+            contract.internal_add_market_data(
+                owner_id,
+                1,
+                nft_contract_id,
+                token_id.to_string(),
+                near_account(),
+                U128::from(10u128.pow(24)),
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+
+        assert_eq!(contract.get_market_data_all(None, None).len(), 3);
+        assert_eq!(contract.get_market_data_all(Some(U128(1)), Some(1))[0].token_id, "1:2");
+        assert_eq!(contract.get_market_data_supply(), U64(3));
+
+        let by_owner = contract.get_market_data_by_owner(accounts(3), None, None);
+        assert_eq!(by_owner.len(), 2);
+        assert_eq!(contract.get_market_data_supply_by_owner(accounts(3)), U64(2));
+
+        let by_contract = contract.get_market_data_by_nft_contract(accounts(2), None, None);
+        assert_eq!(by_contract.len(), 2);
+        assert_eq!(by_contract[1].owner_id, accounts(4));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.delete_market_data(accounts(2), "1:1".to_string());
+
+        assert_eq!(contract.get_market_data_by_owner(accounts(3), None, None).len(), 1);
+        assert_eq!(contract.get_market_data_supply_by_nft_contract(accounts(2)), U64(1));
+        assert!(contract.get_market_data_by_owner(accounts(1), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_get_offers_by_buyer_and_token() {
+        let (mut context, mut contract) = setup_contract();
+
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(one_near)
+            .build());
+
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(one_near), accounts(0));
+        contract.internal_add_offer(accounts(3), None, Some("1".to_string()), near_account(), U128(one_near), accounts(1));
+        contract.internal_add_offer(accounts(3), Some("2:1".to_string()), None, near_account(), U128(one_near), accounts(0));
+
+        let by_buyer = contract.get_offers_by_buyer(accounts(0), None, None);
+        assert_eq!(by_buyer.len(), 2);
+
+        let for_token = contract.get_offers_for_token(accounts(3), "1:1".to_string(), None, None);
+        assert_eq!(for_token.len(), 2);
+        assert_eq!(for_token[0].buyer_id, accounts(0));
+        assert_eq!(for_token[1].token_series_id, Some("1".to_string()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.delete_offer(accounts(3), Some("1:1".to_string()), None);
+
+        assert_eq!(contract.get_offers_by_buyer(accounts(0), None, None).len(), 1);
+        assert_eq!(contract.get_offers_for_token(accounts(3), "1:1".to_string(), None, None).len(), 1);
+        assert_eq!(contract.get_offers_for_token(accounts(3), "1:1".to_string(), Some(U128(0)), Some(1)).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Out of bounds, please use a smaller from_index.")]
+    fn test_get_offers_for_token_out_of_bounds() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(10u128.pow(24)), accounts(0));
+
+        contract.get_offers_for_token(accounts(3), "1:1".to_string(), Some(U128(1)), None);
+    }
+
+    #[test]
+    fn test_get_trades_for_token() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+
+        contract.internal_add_trade(
+            accounts(3),
+            Some("1:1".to_string()),
+            None,
+            accounts(1),
+            Some("1:2".to_string()),
+            accounts(2),
+            1,
+            TradeAssets::default(),
+        );
+
+        let trades = contract.get_trades_for_token(accounts(3), "1:1".to_string(), None, None);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].buyer_id, accounts(2));
+        assert_eq!(trades[0].buyer_nft_contract_id, accounts(1));
+        assert_eq!(trades[0].buyer_token_id, "1:2");
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.delete_trade(accounts(3), Some("1:1".to_string()), None, accounts(1), "1:2".to_string());
+
+        assert!(contract.get_trades_for_token(accounts(3), "1:1".to_string(), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_dutch_auction_price_decay() {
+        let (mut context, mut contract) = setup_contract();
+
+        let one_near = 10u128.pow(24);
+        let started_at: u64 = 1_000_000_000;
+        let ended_at: u64 = 2_000_000_000;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(started_at)
+            .build());
+
+        contract.internal_add_market_data(
+            accounts(3),
+            1,
+            accounts(2),
+            "1:1".to_string(),
+            near_account(),
+            U128(10 * one_near),
+            None,
+            Some(U64(ended_at)),
+            Some(U128(2 * one_near)),
+            None,
+        );
+
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert_eq!(market.current_price, U128(10 * one_near));
+
+        testing_env!(context.block_timestamp(1_250_000_000).build());
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert_eq!(market.current_price, U128(8 * one_near));
+
+        testing_env!(context.block_timestamp(ended_at + 1).build());
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert_eq!(market.current_price, U128(2 * one_near));
+    }
+
+    #[test]
+    fn test_dutch_auction_buy_above_current_price() {
+        let (mut context, mut contract) = setup_contract();
+
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(1_000_000_000)
+            .build());
+
+        contract.internal_add_market_data(
+            accounts(3),
+            1,
+            accounts(2),
+            "1:1".to_string(),
+            near_account(),
+            U128(10 * one_near),
+            None,
+            Some(U64(2_000_000_000)),
+            Some(U128(2 * one_near)),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_500_000_000)
+            .attached_deposit(7 * one_near)
+            .account_balance(100 * one_near)
+            .build());
+
+        contract.buy(accounts(2), "1:1".to_string(), None, None);
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the current price")]
+    fn test_dutch_auction_buy_below_current_price() {
+        let (mut context, mut contract) = setup_contract();
+
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(1_000_000_000)
+            .build());
+
+        contract.internal_add_market_data(
+            accounts(3),
+            1,
+            accounts(2),
+            "1:1".to_string(),
+            near_account(),
+            U128(10 * one_near),
+            None,
+            Some(U64(2_000_000_000)),
+            Some(U128(2 * one_near)),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_500_000_000)
+            .attached_deposit(5 * one_near)
+            .build());
+
+        contract.buy(accounts(2), "1:1".to_string(), None, None);
+    }
+
+    fn setup_sealed_auction(contract: &mut Contract, context: &mut VMContextBuilder, settlement: SealedBidSettlement) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(1_000_000_000)
+            .build());
+
+        contract.internal_add_market_data(
+            accounts(3),
+            1,
+            accounts(2),
+            "1:1".to_string(),
+            near_account(),
+            U128(10u128.pow(24)),
+            None,
+            Some(U64(2_000_000_000)),
+            None,
+            Some(true),
+        );
+        contract.internal_add_sealed_auction(
+            &accounts(2),
+            &"1:1".to_string(),
+            Some(U64(3_000_000_000)),
+            Some(settlement),
+        );
+    }
+
+    fn commit_and_reveal(contract: &mut Contract, context: &mut VMContextBuilder, bidders: Vec<(AccountId, u128, bool)>) {
+        let one_near = 10u128.pow(24);
+        for (bidder_id, amount, _) in &bidders {
+            let commitment = contract.get_sealed_bid_commitment(bidder_id.clone(), U128(amount * one_near), "salt".to_string());
+            testing_env!(context
+                .predecessor_account_id(bidder_id.clone())
+                .block_timestamp(1_500_000_000)
+                .attached_deposit(10 * one_near)
+                .build());
+            contract.commit_sealed_bid(accounts(2), "1:1".to_string(), commitment);
+        }
+        for (bidder_id, amount, reveal) in &bidders {
+            if !reveal {
+                continue;
+            }
+            testing_env!(context
+                .predecessor_account_id(bidder_id.clone())
+                .block_timestamp(2_500_000_000)
+                .attached_deposit(0)
+                .build());
+            contract.reveal_sealed_bid(accounts(2), "1:1".to_string(), U128(amount * one_near), "salt".to_string());
+        }
+    }
+
+    #[test]
+    fn test_sealed_bid_second_price_settlement() {
+        let (mut context, mut contract) = setup_contract();
+        setup_sealed_auction(&mut contract, &mut context, SealedBidSettlement::SecondPrice);
+
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert!(market.is_sealed_bid);
+
+        commit_and_reveal(&mut contract, &mut context, vec![
+            (accounts(1), 5, true),
+            (accounts(4), 8, true),
+            (accounts(5), 9, false),
+        ]);
+
+        let sealed_auction = contract.get_sealed_auction(accounts(2), "1:1".to_string()).unwrap();
+        assert_eq!(sealed_auction.bids.len(), 3);
+        assert_eq!(sealed_auction.bids[1].revealed_price, Some(U128(8 * 10u128.pow(24))));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(3_500_000_000)
+            .account_balance(100 * 10u128.pow(24))
+            .build());
+        contract.settle_sealed_auction(accounts(2), "1:1".to_string());
+
+        let settle_log = near_sdk::test_utils::get_logs().last().unwrap().clone();
+        assert!(settle_log.contains(&format!("\"bidder_id\":\"{}\"", accounts(4))));
+        assert!(settle_log.contains(&format!("\"price\":\"{}\"", 5 * 10u128.pow(24))));
+        assert!(contract.get_sealed_auction(accounts(2), "1:1".to_string()).is_none());
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Commitment does not match")]
+    fn test_sealed_bid_reveal_wrong_amount() {
+        let (mut context, mut contract) = setup_contract();
+        setup_sealed_auction(&mut contract, &mut context, SealedBidSettlement::FirstPrice);
+
+        commit_and_reveal(&mut contract, &mut context, vec![(accounts(1), 5, false)]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(2_500_000_000)
+            .build());
+        contract.reveal_sealed_bid(accounts(2), "1:1".to_string(), U128(4 * 10u128.pow(24)), "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Sealed-bid auction, use commit_sealed_bid")]
+    fn test_sealed_bid_rejects_english_bid() {
+        let (mut context, mut contract) = setup_contract();
+        setup_sealed_auction(&mut contract, &mut context, SealedBidSettlement::FirstPrice);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_500_000_000)
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.add_bid(accounts(2), near_account(), "1:1".to_string(), U128(10u128.pow(24)));
+    }
+
+    #[test]
+    fn test_sealed_bid_no_reveal_cancels_listing() {
+        let (mut context, mut contract) = setup_contract();
+        setup_sealed_auction(&mut contract, &mut context, SealedBidSettlement::FirstPrice);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_unrevealed_bid_policy(UnrevealedBidPolicy::Treasury);
+
+        commit_and_reveal(&mut contract, &mut context, vec![(accounts(1), 5, false)]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(3_500_000_000)
+            .account_balance(100 * 10u128.pow(24))
+            .build());
+        contract.settle_sealed_auction(accounts(2), "1:1".to_string());
+
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+        assert!(contract.get_sealed_auction(accounts(2), "1:1".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot cancel a sealed-bid auction after bidding closed, use settle_sealed_auction")]
+    fn test_sealed_bid_cancel_after_reveal() {
+        let (mut context, mut contract) = setup_contract();
+        setup_sealed_auction(&mut contract, &mut context, SealedBidSettlement::FirstPrice);
+        commit_and_reveal(&mut contract, &mut context, vec![(accounts(1), 5, true)]);
+
+        // every bid is public, the seller cannot back out before settlement
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(3_500_000_000)
+            .attached_deposit(1)
+            .build());
+        contract.delete_market_data(accounts(2), "1:1".to_string());
+    }
+
+    fn setup_reserve_auction(contract: &mut Contract, context: &mut VMContextBuilder, auction_params: AuctionParams) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(1_000_000_000)
+            .build());
+
+        contract.internal_add_market_data(
+            accounts(3),
+            1,
+            accounts(2),
+            "1:1".to_string(),
+            near_account(),
+            U128(10u128.pow(24)),
+            None,
+            Some(U64(2_000_000_000)),
+            None,
+            Some(true),
+        );
+        contract.internal_add_auction_params(&accounts(2), &"1:1".to_string(), auction_params);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_100_000_000)
+            .attached_deposit(2 * 10u128.pow(24))
+            .build());
+        contract.add_bid(accounts(2), near_account(), "1:1".to_string(), U128(2 * 10u128.pow(24)));
+    }
+
+    #[test]
+    fn test_reserve_price_not_met_refunds_bids() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            reserve_price: Some(U128(5 * 10u128.pow(24))),
+            ..Default::default()
+        });
+
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert!(market.has_reserve_price);
+        assert!(market.reserve_price.is_none());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(2_000_000_000 + FIVE_MINUTES)
+            .attached_deposit(1)
+            .build());
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert_eq!(market.reserve_price, Some(U128(5 * 10u128.pow(24))));
+
+        contract.end_auction(accounts(2), "1:1".to_string());
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Reserve price not met")]
+    fn test_accept_bid_below_reserve_before_end() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            reserve_price: Some(U128(5 * 10u128.pow(24))),
+            ..Default::default()
+        });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(1_200_000_000)
+            .attached_deposit(1)
+            .build());
+        contract.accept_bid(accounts(2), "1:1".to_string());
+    }
+
+    #[test]
+    fn test_buy_now_settles_auction() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            buy_now_price: Some(U128(8 * 10u128.pow(24))),
+            ..Default::default()
+        });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .block_timestamp(1_200_000_000)
+            .attached_deposit(8 * 10u128.pow(24))
+            .account_balance(100 * 10u128.pow(24))
+            .build());
+        contract.buy(accounts(2), "1:1".to_string(), None, None);
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid must be lower than the buy now price")]
+    fn test_bid_at_buy_now_price() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            buy_now_price: Some(U128(8 * 10u128.pow(24))),
+            ..Default::default()
+        });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .block_timestamp(1_200_000_000)
+            .attached_deposit(8 * 10u128.pow(24))
+            .build());
+        contract.add_bid(accounts(2), near_account(), "1:1".to_string(), U128(8 * 10u128.pow(24)));
+    }
+
+    #[test]
+    fn test_custom_auction_rules() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            min_increment: Some(U128(10u128.pow(24))),
+            extension_window: Some(U64(0)),
+            ..Default::default()
+        });
+
+        let rules = contract
+            .get_market_data(accounts(2), "1:1".to_string())
+            .auction_rules
+            .unwrap();
+        assert_eq!(rules.min_increment, Some(U128(10u128.pow(24))));
+        assert_eq!(rules.min_increment_bps, None);
+        assert_eq!(rules.max_bid_history, DEFAULT_MAX_BID_HISTORY);
+
+        // 2 NEAR + 1 NEAR absolute increment, no anti-sniping extension
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .block_timestamp(1_900_000_000)
+            .attached_deposit(3 * 10u128.pow(24))
+            .build());
+        contract.add_bid(accounts(2), near_account(), "1:1".to_string(), U128(3 * 10u128.pow(24)));
+
+        let market = contract.get_market_data(accounts(2), "1:1".to_string());
+        assert_eq!(market.ended_at, Some(U64(2_000_000_000)));
+        assert_eq!(market.bids.unwrap().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "min_increment_bps higher than 5000")]
+    fn test_auction_rules_above_limit() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams {
+            min_increment_bps: Some(6_000),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_settle_auction_by_keeper() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams::default());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(2_000_000_000 + FIVE_MINUTES)
+            .attached_deposit(0)
+            .account_balance(100 * 10u128.pow(24))
+            .build());
+        contract.settle_auction(accounts(2), "1:1".to_string());
+
+        assert_eq!(contract.get_market_data_supply(), U64(0));
+        let key = format!("{}{}{}", accounts(2), DELIMETER, "1:1");
+        assert_eq!(contract.auction_keepers.get(&key), Some(accounts(5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Auction has not ended yet")]
+    fn test_settle_auction_before_end() {
+        let (mut context, mut contract) = setup_contract();
+        setup_reserve_auction(&mut contract, &mut context, AuctionParams::default());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(1_500_000_000)
+            .attached_deposit(0)
+            .build());
+        contract.settle_auction(accounts(2), "1:1".to_string());
+    }
+
+    fn setup_expiring_offer(contract: &mut Contract, context: &mut VMContextBuilder) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_000_000_000)
+            .attached_deposit(STORAGE_ADD_MARKET_DATA)
+            .build());
+        contract.storage_deposit(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.add_offer(
+            accounts(2),
+            Some("1:1".to_string()),
+            None,
+            near_account(),
+            U128(10u128.pow(24)),
+            Some(U64(2_000_000_000)),
+        );
+    }
+
+    #[test]
+    fn test_cleanup_expired_offers() {
+        let (mut context, mut contract) = setup_contract();
+        setup_expiring_offer(&mut contract, &mut context);
+
+        let offer = contract.get_offer(accounts(2), accounts(1), Some("1:1".to_string()), None);
+        assert_eq!(offer.expires_at, Some(U64(2_000_000_000)));
+        assert_eq!(contract.get_supply_by_owner_id(accounts(1)), U64(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(1_500_000_000)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.cleanup_expired_offers(None, None), U64(0));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(2_000_000_000)
+            .account_balance(100 * 10u128.pow(24))
+            .build());
+        assert_eq!(contract.cleanup_expired_offers(None, None), U64(1));
+        assert_eq!(contract.get_supply_by_owner_id(accounts(1)), U64(0));
+        assert!(contract.get_offers_by_buyer(accounts(1), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_accept_expired_offer() {
+        let (mut context, mut contract) = setup_contract();
+        setup_expiring_offer(&mut contract, &mut context);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(2_000_000_000)
+            .build());
+        match contract.internal_accept_offer(accounts(2), accounts(1), "1:1".to_string(), accounts(3), 1, 10u128.pow(24)) {
+            PromiseOrValue::Value(accepted) => assert!(!accepted),
+            PromiseOrValue::Promise(_) => panic!("expired offer accepted"),
+        }
+        assert_eq!(contract.get_offers_by_buyer(accounts(1), None, None).len(), 1);
+    }
+
+    fn setup_collection_offer(contract: &mut Contract, context: &mut VMContextBuilder, scope: OfferScope, extra: Option<String>) -> U64 {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(1_000_000_000)
+            .attached_deposit(STORAGE_ADD_MARKET_DATA)
+            .build());
+        contract.storage_deposit(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(2 * 10u128.pow(24))
+            .build());
+        contract.add_collection_offer(accounts(2), scope, extra, near_account(), U128(10u128.pow(24)), 2, None)
+    }
+
+    #[test]
+    fn test_collection_offer_partial_fills() {
+        let (mut context, mut contract) = setup_contract();
+        let offer_id = setup_collection_offer(&mut contract, &mut context, OfferScope::Series("1|42".to_string()), None);
+        assert_eq!(contract.get_supply_by_owner_id(accounts(1)), U64(1));
+        assert_eq!(contract.get_collection_offers(Some(accounts(2)), None, None).len(), 1);
+        assert!(contract.get_collection_offers(Some(accounts(3)), None, None).is_empty());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+
+        match contract.internal_accept_collection_offer(accounts(2), offer_id, "1|7:1".to_string(), accounts(3), 1, 10u128.pow(24)) {
+            PromiseOrValue::Value(accepted) => assert!(!accepted),
+            PromiseOrValue::Promise(_) => panic!("token of another series accepted"),
+        }
+        assert_eq!(contract.get_collection_offer(offer_id).unwrap().filled, 0);
+
+        contract.internal_accept_collection_offer(accounts(2), offer_id, "1|42:1".to_string(), accounts(3), 1, 10u128.pow(24));
+        assert_eq!(contract.get_collection_offer(offer_id).unwrap().filled, 1);
+
+        contract.internal_accept_collection_offer(accounts(2), offer_id, "1|42:2".to_string(), accounts(4), 1, 10u128.pow(24));
+        assert!(contract.get_collection_offer(offer_id).is_none());
+        assert_eq!(contract.get_supply_by_owner_id(accounts(1)), U64(0));
+        assert!(contract.get_collection_offers(Some(accounts(2)), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_collection_offer_trait_match_reserves_unit() {
+        let (mut context, mut contract) = setup_contract();
+        let offer_id = setup_collection_offer(&mut contract, &mut context, OfferScope::Contract, Some("\"zone\":\"vip\"".to_string()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+
+        match contract.internal_accept_collection_offer(accounts(2), offer_id, "1|42:1".to_string(), accounts(3), 1, 10u128.pow(24)) {
+            PromiseOrValue::Promise(_) => {}
+            PromiseOrValue::Value(_) => panic!("trait offer should check the token metadata"),
+        }
+        assert_eq!(contract.get_collection_offer(offer_id).unwrap().filled, 1);
+
+        assert!(collection_offer_matches(
+            &OfferScope::Event("1|42".to_string()),
+            &Some("\"zone\":\"vip\"".to_string()),
+            &"3|9:1".to_string(),
+            Some(&MatchTokenMetadata {
+                extra: Some("{\"zone\":\"vip\"}".to_string()),
+                reference: Some("1|42".to_string()),
+            }),
+        ));
+    }
+
+    fn setup_bundle(contract: &mut Contract, context: &mut VMContextBuilder) -> U64 {
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .signer_account_id(accounts(3))
+            .attached_deposit(STORAGE_ADD_MARKET_DATA)
+            .build());
+        contract.storage_deposit(None);
+
+        let bundle_id = contract.create_bundle(
+            vec![
+                BundleTokenArgs { nft_contract_id: accounts(2), token_id: "1|42:1".to_string() },
+                BundleTokenArgs { nft_contract_id: accounts(2), token_id: "2|43:1".to_string() },
+            ],
+            near_account(),
+            U128(3 * 10u128.pow(24)),
+        );
+
+        contract.internal_add_bundle_approval(bundle_id, accounts(2), "1|42:1".to_string(), accounts(3), 1);
+        bundle_id
+    }
+
+    #[test]
+    fn test_bundle_listing_and_buy() {
+        let (mut context, mut contract) = setup_contract();
+        let bundle_id = setup_bundle(&mut contract, &mut context);
+        assert!(!contract.get_bundle(bundle_id).unwrap().is_active);
+
+        contract.internal_add_bundle_approval(bundle_id, accounts(2), "2|43:1".to_string(), accounts(3), 2);
+        assert!(contract.get_bundle(bundle_id).unwrap().is_active);
+        assert_eq!(contract.get_bundles_by_owner(accounts(3), None, None).len(), 1);
+        assert_eq!(contract.get_supply_by_owner_id(accounts(3)), U64(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(3 * 10u128.pow(24))
+            .build());
+        contract.buy_bundle(bundle_id);
+
+        assert!(contract.get_bundle(bundle_id).is_none());
+        assert_eq!(contract.get_supply_by_owner_id(accounts(3)), U64(0));
+        assert!(contract.get_bundles_by_owner(accounts(3), None, None).is_empty());
+        assert!(contract.bundles_by_owner_id.get(&accounts(3)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Bundle is not active, some tokens are not approved")]
+    fn test_buy_inactive_bundle() {
+        let (mut context, mut contract) = setup_contract();
+        let bundle_id = setup_bundle(&mut contract, &mut context);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(3 * 10u128.pow(24))
+            .build());
+        contract.buy_bundle(bundle_id);
+    }
+
+    #[test]
+    fn test_resolve_bundle_purchase() {
+        let (mut context, mut contract) = setup_contract();
+        let bundle_id = setup_bundle(&mut contract, &mut context);
+        contract.internal_add_bundle_approval(bundle_id, accounts(2), "2|43:1".to_string(), accounts(3), 2);
+        let bundle = contract.bundles.get(&bundle_id.0).unwrap();
+        let shares = bundle_shares(bundle.price.0, bundle.items.len());
+        assert_eq!(shares.iter().sum::<u128>(), bundle.price.0);
+
+        let payout = near_sdk::serde_json::to_vec(&Payout {
+            payout: vec![(accounts(3), U128(shares[1]))].into_iter().collect(),
+        })
+        .unwrap();
+
+        // one transfer failed, the bundle is rolled back
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).account_balance(100 * 10u128.pow(24)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(payout.clone()), near_sdk::PromiseResult::Failed],
+        );
+        let bundle = contract.bundles.get(&bundle_id.0).unwrap();
+        assert!(!contract.resolve_bundle_purchase(bundle_id, accounts(1), bundle, Some(U128(500))));
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).account_balance(100 * 10u128.pow(24)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(payout.clone()), near_sdk::PromiseResult::Successful(payout)],
+        );
+        let bundle = contract.bundles.get(&bundle_id.0).unwrap();
+        assert!(contract.resolve_bundle_purchase(bundle_id, accounts(1), bundle, Some(U128(500))));
+    }
+
+    #[test]
+    fn test_resolve_bundle_purchase_clears_token() {
+        let (mut context, mut contract) = setup_contract();
+        let bundle_id = setup_bundle(&mut contract, &mut context);
+        contract.internal_add_bundle_approval(bundle_id, accounts(2), "2|43:1".to_string(), accounts(3), 2);
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(one_near)
+            .build());
+        contract.internal_add_offer(accounts(2), Some("1|42:1".to_string()), None, near_account(), U128(one_near), accounts(4));
+        contract.internal_add_trade(
+            accounts(2),
+            Some("2|43:1".to_string()),
+            None,
+            accounts(2),
+            Some("3|44:1".to_string()),
+            accounts(4),
+            1,
+            TradeAssets::default(),
+        );
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).account_balance(100 * one_near).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![]), near_sdk::PromiseResult::Successful(vec![])],
+        );
+        let bundle = contract.bundles.get(&bundle_id.0).unwrap();
+        assert!(contract.resolve_bundle_purchase(bundle_id, accounts(1), bundle, Some(U128(500))));
+
+        // the offer is refunded and the trade released
+        assert!(contract.get_offers_for_token(accounts(2), "1|42:1".to_string(), None, None).is_empty());
+        assert!(contract.get_trades_for_token(accounts(2), "2|43:1".to_string(), None, None).is_empty());
+        assert_eq!(contract.get_credit_balance(accounts(4)), U128(one_near));
+    }
+
+    #[test]
+    fn test_bundle_delivery_fail() {
+        let (mut context, mut contract) = setup_contract();
+        let item = BundleItem {
+            nft_contract_id: accounts(2),
+            token_id: "1|42:1".to_string(),
+            approval_id: Some(1),
+        };
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        assert!(!contract.resolve_bundle_delivery(accounts(1), item));
+        assert_eq!(contract.get_bundle_delivery(accounts(2), "1|42:1".to_string()), Some(accounts(1)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.claim_bundle_item(accounts(2), "1|42:1".to_string());
+        assert!(contract.get_bundle_delivery(accounts(2), "1|42:1".to_string()).is_none());
+    }
+
+    fn setup_multi_asset_trade(contract: &mut Contract, context: &mut VMContextBuilder) {
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(2 * one_near).build());
+        contract.trade_deposit(None);
+
+        contract.internal_add_trade(
+            accounts(2),
+            Some("1:1".to_string()),
+            None,
+            accounts(2),
+            Some("1:2".to_string()),
+            accounts(3),
+            1,
+            TradeAssets {
+                buyer_amount: Some(U128(one_near)),
+                seller_amount: None,
+                ft_token_id: None,
+                buyer_tokens: Some(vec![BundleTokenArgs {
+                    nft_contract_id: accounts(2),
+                    token_id: "1:3".to_string(),
+                }]),
+                seller_tokens: Some(vec![BundleTokenArgs {
+                    nft_contract_id: accounts(2),
+                    token_id: "1:4".to_string(),
+                }]),
+            },
+        );
+    }
+
+    #[test]
+    fn test_add_trade_with_near_top_up() {
+        let (mut context, mut contract) = setup_contract();
+        setup_multi_asset_trade(&mut contract, &mut context);
+        let one_near = 10u128.pow(24);
+
+        // the top-up is escrowed at proposal
+        assert_eq!(contract.trade_balance_of(accounts(3)), U128(one_near));
+        let trade_data = contract.get_trade(accounts(2), Some("1:1".to_string()), None, accounts(3), accounts(2), "1:2".to_string());
+        assert_eq!(trade_data.buyer_amount, Some(one_near));
+        assert_eq!(trade_data.ft_token_id, Some(NEAR.to_string()));
+        assert_eq!(trade_data.is_active, Some(false));
+
+        // every extra token has to be approved by its owner
+        contract.internal_add_trade_item_approval(
+            accounts(3),
+            make_triple(&accounts(2), &accounts(3), "1:2"),
+            make_triple(&accounts(2), &accounts(3), "1:1"),
+            accounts(2),
+            "1:3".to_string(),
+            accounts(3),
+            2,
+        );
+        contract.internal_add_trade_item_approval(
+            accounts(3),
+            make_triple(&accounts(2), &accounts(3), "1:2"),
+            make_triple(&accounts(2), &accounts(3), "1:1"),
+            accounts(2),
+            "1:4".to_string(),
+            accounts(4),
+            3,
+        );
+        let trade_data = contract.get_trade(accounts(2), Some("1:1".to_string()), None, accounts(3), accounts(2), "1:2".to_string());
+        assert_eq!(trade_data.is_active, Some(true));
+        let trade_items = contract
+            .get_trade_items(accounts(2), Some("1:1".to_string()), None, accounts(3), accounts(2), "1:2".to_string())
+            .unwrap();
+        assert_eq!(trade_items.seller_items[0].owner_id, Some(accounts(4)));
+
+        contract.internal_accept_trade(accounts(2), accounts(3), "1:1".to_string(), accounts(4), 4, accounts(2), "1:2".to_string());
+        assert!(contract.trade_items.get(&trade_key(
+            &make_triple(&accounts(2), &accounts(3), "1:2"),
+            &make_triple(&accounts(2), &accounts(3), "1:1"),
+        )).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Trade is not active, some tokens are not approved")]
+    fn test_accept_trade_with_unapproved_items() {
+        let (mut context, mut contract) = setup_contract();
+        setup_multi_asset_trade(&mut contract, &mut context);
+
+        contract.internal_accept_trade(accounts(2), accounts(3), "1:1".to_string(), accounts(4), 4, accounts(2), "1:2".to_string());
+    }
+
+    #[test]
+    fn test_trade_withdraw_uses_credit_ledger() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(2 * one_near).build());
+        contract.trade_deposit(None);
+        assert_eq!(contract.get_credit_balance(accounts(3)), U128(2 * one_near));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(1).build());
+        contract.trade_withdraw(Some(U128(one_near)));
+        assert_eq!(contract.trade_balance_of(accounts(3)), U128(one_near));
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient trade deposit")]
+    fn test_add_trade_insufficient_trade_deposit() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+
+        contract.internal_add_trade(
+            accounts(2),
+            Some("1:1".to_string()),
+            None,
+            accounts(2),
+            Some("1:2".to_string()),
+            accounts(3),
+            1,
+            TradeAssets {
+                buyer_amount: Some(U128(1)),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_callback_second_trade_refund() {
+        let (mut context, mut contract) = setup_contract();
+        let settlement = || TradeSettlement {
+            buyer_id: accounts(3),
+            seller_id: accounts(4),
+            buyer_tokens: vec![TradeItem {
+                nft_contract_id: accounts(2),
+                token_id: "1:2".to_string(),
+                approval_id: Some(1),
+                owner_id: Some(accounts(3)),
+            }],
+            seller_tokens: vec![TradeItem {
+                nft_contract_id: accounts(2),
+                token_id: "1:1".to_string(),
+                approval_id: Some(2),
+                owner_id: Some(accounts(4)),
+            }],
+            buyer_amount: Some(U128(10u128.pow(24))),
+            seller_amount: None,
+            transaction_fee: U128(500),
+        };
+
+        // seller's token failed, buyer's token and top-up go back
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).account_balance(100 * 10u128.pow(24)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        assert!(!contract.callback_second_trade(settlement()));
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).account_balance(100 * 10u128.pow(24)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        assert!(contract.callback_second_trade(settlement()));
+    }
+
+    fn setup_expiring_trade(contract: &mut Contract, context: &mut VMContextBuilder) {
+        testing_env!(context.predecessor_account_id(accounts(3)).block_timestamp(1_000).build());
+
+        contract.add_trade(
+            accounts(2),
+            Some("1:1".to_string()),
+            None,
+            accounts(2),
+            accounts(3),
+            Some("1:2".to_string()),
+            1,
+            TradeAssets::default(),
+            Some(accounts(4)),
+            Some(U64(2_000)),
+        );
+    }
+
+    #[test]
+    fn test_trade_expiry_and_enumeration() {
+        let (mut context, mut contract) = setup_contract();
+        setup_expiring_trade(&mut contract, &mut context);
+
+        let incoming = contract.get_incoming_trades(accounts(4), None, None);
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].expires_at, Some(U64(2_000)));
+        assert_eq!(contract.get_outgoing_trades(accounts(3), None, None).len(), 1);
+        assert_eq!(contract.get_outgoing_trades(accounts(4), None, None).len(), 0);
+
+        // not expired yet
+        assert_eq!(contract.expiring_trades.len(), 1);
+        assert_eq!(contract.cleanup_expired_trades(None, None), U64(0));
+
+        testing_env!(context.predecessor_account_id(accounts(5)).block_timestamp(2_000).build());
+        assert_eq!(contract.cleanup_expired_trades(None, None), U64(1));
+        assert_eq!(contract.get_outgoing_trades(accounts(3), None, None).len(), 0);
+        assert_eq!(contract.get_incoming_trades(accounts(4), None, None).len(), 0);
+        assert!(contract.trades_by_proposer.get(&accounts(3)).is_none());
+        assert!(contract.trades_by_receiver.get(&accounts(4)).is_none());
+        assert!(contract.expiring_trades.is_empty());
+        assert!(contract.trade_expires_at.get(&trade_key(
+            &make_triple(&accounts(2), &accounts(3), "1:2"),
+            &make_triple(&accounts(2), &accounts(3), "1:1"),
+        )).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Trade has expired")]
+    fn test_accept_expired_trade() {
+        let (mut context, mut contract) = setup_contract();
+        setup_expiring_trade(&mut contract, &mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).block_timestamp(2_000).build());
+        contract.internal_accept_trade(accounts(2), accounts(3), "1:1".to_string(), accounts(4), 2, accounts(2), "1:2".to_string());
+    }
+
+    #[test]
+    fn test_reject_trade() {
+        let (mut context, mut contract) = setup_contract();
+        setup_expiring_trade(&mut contract, &mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(4)).attached_deposit(1).build());
+        let rejected = contract.reject_trade(accounts(2), "1:1".to_string(), accounts(3), accounts(2), "1:2".to_string());
+        assert!(matches!(rejected, PromiseOrValue::Value(true)));
+        assert_eq!(contract.get_incoming_trades(accounts(4), None, None).len(), 0);
+        assert!(contract.get_trades_for_token(accounts(2), "1:1".to_string(), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_refund_is_credited() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(one_near).build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(one_near), accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.delete_offer(accounts(3), Some("1:1".to_string()), None);
+
+        assert_eq!(contract.get_credit_balance(accounts(0)), U128(one_near));
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+        contract.internal_credit_near(accounts(1), 3 * one_near);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.withdraw(U128(one_near));
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(2 * one_near));
+
+        contract.withdraw_all();
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(0));
+
+        // the transfer failed, the amount is credited back
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        assert!(!contract.resolve_withdraw(accounts(1), U128(2 * one_near)));
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(2 * one_near));
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient credit balance")]
+    fn test_withdraw_more_than_balance() {
+        let (mut context, mut contract) = setup_contract();
+        contract.internal_credit_near(accounts(1), 1);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.withdraw(U128(2));
+    }
+
+    #[test]
+    fn test_offer_transaction_fee_snapshot() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(one_near).build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(one_near), accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_transaction_fee(1000, None);
+
+        // the fee raise does not reach the existing offer
+        let offer_data = contract.get_offer(accounts(3), accounts(0), Some("1:1".to_string()), None);
+        assert_eq!(offer_data.transaction_fee, U128(500));
+
+        let payout = near_sdk::serde_json::to_vec(&Payout {
+            payout: vec![(accounts(4), U128(one_near))].into_iter().collect(),
+        })
+        .unwrap();
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(payout)],
+        );
+        let offer_data = contract
+            .internal_delete_offer(accounts(3), accounts(0), "1:1".to_string())
+            .unwrap();
+        contract.resolve_offer(accounts(4), offer_data, "1:1".to_string(), Some(U128(500)));
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(one_near / 20));
+        assert_eq!(contract.get_credit_balance(accounts(4)), U128(one_near - one_near / 20));
+
+        // new offers take the new fee
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(one_near).build());
+        contract.internal_add_offer(accounts(3), Some("1:2".to_string()), None, near_account(), U128(one_near), accounts(0));
+        let offer_data = contract.get_offer(accounts(3), accounts(0), Some("1:2".to_string()), None);
+        assert_eq!(offer_data.transaction_fee, U128(1000));
+    }
+
+    #[test]
+    fn test_fee_policy_precedence_and_promo() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_fee_policy(None, Some(FeeMarketType::Auction), Some(300));
+        contract.set_fee_policy(Some(accounts(3)), None, Some(200));
+        contract.set_fee_policy(Some(accounts(3)), Some(FeeMarketType::Offer), Some(100));
+
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Offer, None), U128(100));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Auction, None), U128(200));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(4), FeeMarketType::Auction, None), U128(300));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(4), FeeMarketType::Sale, None), U128(500));
+
+        // offers snapshot the policy fee
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(10u128.pow(24)).build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(10u128.pow(24)), accounts(0));
+        let offer_data = contract.get_offer(accounts(3), accounts(0), Some("1:1".to_string()), None);
+        assert_eq!(offer_data.transaction_fee, U128(100));
+
+        // the promo only waives fees on its contract while the window is open
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        let now = to_sec(env::block_timestamp());
+        contract.set_fee_promo(Some(FeePromo {
+            start_time: now,
+            end_time: now + 3600,
+            nft_contract_id: Some(accounts(3)),
+        }));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Offer, None), U128(0));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(4), FeeMarketType::Sale, None), U128(500));
+
+        testing_env!(context.block_timestamp(env::block_timestamp() + 3600 * 1_000_000_000).build());
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Offer, None), U128(100));
+    }
+
+    #[test]
+    fn test_volume_discount() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_volume_discounts(vec![
+            VolumeDiscountTier { min_volume: U128(one_near), discount_bps: 2000 },
+            VolumeDiscountTier { min_volume: U128(10 * one_near), discount_bps: 5000 },
+        ]);
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Sale, Some(accounts(4))), U128(500));
+
+        contract.internal_add_seller_volume(&accounts(4), one_near);
+        assert_eq!(contract.get_seller_volume(accounts(4)), U128(one_near));
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Sale, Some(accounts(4))), U128(400));
+
+        contract.internal_add_seller_volume(&accounts(4), 9 * one_near);
+        assert_eq!(contract.get_transaction_fee_preview(accounts(3), FeeMarketType::Sale, Some(accounts(4))), U128(250));
+    }
+
+    #[test]
+    fn test_volume_discount_frozen_for_known_receiver() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_volume_discounts(vec![VolumeDiscountTier { min_volume: U128(one_near), discount_bps: 2000 }]);
+        contract.internal_add_seller_volume(&accounts(4), one_near);
+
+        // the trade names accounts(4) as receiver, the view returns the discounted fee charged at acceptance
+        setup_expiring_trade(&mut contract, &mut context);
+        assert_eq!(contract.get_incoming_trades(accounts(4), None, None)[0].transaction_fee, U128(400));
+
+        // offers do not know their seller, they keep the undiscounted fee
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(one_near).build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(one_near), accounts(1));
+        assert_eq!(contract.get_offers_by_buyer(accounts(1), None, None)[0].transaction_fee, U128(500));
+    }
+
+    #[test]
+    fn test_treasury_fee_splits() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_treasury_fee_splits(vec![
+            FeeShare { account_id: accounts(1), bps: 7000 },
+            FeeShare { account_id: accounts(2), bps: 3000 },
+        ]);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(one_near).build());
+        contract.internal_add_offer(accounts(3), Some("1:1".to_string()), None, near_account(), U128(one_near), accounts(0));
+
+        let payout = near_sdk::serde_json::to_vec(&Payout {
+            payout: vec![(accounts(4), U128(one_near))].into_iter().collect(),
+        })
+        .unwrap();
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(payout)],
+        );
+        let offer_data = contract
+            .internal_delete_offer(accounts(3), accounts(0), "1:1".to_string())
+            .unwrap();
+        contract.resolve_offer(accounts(4), offer_data, "1:1".to_string(), Some(U128(500)));
+
+        let treasury_fee = one_near / 20;
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(treasury_fee * 7 / 10));
+        assert_eq!(contract.get_credit_balance(accounts(2)), U128(treasury_fee * 3 / 10));
+        assert_eq!(contract.get_credit_balance(accounts(4)), U128(one_near - treasury_fee));
+    }
+
+    #[test]
+    #[should_panic(expected = "shares must sum to 10000")]
+    fn test_treasury_fee_splits_invalid_total() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_treasury_fee_splits(vec![
+            FeeShare { account_id: accounts(1), bps: 7000 },
+            FeeShare { account_id: accounts(2), bps: 2000 },
+        ]);
+    }
+
+    #[test]
+    fn test_primary_listing_purchase() {
+        let (mut context, mut contract) = setup_contract();
+        let one_near = 10u128.pow(24);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(STORAGE_ADD_MARKET_DATA)
+            .build());
+        contract.storage_deposit(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .signer_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        contract.nft_on_series_approve(
+            "1|1".to_string(),
+            accounts(3),
+            json!({ "market_type": "primary_sale", "price": U128(one_near) }).to_string(),
+        );
+        let listing = contract.get_primary_listing(accounts(2), "1|1".to_string()).unwrap();
+        assert_eq!(listing.price, U128(one_near));
+        assert_eq!(listing.transaction_fee, U128(500));
+        assert_eq!(contract.get_supply_by_owner_id(accounts(3)), U64(1));
+
+        // royalty_buy share and the creator's share, the fee comes out of the creator's share
+        let payout = near_sdk::serde_json::to_vec(&Payout {
+            payout: vec![(accounts(3), U128(one_near * 9 / 10)), (accounts(4), U128(one_near / 10))]
+                .into_iter()
+                .collect(),
+        })
+        .unwrap();
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(payout)],
+        );
+        let listing = contract.primary_listings.get(&format!("{}{}1|1", accounts(2), DELIMETER)).unwrap();
+        assert!(contract.resolve_primary_purchase(accounts(5), listing, U128(PRIMARY_MINT_STORAGE_DEPOSIT), U128(500)));
+        assert_eq!(contract.get_credit_balance(accounts(3)), U128(one_near * 9 / 10 - one_near / 20));
+        assert_eq!(contract.get_credit_balance(accounts(4)), U128(one_near / 10));
+        assert_eq!(contract.get_credit_balance(accounts(1)), U128(one_near / 20));
+        assert_eq!(contract.get_primary_listing(accounts(2), "1|1".to_string()).unwrap().sold, 1);
+
+        // a failed mint refunds the price and the mint deposit
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        let listing = contract.primary_listings.get(&format!("{}{}1|1", accounts(2), DELIMETER)).unwrap();
+        assert!(!contract.resolve_primary_purchase(accounts(5), listing, U128(PRIMARY_MINT_STORAGE_DEPOSIT), U128(500)));
+        assert_eq!(contract.get_credit_balance(accounts(5)), U128(one_near + PRIMARY_MINT_STORAGE_DEPOSIT));
+    }
+
     #[test]
     fn test_audit_issue_1_extra_attached_near_may_be_locked() {}
 
@@ -28,6 +28,42 @@ pub struct MarketArgs {
     pub buyer_nft_contract_id: Option<AccountId>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub buyer_token_id: Option<TokenId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_sealed_bid: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reveal_ended_at: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sealed_bid_settlement: Option<SealedBidSettlement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve_price: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_now_price: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_increment: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_increment_bps: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension_window: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension_length: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bid_history: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offer_id: Option<U64>, // collection offer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buyer_amount: Option<U128>, // trade top-up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seller_amount: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buyer_tokens: Option<Vec<BundleTokenArgs>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seller_tokens: Option<Vec<BundleTokenArgs>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seller_id: Option<AccountId>, // trade receiver
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<U64>,
 }
 
 trait NonFungibleTokenApprovalsReceiver {
@@ -77,7 +113,25 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             seller_token_id,
             seller_token_series_id,
             buyer_nft_contract_id,
-            buyer_token_id
+            buyer_token_id,
+            is_sealed_bid,
+            reveal_ended_at,
+            sealed_bid_settlement,
+            reserve_price,
+            buy_now_price,
+            min_increment,
+            min_increment_bps,
+            extension_window,
+            extension_length,
+            max_bid_history,
+            offer_id,
+            bundle_id,
+            buyer_amount,
+            seller_amount,
+            buyer_tokens,
+            seller_tokens,
+            seller_id,
+            expires_at,
         } = near_sdk::serde_json::from_str(&msg).expect("Not valid MarketArgs");
 
         // replace old approval id on trade
@@ -124,8 +178,8 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             self.internal_add_market_data(
                 owner_id,
                 approval_id,
-                nft_contract_id,
-                token_id,
+                nft_contract_id.clone(),
+                token_id.clone(),
                 ft_token_id_res,
                 price.unwrap(),
                 started_at,
@@ -133,6 +187,35 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
                 end_price,
                 is_auction,
             );
+
+            if is_sealed_bid == Some(true) {
+                self.internal_add_sealed_auction(
+                    &nft_contract_id,
+                    &token_id,
+                    reveal_ended_at,
+                    sealed_bid_settlement,
+                );
+            }
+
+            let auction_params = AuctionParams {
+                reserve_price,
+                buy_now_price,
+                min_increment,
+                min_increment_bps,
+                extension_window,
+                extension_length,
+                max_bid_history,
+            };
+            if reserve_price.is_some()
+                || buy_now_price.is_some()
+                || min_increment.is_some()
+                || min_increment_bps.is_some()
+                || extension_window.is_some()
+                || extension_length.is_some()
+                || max_bid_history.is_some()
+            {
+                self.internal_add_auction_params(&nft_contract_id, &token_id, auction_params);
+            }
         } else if market_type == "accept_offer" {
             assert!(buyer_id.is_some(), "Account id is not specified");
             assert!(price.is_some(), "Price is not specified (for check)");
@@ -161,6 +244,28 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
                 approval_id,
                 price.unwrap().0,
             );
+        } else if market_type == "accept_collection_offer" {
+            assert!(offer_id.is_some(), "Offer id is not specified");
+            assert!(price.is_some(), "Price is not specified (for check)");
+
+            self.internal_accept_collection_offer(
+                nft_contract_id,
+                offer_id.unwrap(),
+                token_id,
+                owner_id,
+                approval_id,
+                price.unwrap().0,
+            );
+        } else if market_type == "add_bundle_item" {
+            assert!(bundle_id.is_some(), "Bundle id is not specified");
+
+            self.internal_add_bundle_approval(
+                bundle_id.unwrap(),
+                nft_contract_id,
+                token_id,
+                owner_id,
+                approval_id,
+            );
         } else if market_type == "add_trade" {
 
             let storage_amount = self.storage_minimum_balance().0;
@@ -186,6 +291,34 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
                 owner_id,
                 Some(token_id),
                 approval_id,
+                TradeAssets {
+                    buyer_amount,
+                    seller_amount,
+                    ft_token_id,
+                    buyer_tokens,
+                    seller_tokens,
+                },
+                seller_id,
+                expires_at,
+            );
+        } else if market_type == "add_trade_item" {
+            assert!(seller_nft_contract_id.is_some(), "Seller NFT contract id is not specified");
+            assert!(buyer_nft_contract_id.is_some(), "Buyer NFT contract id is not specified");
+            assert!(buyer_token_id.is_some(), "Buyer token id is not specified");
+
+            let buyer_id = buyer_id.unwrap_or_else(|| owner_id.clone());
+            let token = seller_token_id
+                .or(seller_token_series_id)
+                .expect("Seller token id is not specified");
+
+            self.internal_add_trade_item_approval(
+                buyer_id.clone(),
+                make_triple(&buyer_nft_contract_id.unwrap(), &buyer_id, &buyer_token_id.unwrap()),
+                make_triple(&seller_nft_contract_id.unwrap(), &buyer_id, &token),
+                nft_contract_id,
+                token_id,
+                owner_id,
+                approval_id,
             );
         } else if market_type == "accept_trade" {
 
@@ -0,0 +1,328 @@
+use crate::*;
+use crate::nft_callbacks::MarketArgs;
+
+// primary sales of event series (1|n), the token is lazy minted by the nft contract at purchase
+// the listing is created by the nft contract on behalf of the creator through nft_on_series_approve
+
+pub const PRIMARY_MINT_STORAGE_DEPOSIT: u128 = 50_000_000_000_000_000_000_000; // unused part is refunded by the nft contract
+const GAS_FOR_NFT_MINT_PRIMARY: Gas = Gas(60_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrimaryListing {
+    pub owner_id: AccountId, // event creator
+    pub nft_contract_id: AccountId,
+    pub token_series_id: TokenSeriesId,
+    pub ft_token_id: AccountId,
+    pub price: U128,
+    pub sold: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrimaryListingJson {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_series_id: TokenSeriesId,
+    pub ft_token_id: AccountId,
+    pub price: U128,
+    pub sold: u64,
+    pub transaction_fee: U128,
+}
+
+#[ext_contract(ext_primary_mint)]
+trait ExtPrimaryMint {
+    fn nft_mint_primary(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        buyer_id: AccountId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_on_series_approve(&mut self, token_series_id: TokenSeriesId, owner_id: AccountId, msg: String) {
+        let nft_contract_id = env::predecessor_account_id();
+        let signer_id = env::signer_account_id();
+        assert_ne!(
+            env::current_account_id(), nft_contract_id,
+            "nft_on_series_approve should only be called via cross-contract call"
+        );
+        assert_eq!(owner_id, signer_id, "owner_id should be signer_id");
+        assert!(
+            self.approved_nft_contract_ids.contains(&nft_contract_id),
+            "nft_contract_id is not approved"
+        );
+        assert!(token_series_id.starts_with("1|"), "Only event series can be sold as primary");
+
+        let MarketArgs {
+            market_type,
+            price,
+            ft_token_id,
+            ..
+        } = near_sdk::serde_json::from_str(&msg).expect("Not valid MarketArgs");
+        assert_eq!(market_type, "primary_sale", "market_type not valid");
+        let price = price.expect("price not specified");
+        assert!(price.0 < MAX_PRICE, "price higher than {}", MAX_PRICE);
+        let ft_token_id = ft_token_id.unwrap_or_else(near_account);
+        assert_eq!(ft_token_id.to_string(), NEAR, "NEAR support only");
+
+        let key = format!("{}{}{}", nft_contract_id, DELIMETER, token_series_id);
+        let sold = match self.primary_listings.get(&key) {
+            Some(listing) => listing.sold,
+            None => {
+                let storage_amount = self.storage_minimum_balance().0;
+                let owner_paid_storage = self.storage_deposits.get(&signer_id).unwrap_or(0);
+                let signer_storage_required =
+                    (self.get_supply_by_owner_id(signer_id).0 + 1) as u128 * storage_amount;
+
+                if owner_paid_storage < signer_storage_required {
+                    let notif = format!(
+                        "Insufficient storage paid: {}, for {} sales at {} rate of per sale",
+                        owner_paid_storage,
+                        signer_storage_required / storage_amount,
+                        storage_amount
+                    );
+                    env::log_str(&notif);
+                    return;
+                }
+
+                let mut token_ids = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
+                    UnorderedSet::new(
+                        StorageKey::ByOwnerIdInner {
+                            account_id_hash: hash_account_id(&owner_id),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                    )
+                });
+                token_ids.insert(&make_key_owner_by_id_primary(&key));
+                self.by_owner_id.insert(&owner_id, &token_ids);
+                0
+            }
+        };
+
+        // relisting updates the price and takes a new fee snapshot
+        self.internal_snapshot_transaction_fee(
+            &make_key_owner_by_id_primary(&key),
+            &nft_contract_id,
+            FeeMarketType::Sale,
+            Some(&owner_id),
+        );
+        self.primary_listings.insert(
+            &key,
+            &PrimaryListing {
+                owner_id: owner_id.clone(),
+                nft_contract_id: nft_contract_id.clone(),
+                token_series_id: token_series_id.clone(),
+                ft_token_id: ft_token_id.clone(),
+                price,
+                sold,
+            },
+        );
+
+        env::log_str(
+            &json!({
+                "type": "add_primary_listing",
+                "params": {
+                    "owner_id": owner_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_series_id": token_series_id,
+                    "ft_token_id": ft_token_id,
+                    "price": price,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    #[payable]
+    pub fn delete_primary_listing(&mut self, nft_contract_id: AccountId, token_series_id: TokenSeriesId) {
+        assert_one_yocto();
+        let key = format!("{}{}{}", nft_contract_id, DELIMETER, token_series_id);
+        let listing = self.primary_listings.get(&key).expect("Primary listing does not exist");
+        assert!(
+            [listing.owner_id.clone(), self.owner_id.clone()].contains(&env::predecessor_account_id()),
+            "Seller or owner only"
+        );
+
+        self.internal_remove_primary_listing(&key, &listing.owner_id);
+
+        env::log_str(
+            &json!({
+                "type": "delete_primary_listing",
+                "params": {
+                    "owner_id": listing.owner_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_series_id": token_series_id,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    #[payable]
+    pub fn buy_primary(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_series_id: TokenSeriesId,
+        receiver_id: Option<AccountId>,
+    ) -> Promise {
+        let key = format!("{}{}{}", nft_contract_id, DELIMETER, token_series_id);
+        let listing = self.primary_listings.get(&key).expect("Primary listing does not exist");
+        let buyer_id = env::predecessor_account_id();
+        assert_ne!(buyer_id, listing.owner_id, "Cannot buy your own sale");
+
+        let attached_deposit = env::attached_deposit();
+        assert!(
+            attached_deposit >= listing.price.0 + PRIMARY_MINT_STORAGE_DEPOSIT,
+            "attached deposit is less than price + storage : {}",
+            listing.price.0 + PRIMARY_MINT_STORAGE_DEPOSIT
+        );
+        let storage_deposit = attached_deposit - listing.price.0;
+        let transaction_fee = self.get_snapshot_transaction_fee(&make_key_owner_by_id_primary(&key));
+
+        ext_primary_mint::ext(nft_contract_id)
+            .with_attached_deposit(storage_deposit)
+            .with_static_gas(GAS_FOR_NFT_MINT_PRIMARY)
+            .nft_mint_primary(
+                token_series_id,
+                receiver_id.unwrap_or_else(|| buyer_id.clone()),
+                buyer_id.clone(),
+                listing.price,
+                Some(50u32),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ROYALTIES)
+                    .resolve_primary_purchase(buyer_id, listing, U128(storage_deposit), U128(transaction_fee)),
+            )
+    }
+
+    #[private]
+    pub fn resolve_primary_purchase(
+        &mut self,
+        buyer_id: AccountId,
+        listing: PrimaryListing,
+        storage_deposit: U128,
+        transaction_fee: U128,
+    ) -> bool {
+        let result = promise_result_as_success();
+        if result.is_none() {
+            // the mint deposit comes back to the market when the call fails
+            self.internal_credit_near(buyer_id.clone(), listing.price.0 + storage_deposit.0);
+            env::log_str(
+                &json!({
+                    "type": "resolve_primary_purchase_fail",
+                    "params": {
+                        "owner_id": listing.owner_id,
+                        "nft_contract_id": listing.nft_contract_id,
+                        "token_series_id": listing.token_series_id,
+                        "price": listing.price,
+                        "buyer_id": buyer_id,
+                    }
+                })
+                .to_string(),
+            );
+            return false;
+        }
+
+        let payout = parse_payout(&result.unwrap(), listing.price.0).unwrap_or_else(|| {
+            let mut payout = PayoutHashMap::new();
+            payout.insert(listing.owner_id.clone(), listing.price);
+            payout
+        });
+
+        let treasury_fee = listing.price.0 * transaction_fee.0 / (MAX_TREASURY_PERCENTAGE as u128);
+        self.internal_add_seller_volume(&listing.owner_id, listing.price.0);
+        let mut treasury_fee_splits = vec![];
+        for (receiver_id, amount) in payout.iter() {
+            if receiver_id == &listing.owner_id {
+                let amount_after = amount.0.saturating_sub(treasury_fee);
+                if amount_after > 0 {
+                    self.internal_credit_near(receiver_id.clone(), amount_after);
+                }
+                treasury_fee_splits = self.internal_credit_treasury_fee(amount.0.min(treasury_fee));
+            } else {
+                self.internal_credit_near(receiver_id.clone(), amount.0);
+            }
+        }
+
+        let key = format!("{}{}{}", listing.nft_contract_id, DELIMETER, listing.token_series_id);
+        if let Some(mut current) = self.primary_listings.get(&key) {
+            current.sold += 1;
+            self.primary_listings.insert(&key, &current);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "resolve_primary_purchase",
+                "params": {
+                    "owner_id": listing.owner_id,
+                    "nft_contract_id": listing.nft_contract_id,
+                    "token_series_id": listing.token_series_id,
+                    "ft_token_id": listing.ft_token_id,
+                    "price": listing.price,
+                    "buyer_id": buyer_id,
+                    "treasury_fee": U128(treasury_fee),
+                    "treasury_fee_splits": treasury_fee_splits,
+                }
+            })
+            .to_string(),
+        );
+        true
+    }
+
+    pub fn get_primary_listing(&self, nft_contract_id: AccountId, token_series_id: TokenSeriesId) -> Option<PrimaryListingJson> {
+        let key = format!("{}{}{}", nft_contract_id, DELIMETER, token_series_id);
+        self.primary_listings
+            .get(&key)
+            .map(|listing| self.internal_primary_listing_json(&key, listing))
+    }
+
+    pub fn get_primary_listings(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<PrimaryListingJson> {
+        let (start_index, limit) = pagination(from_index, limit, self.primary_listings.len());
+
+        self.primary_listings
+            .iter()
+            .skip(start_index)
+            .take(limit)
+            .map(|(key, listing)| self.internal_primary_listing_json(&key, listing))
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_primary_listing_json(&self, key: &ContractAndTokenId, listing: PrimaryListing) -> PrimaryListingJson {
+        PrimaryListingJson {
+            owner_id: listing.owner_id,
+            nft_contract_id: listing.nft_contract_id,
+            token_series_id: listing.token_series_id,
+            ft_token_id: listing.ft_token_id,
+            price: listing.price,
+            sold: listing.sold,
+            transaction_fee: U128(self.get_snapshot_transaction_fee(&make_key_owner_by_id_primary(key))),
+        }
+    }
+
+    fn internal_remove_primary_listing(&mut self, key: &ContractAndTokenId, owner_id: &AccountId) {
+        self.primary_listings.remove(key);
+        self.transaction_fee_snapshots.remove(&make_key_owner_by_id_primary(key));
+
+        if let Some(mut by_owner_id) = self.by_owner_id.get(owner_id) {
+            by_owner_id.remove(&make_key_owner_by_id_primary(key));
+            if by_owner_id.is_empty() {
+                self.by_owner_id.remove(owner_id);
+            } else {
+                self.by_owner_id.insert(owner_id, &by_owner_id);
+            }
+        }
+    }
+}
+
+fn make_key_owner_by_id_primary(contract_and_series_id: &str) -> String {
+    format!("{}{}primary", contract_and_series_id, DELIMETER)
+}
@@ -0,0 +1,397 @@
+use crate::*;
+
+// sealed-bid (commit-reveal) auctions
+
+pub const MAX_SEALED_BIDS: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum SealedBidSettlement {
+    FirstPrice,
+    SecondPrice,
+}
+
+// what happens with the deposit of a bid that was never revealed
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum UnrevealedBidPolicy {
+    Refund,
+    Seller,
+    Treasury,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SealedBid {
+    pub bidder_id: AccountId,
+    pub commitment: String, // hex sha256 of "bidder_id||amount||salt"
+    pub deposit: U128,
+    pub revealed_price: Option<U128>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SealedAuction {
+    pub reveal_ended_at: u64,
+    pub settlement: SealedBidSettlement,
+    pub bids: Vec<SealedBid>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SealedAuctionJson {
+    pub reveal_ended_at: U64,
+    pub settlement: SealedBidSettlement,
+    pub bids: Vec<SealedBid>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_unrevealed_bid_policy(&mut self, policy: UnrevealedBidPolicy) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.unrevealed_bid_policy = policy;
+    }
+
+    pub fn get_unrevealed_bid_policy(&self) -> UnrevealedBidPolicy {
+        self.unrevealed_bid_policy
+    }
+
+    // bidding window: started_at..=ended_at, attached deposit must cover the hidden amount
+    #[payable]
+    pub fn commit_sealed_bid(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        commitment: String,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
+        let market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Token id does not exist");
+        let mut sealed_auction = self
+            .sealed_auctions
+            .get(&contract_and_token_id)
+            .expect("Not a sealed-bid auction");
+
+        let bidder_id = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
+        let deposit = env::attached_deposit();
+
+        assert!(
+            current_time >= market_data.started_at.unwrap(),
+            "Sale has not started yet"
+        );
+        assert!(
+            current_time <= market_data.ended_at.unwrap(),
+            "Bidding has ended"
+        );
+        assert_ne!(market_data.owner_id, bidder_id, "Owner cannot bid their own token");
+        assert!(
+            commitment.len() == 64 && commitment.chars().all(|c| c.is_ascii_hexdigit()),
+            "Commitment must be a hex encoded sha256 hash"
+        );
+        assert!(
+            deposit >= market_data.price,
+            "Can't deposit less than starting price: {:?}",
+            U128(market_data.price)
+        );
+
+        if let Some(sealed_bid) = sealed_auction
+            .bids
+            .iter_mut()
+            .find(|sealed_bid| sealed_bid.bidder_id == bidder_id)
+        {
+            // replacing a commitment refunds the previous deposit
+            self.internal_credit_near(bidder_id.clone(), sealed_bid.deposit.0);
+            sealed_bid.commitment = commitment.to_lowercase();
+            sealed_bid.deposit = U128(deposit);
+        } else {
+            assert!(
+                sealed_auction.bids.len() < MAX_SEALED_BIDS,
+                "Maximum number of sealed bids reached"
+            );
+            sealed_auction.bids.push(SealedBid {
+                bidder_id: bidder_id.clone(),
+                commitment: commitment.to_lowercase(),
+                deposit: U128(deposit),
+                revealed_price: None,
+            });
+        }
+
+        self.sealed_auctions.insert(&contract_and_token_id, &sealed_auction);
+
+        env::log_str(
+            &json!({
+                "type": "commit_sealed_bid",
+                "params": {
+                    "bidder_id": bidder_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "commitment": commitment.to_lowercase(),
+                    "deposit": U128(deposit),
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // reveal window: after ended_at up to reveal_ended_at
+    pub fn reveal_sealed_bid(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        salt: String,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
+        let market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Token id does not exist");
+        let mut sealed_auction = self
+            .sealed_auctions
+            .get(&contract_and_token_id)
+            .expect("Not a sealed-bid auction");
+
+        let bidder_id = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
+
+        assert!(
+            current_time > market_data.ended_at.unwrap(),
+            "Bidding has not ended yet"
+        );
+        assert!(
+            current_time <= sealed_auction.reveal_ended_at,
+            "Reveal has ended"
+        );
+
+        let sealed_bid = sealed_auction
+            .bids
+            .iter_mut()
+            .find(|sealed_bid| sealed_bid.bidder_id == bidder_id)
+            .expect("Sealed bid does not exist");
+
+        assert!(sealed_bid.revealed_price.is_none(), "Sealed bid already revealed");
+        assert_eq!(
+            sealed_bid.commitment,
+            sealed_bid_commitment(&bidder_id, amount, &salt),
+            "Commitment does not match"
+        );
+        assert!(
+            amount.0 >= market_data.price,
+            "Can't pay less than starting price: {:?}",
+            U128(market_data.price)
+        );
+        assert!(
+            amount.0 <= sealed_bid.deposit.0,
+            "Amount is higher than deposit"
+        );
+
+        sealed_bid.revealed_price = Some(amount);
+        self.sealed_auctions.insert(&contract_and_token_id, &sealed_auction);
+
+        env::log_str(
+            &json!({
+                "type": "reveal_sealed_bid",
+                "params": {
+                    "bidder_id": bidder_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "amount": amount,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // anyone can settle once the reveal window is over
+    pub fn settle_sealed_auction(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
+        let market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Token id does not exist");
+        let sealed_auction = self
+            .sealed_auctions
+            .get(&contract_and_token_id)
+            .expect("Not a sealed-bid auction");
+
+        assert!(
+            env::block_timestamp() > sealed_auction.reveal_ended_at,
+            "Reveal has not ended yet"
+        );
+
+        self.sealed_auctions.remove(&contract_and_token_id);
+
+        // highest revealed amount wins, earliest commitment breaks ties
+        let mut winner: Option<usize> = None;
+        let mut second_price = market_data.price;
+        for (index, sealed_bid) in sealed_auction.bids.iter().enumerate() {
+            if let Some(revealed_price) = sealed_bid.revealed_price {
+                match winner {
+                    Some(winner_index) => {
+                        let winner_price = sealed_auction.bids[winner_index].revealed_price.unwrap().0;
+                        if revealed_price.0 > winner_price {
+                            second_price = winner_price;
+                            winner = Some(index);
+                        } else if revealed_price.0 > second_price {
+                            second_price = revealed_price.0;
+                        }
+                    }
+                    None => winner = Some(index),
+                }
+            }
+        }
+
+        let price = winner.map(|winner_index| {
+            match sealed_auction.settlement {
+                SealedBidSettlement::FirstPrice => sealed_auction.bids[winner_index].revealed_price.unwrap().0,
+                SealedBidSettlement::SecondPrice => second_price,
+            }
+        });
+
+        for (index, sealed_bid) in sealed_auction.bids.iter().enumerate() {
+            if Some(index) == winner {
+                let refund = sealed_bid.deposit.0 - price.unwrap();
+                if refund > 0 {
+                    self.internal_credit_near(sealed_bid.bidder_id.clone(), refund);
+                }
+            } else if sealed_bid.revealed_price.is_some() {
+                self.internal_credit_near(sealed_bid.bidder_id.clone(), sealed_bid.deposit.0);
+            } else {
+                let receiver_id = match self.unrevealed_bid_policy {
+                    UnrevealedBidPolicy::Refund => sealed_bid.bidder_id.clone(),
+                    UnrevealedBidPolicy::Seller => market_data.owner_id.clone(),
+                    UnrevealedBidPolicy::Treasury => self.treasury_id.clone(),
+                };
+                self.internal_credit_near(receiver_id, sealed_bid.deposit.0);
+            }
+        }
+
+        if let Some(winner_index) = winner {
+            let winner_id = sealed_auction.bids[winner_index].bidder_id.clone();
+
+            env::log_str(
+                &json!({
+                    "type": "settle_sealed_auction",
+                    "params": {
+                        "bidder_id": winner_id,
+                        "nft_contract_id": nft_contract_id,
+                        "token_id": token_id,
+                        "settlement": sealed_auction.settlement,
+                        "price": U128(price.unwrap()),
+                        "unrevealed_bid_policy": self.unrevealed_bid_policy,
+                    }
+                })
+                .to_string(),
+            );
+
+            self.internal_process_purchase(nft_contract_id, token_id, winner_id, price.unwrap());
+        } else {
+            self.internal_delete_market_data(&nft_contract_id, &token_id);
+
+            env::log_str(
+                &json!({
+                    "type": "delete_market_data",
+                    "params": {
+                        "owner_id": market_data.owner_id,
+                        "nft_contract_id": nft_contract_id,
+                        "token_id": token_id,
+                    }
+                })
+                .to_string(),
+            );
+        }
+    }
+
+    pub fn get_sealed_auction(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Option<SealedAuctionJson> {
+        let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
+        self.sealed_auctions
+            .get(&contract_and_token_id)
+            .map(|sealed_auction| SealedAuctionJson {
+                reveal_ended_at: sealed_auction.reveal_ended_at.into(),
+                settlement: sealed_auction.settlement,
+                bids: sealed_auction.bids,
+            })
+    }
+
+    // helper for clients, same hash that reveal_sealed_bid checks
+    pub fn get_sealed_bid_commitment(&self, bidder_id: AccountId, amount: U128, salt: String) -> String {
+        sealed_bid_commitment(&bidder_id, amount, &salt)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_add_sealed_auction(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        reveal_ended_at: Option<U64>,
+        settlement: Option<SealedBidSettlement>,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let market_data = self
+            .market
+            .get(&contract_and_token_id)
+            .expect("Token id does not exist");
+
+        assert_eq!(market_data.is_auction, Some(true), "Sealed-bid auction must be an auction");
+
+        let reveal_ended_at = reveal_ended_at.expect("Reveal ended at is none").0;
+        assert!(
+            reveal_ended_at > market_data.ended_at.unwrap(),
+            "reveal_ended_at must be after ended_at"
+        );
+        let settlement = settlement.unwrap_or(SealedBidSettlement::FirstPrice);
+
+        self.sealed_auctions.insert(
+            &contract_and_token_id,
+            &SealedAuction {
+                reveal_ended_at,
+                settlement,
+                bids: Vec::new(),
+            },
+        );
+
+        env::log_str(
+            &json!({
+                "type": "add_sealed_auction",
+                "params": {
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "reveal_ended_at": U64(reveal_ended_at),
+                    "settlement": settlement,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // refund every committed deposit, used when the listing disappears before settlement
+    pub(crate) fn internal_remove_sealed_auction(&mut self, contract_and_token_id: &ContractAndTokenId) {
+        if let Some(sealed_auction) = self.sealed_auctions.remove(contract_and_token_id) {
+            for sealed_bid in sealed_auction.bids {
+                self.internal_credit_near(sealed_bid.bidder_id, sealed_bid.deposit.0);
+            }
+        }
+    }
+
+    pub(crate) fn internal_is_sealed_auction(&self, contract_and_token_id: &ContractAndTokenId) -> bool {
+        self.sealed_auctions.get(contract_and_token_id).is_some()
+    }
+}
+
+pub fn sealed_bid_commitment(bidder_id: &AccountId, amount: U128, salt: &str) -> String {
+    env::sha256(format!("{}{}{}{}{}", bidder_id, DELIMETER, amount.0, DELIMETER, salt).as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
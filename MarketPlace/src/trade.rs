@@ -0,0 +1,603 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+// multi-asset trades, extra tokens on both sides and a NEAR top-up
+// the buyer's top-up is taken from the credit balance and escrowed when the trade is proposed,
+// the seller's one when the trade is accepted
+
+pub const MAX_TRADE_TOKENS: usize = 4; // both sides, main tokens included
+const GAS_FOR_RESOLVE_REJECT_TRADE: Gas = Gas(BASE_GAS.0 * 2);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TradeItem {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: Option<u64>, // set from nft_on_approve
+    pub owner_id: Option<AccountId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TradeItems {
+    pub buyer_items: Vec<TradeItem>,
+    pub seller_items: Vec<TradeItem>,
+}
+
+impl TradeItems {
+    pub fn is_active(&self) -> bool {
+        self.buyer_items
+            .iter()
+            .chain(self.seller_items.iter())
+            .all(|item| item.approval_id.is_some())
+    }
+}
+
+#[derive(Default)]
+pub struct TradeAssets {
+    pub buyer_amount: Option<U128>,
+    pub seller_amount: Option<U128>,
+    pub ft_token_id: Option<AccountId>,
+    pub buyer_tokens: Option<Vec<BundleTokenArgs>>,
+    pub seller_tokens: Option<Vec<BundleTokenArgs>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TradeSettlement {
+    pub buyer_id: AccountId,
+    pub seller_id: AccountId,
+    pub buyer_tokens: Vec<TradeItem>, // main token first
+    pub seller_tokens: Vec<TradeItem>,
+    pub buyer_amount: Option<U128>,
+    pub seller_amount: Option<U128>,
+    pub transaction_fee: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    // trade deposits share the credit ledger, unused funds leave through withdraw
+    #[payable]
+    pub fn trade_deposit(&mut self, account_id: Option<AccountId>) -> U128 {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let deposit = env::attached_deposit();
+        assert!(deposit > 0, "Requires attached deposit");
+
+        self.internal_credit_near(account_id.clone(), deposit);
+        self.get_credit_balance(account_id)
+    }
+
+    #[payable]
+    pub fn trade_withdraw(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = amount
+            .map(|amount| amount.0)
+            .unwrap_or_else(|| self.credits.get(&account_id).unwrap_or(0));
+        self.internal_withdraw(account_id, amount)
+    }
+
+    pub fn trade_balance_of(&self, account_id: AccountId) -> U128 {
+        self.get_credit_balance(account_id)
+    }
+
+    pub fn get_trade_items(
+        &self,
+        seller_nft_contract_id: AccountId,
+        seller_token_id: Option<TokenId>,
+        seller_token_series_id: Option<String>,
+        buyer_id: AccountId,
+        buyer_nft_contract_id: AccountId,
+        buyer_token_id: TokenId,
+    ) -> Option<TradeItems> {
+        let token = seller_token_id
+            .or(seller_token_series_id)
+            .expect("Seller token id is not specified");
+
+        self.trade_items.get(&trade_key(
+            &make_triple(&buyer_nft_contract_id, &buyer_id, &buyer_token_id),
+            &make_triple(&seller_nft_contract_id, &buyer_id, &token),
+        ))
+    }
+
+    // the receiving owner turns down a trade proposed for their token
+    #[payable]
+    pub fn reject_trade(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        buyer_id: AccountId,
+        buyer_nft_contract_id: AccountId,
+        buyer_token_id: TokenId,
+    ) -> PromiseOrValue<bool> {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let buyer_contract_account_id_token_id = make_triple(&buyer_nft_contract_id, &buyer_id, &buyer_token_id);
+        let contract_account_id_token_id = make_triple(&nft_contract_id, &buyer_id, &token_id);
+
+        self.trades
+            .get(&buyer_contract_account_id_token_id)
+            .and_then(|trade_list| trade_list.trade_data.get(&contract_account_id_token_id).cloned())
+            .expect("Trade data does not exist");
+
+        let key = trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id);
+        if self.trade_receivers.get(&key).as_ref() == Some(&owner_id) {
+            self.internal_reject_trade(buyer_id, buyer_contract_account_id_token_id, contract_account_id_token_id, owner_id);
+            return PromiseOrValue::Value(true);
+        }
+
+        // otherwise the ownership is checked on the NFT contract
+        PromiseOrValue::Promise(
+            ext_contract_transfer::ext(nft_contract_id)
+                .with_static_gas(GAS_FOR_NFT_TOKEN)
+                .nft_token(token_id)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_REJECT_TRADE)
+                        .resolve_reject_trade(
+                            buyer_id,
+                            buyer_contract_account_id_token_id,
+                            contract_account_id_token_id,
+                            owner_id,
+                        ),
+                ),
+        )
+    }
+
+    #[private]
+    pub fn resolve_reject_trade(
+        &mut self,
+        buyer_id: AccountId,
+        buyer_contract_account_id_token_id: ContractAccountIdTokenId,
+        contract_account_id_token_id: ContractAccountIdTokenId,
+        owner_id: AccountId,
+    ) -> bool {
+        let token: Option<MatchToken> = promise_result_as_success()
+            .and_then(|value| near_sdk::serde_json::from_slice::<Option<MatchToken>>(&value).ok())
+            .flatten();
+
+        if token.is_none_or(|token| token.owner_id != owner_id) {
+            env::log_str("Token owner only");
+            return false;
+        }
+
+        let exists = self
+            .trades
+            .get(&buyer_contract_account_id_token_id)
+            .is_some_and(|trade_list| trade_list.trade_data.contains_key(&contract_account_id_token_id));
+        if !exists {
+            env::log_str("Trade data does not exist");
+            return false;
+        }
+
+        self.internal_reject_trade(buyer_id, buyer_contract_account_id_token_id, contract_account_id_token_id, owner_id);
+        true
+    }
+
+    // permissionless, releases expired trades within expiring_trades[from_index..from_index + limit]
+    pub fn cleanup_expired_trades(&mut self, from_index: Option<U128>, limit: Option<u64>) -> U64 {
+        let (start_index, limit) = pagination(from_index, limit, self.expiring_trades.len());
+
+        let expired_trades: Vec<(ContractAccountIdTokenId, ContractAccountIdTokenId)> = self
+            .expiring_trades
+            .iter()
+            .skip(start_index)
+            .take(limit)
+            .filter(|(buyer_contract_account_id_token_id, contract_account_id_token_id)| {
+                self.internal_trade_expired(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id))
+            })
+            .collect();
+
+        for (buyer_contract_account_id_token_id, contract_account_id_token_id) in expired_trades.iter() {
+            let buyer_id = triple_account_id(buyer_contract_account_id_token_id);
+            let trade_data = self
+                .internal_delete_trade(
+                    buyer_id.clone(),
+                    buyer_contract_account_id_token_id.clone(),
+                    contract_account_id_token_id.clone(),
+                )
+                .unwrap();
+            self.internal_release_trade(buyer_contract_account_id_token_id, contract_account_id_token_id, &trade_data);
+
+            env::log_str(
+                &json!({
+                    "type": "delete_trade",
+                    "params": {
+                        "nft_contract_id": trade_data.nft_contract_id,
+                        "buyer_id": buyer_id,
+                        "token_id": trade_data.token_id,
+                        "token_series_id": trade_data.token_series_id,
+                        "expired": true,
+                    }
+                })
+                .to_string(),
+            );
+        }
+
+        U64(expired_trades.len() as u64)
+    }
+
+    // trades proposed to the account, as named by the proposer or as seller of the listed token
+    pub fn get_incoming_trades(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TradeDataJson> {
+        self.trades_by_receiver
+            .get(&account_id)
+            .map(|trades| {
+                let (start_index, limit) = pagination(from_index, limit, trades.len());
+                trades
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|(buyer_contract_account_id_token_id, contract_account_id_token_id)| {
+                        self.internal_trade_data_json(&buyer_contract_account_id_token_id, &contract_account_id_token_id)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_outgoing_trades(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TradeDataJson> {
+        self.trades_by_proposer
+            .get(&account_id)
+            .map(|trades| {
+                let (start_index, limit) = pagination(from_index, limit, trades.len());
+                trades
+                    .iter()
+                    .skip(start_index)
+                    .take(limit)
+                    .map(|(buyer_contract_account_id_token_id, contract_account_id_token_id)| {
+                        self.internal_trade_data_json(&buyer_contract_account_id_token_id, &contract_account_id_token_id)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[private]
+    pub fn callback_first_trade(&mut self, settlement: TradeSettlement) -> PromiseOrValue<bool> {
+        let results = trade_promise_results();
+        if results.iter().any(|success| !success) {
+            self.internal_rollback_trade(&settlement, &results, &[], "buyer's nft failed to trade");
+            return PromiseOrValue::Value(false);
+        }
+
+        let (_, callback_second_gas) = trade_callback_gas(&settlement);
+        PromiseOrValue::Promise(trade_escrow_tokens(&settlement.seller_tokens).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(callback_second_gas)
+                .callback_second_trade(settlement),
+        ))
+    }
+
+    #[private]
+    pub fn callback_second_trade(&mut self, settlement: TradeSettlement) -> bool {
+        let results = trade_promise_results();
+        if results.iter().any(|success| !success) {
+            let buyer_results = vec![true; settlement.buyer_tokens.len()];
+            self.internal_rollback_trade(
+                &settlement,
+                &buyer_results,
+                &results,
+                "seller's nft failed to trade, rollback buyer's nft",
+            );
+            return false;
+        }
+
+        self.internal_swap_nft(settlement);
+        true
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_trade_expired(&self, trade_key: &str) -> bool {
+        self.trade_expires_at
+            .get(&trade_key.to_string())
+            .is_some_and(|expires_at| env::block_timestamp() >= expires_at)
+    }
+
+    fn internal_reject_trade(
+        &mut self,
+        buyer_id: AccountId,
+        buyer_contract_account_id_token_id: ContractAccountIdTokenId,
+        contract_account_id_token_id: ContractAccountIdTokenId,
+        owner_id: AccountId,
+    ) {
+        let trade_data = self
+            .internal_delete_trade(
+                buyer_id.clone(),
+                buyer_contract_account_id_token_id.clone(),
+                contract_account_id_token_id.clone(),
+            )
+            .unwrap();
+        self.internal_release_trade(&buyer_contract_account_id_token_id, &contract_account_id_token_id, &trade_data);
+
+        env::log_str(
+            &json!({
+                "type": "reject_trade",
+                "params": {
+                    "owner_id": owner_id,
+                    "buyer_id": buyer_id,
+                    "nft_contract_id": trade_data.nft_contract_id,
+                    "token_id": trade_data.token_id,
+                    "token_series_id": trade_data.token_series_id,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub(crate) fn internal_add_trade_items(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        buyer_tokens: Option<Vec<BundleTokenArgs>>,
+        seller_tokens: Option<Vec<BundleTokenArgs>>,
+    ) -> bool {
+        let trade_items = TradeItems {
+            buyer_items: self.internal_trade_items_from_args(buyer_tokens.unwrap_or_default()),
+            seller_items: self.internal_trade_items_from_args(seller_tokens.unwrap_or_default()),
+        };
+        assert!(
+            trade_items.buyer_items.len() + trade_items.seller_items.len() + 2 <= MAX_TRADE_TOKENS,
+            "Trade can have at most {} tokens",
+            MAX_TRADE_TOKENS
+        );
+
+        if trade_items.buyer_items.is_empty() && trade_items.seller_items.is_empty() {
+            return true;
+        }
+
+        self.trade_items.insert(
+            &trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id),
+            &trade_items,
+        );
+        false
+    }
+
+    fn internal_trade_items_from_args(&self, tokens: Vec<BundleTokenArgs>) -> Vec<TradeItem> {
+        let mut items: Vec<TradeItem> = Vec::new();
+        for token in tokens {
+            assert!(
+                self.approved_nft_contract_ids.contains(&token.nft_contract_id),
+                "nft_contract_id is not approved"
+            );
+            assert!(
+                !items.iter().any(|item| {
+                    item.nft_contract_id == token.nft_contract_id && item.token_id == token.token_id
+                }),
+                "Duplicated token in trade"
+            );
+            items.push(TradeItem {
+                nft_contract_id: token.nft_contract_id,
+                token_id: token.token_id,
+                approval_id: None,
+                owner_id: None,
+            });
+        }
+        items
+    }
+
+    pub(crate) fn internal_add_trade_item_approval(
+        &mut self,
+        buyer_id: AccountId,
+        buyer_contract_account_id_token_id: ContractAccountIdTokenId,
+        contract_account_id_token_id: ContractAccountIdTokenId,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) {
+        let key = trade_key(&buyer_contract_account_id_token_id, &contract_account_id_token_id);
+        let mut trade_items = self.trade_items.get(&key).expect("Trade items do not exist");
+
+        let items = if owner_id == buyer_id {
+            &mut trade_items.buyer_items
+        } else {
+            &mut trade_items.seller_items
+        };
+        let item = items
+            .iter_mut()
+            .find(|item| item.nft_contract_id == nft_contract_id && item.token_id == token_id)
+            .expect("Token is not in trade");
+        item.approval_id = Some(approval_id);
+        item.owner_id = Some(owner_id.clone());
+
+        let is_active = trade_items.is_active();
+        self.trade_items.insert(&key, &trade_items);
+
+        let mut trade_list = self
+            .trades
+            .get(&buyer_contract_account_id_token_id)
+            .expect("Trade list does not exist");
+        trade_list
+            .trade_data
+            .get_mut(&contract_account_id_token_id)
+            .expect("Trade data does not exist")
+            .is_active = Some(is_active);
+        self.trades.insert(&buyer_contract_account_id_token_id, &trade_list);
+
+        env::log_str(
+            &json!({
+                "type": "add_trade_item",
+                "params": {
+                    "buyer_id": buyer_id,
+                    "owner_id": owner_id,
+                    "nft_contract_id": nft_contract_id,
+                    "token_id": token_id,
+                    "approval_id": approval_id,
+                    "is_active": is_active,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub(crate) fn internal_escrow_trade_amount(&mut self, account_id: &AccountId, amount: Option<U128>) -> Option<Balance> {
+        let amount = amount.map(|amount| amount.0).filter(|amount| *amount > 0)?;
+        let balance = self.credits.get(account_id).unwrap_or(0);
+        assert!(
+            balance >= amount,
+            "Insufficient trade deposit: {}, required {}",
+            balance,
+            amount
+        );
+
+        if balance == amount {
+            self.credits.remove(account_id);
+        } else {
+            self.credits.insert(account_id, &(balance - amount));
+        }
+        Some(amount)
+    }
+
+    // trade is gone without being settled, the buyer gets the escrowed amount back
+    pub(crate) fn internal_release_trade(
+        &mut self,
+        buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+        contract_account_id_token_id: &ContractAccountIdTokenId,
+        trade_data: &TradeData,
+    ) {
+        self.trade_items
+            .remove(&trade_key(buyer_contract_account_id_token_id, contract_account_id_token_id));
+
+        if let Some(buyer_amount) = trade_data.buyer_amount.filter(|amount| *amount > 0) {
+            self.internal_credit_near(triple_account_id(buyer_contract_account_id_token_id), buyer_amount);
+        }
+    }
+
+    pub(crate) fn internal_settle_trade(
+        &mut self,
+        buyer_id: AccountId,
+        buyer_token: TradeItem,
+        seller_id: AccountId,
+        seller_token: TradeItem,
+        trade_data: TradeData,
+        trade_items: TradeItems,
+        transaction_fee: u128,
+    ) -> Promise {
+        assert!(
+            trade_data.is_active != Some(false),
+            "Trade is not active, some tokens are not approved"
+        );
+        assert!(
+            trade_items
+                .seller_items
+                .iter()
+                .all(|item| item.owner_id.as_ref() == Some(&seller_id)),
+            "Trade tokens must be approved by the seller"
+        );
+
+        let seller_amount = self.internal_escrow_trade_amount(&seller_id, trade_data.seller_amount.map(U128));
+
+        let mut buyer_tokens = vec![buyer_token];
+        buyer_tokens.extend(trade_items.buyer_items);
+        let mut seller_tokens = vec![seller_token];
+        seller_tokens.extend(trade_items.seller_items);
+
+        self.trade_swap_nft(TradeSettlement {
+            buyer_id,
+            seller_id,
+            buyer_tokens,
+            seller_tokens,
+            buyer_amount: trade_data.buyer_amount.map(U128),
+            seller_amount: seller_amount.map(U128),
+            transaction_fee: U128(transaction_fee),
+        })
+    }
+
+    fn internal_rollback_trade(
+        &mut self,
+        settlement: &TradeSettlement,
+        buyer_results: &[bool],
+        seller_results: &[bool],
+        reason: &str,
+    ) {
+        for (item, _) in settlement.buyer_tokens.iter().zip(buyer_results).filter(|(_, success)| **success) {
+            ext_contract_transfer::ext(item.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(settlement.buyer_id.clone(), item.token_id.clone(), None, None);
+        }
+        for (item, _) in settlement.seller_tokens.iter().zip(seller_results).filter(|(_, success)| **success) {
+            ext_contract_transfer::ext(item.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(settlement.seller_id.clone(), item.token_id.clone(), None, None);
+        }
+
+        if let Some(buyer_amount) = settlement.buyer_amount {
+            self.internal_credit_near(settlement.buyer_id.clone(), buyer_amount.0);
+        }
+        if let Some(seller_amount) = settlement.seller_amount {
+            self.internal_credit_near(settlement.seller_id.clone(), seller_amount.0);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "accept_trade_fail",
+                "params": {
+                    "sender_id": settlement.seller_id,
+                    "buyer_id": settlement.buyer_id,
+                    "reason": reason,
+                }
+            })
+            .to_string(),
+        );
+    }
+}
+
+// main token first, then the extra ones, all joined so a single callback sees every result
+pub(crate) fn trade_escrow_tokens(tokens: &[TradeItem]) -> Promise {
+    tokens
+        .iter()
+        .map(|item| {
+            ext_contract_transfer::ext(item.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(env::current_account_id(), item.token_id.clone(), item.approval_id, None)
+        })
+        .reduce(|transfers, transfer| transfers.and(transfer))
+        .expect("Trade has no tokens")
+}
+
+// gas for both callbacks, the first one also prepays the second
+pub(crate) fn trade_callback_gas(settlement: &TradeSettlement) -> (Gas, Gas) {
+    let token_count = (settlement.buyer_tokens.len() + settlement.seller_tokens.len()) as u64;
+    let callback_second_gas =
+        Gas(GAS_FOR_CALLBACK_SECOND_TRADE.0 + GAS_FOR_NFT_TRANSFER.0 * token_count.saturating_sub(2));
+    let callback_first_gas = Gas(
+        GAS_FOR_CALLBACK_FIRST_TRADE.0
+            + GAS_FOR_NFT_TRANSFER.0 * (settlement.seller_tokens.len() as u64).saturating_sub(1)
+            + callback_second_gas.0,
+    );
+    (callback_first_gas, callback_second_gas)
+}
+
+fn trade_promise_results() -> Vec<bool> {
+    (0..env::promise_results_count())
+        .map(|index| matches!(env::promise_result(index), PromiseResult::Successful(_)))
+        .collect()
+}
+
+pub(crate) fn trade_key(
+    buyer_contract_account_id_token_id: &ContractAccountIdTokenId,
+    contract_account_id_token_id: &ContractAccountIdTokenId,
+) -> String {
+    format!(
+        "{}{}{}",
+        buyer_contract_account_id_token_id, DELIMETER, contract_account_id_token_id
+    )
+}
+
+pub(crate) fn triple_account_id(contract_account_id_token_id: &ContractAccountIdTokenId) -> AccountId {
+    AccountId::new_unchecked(contract_account_id_token_id.split(DELIMETER).nth(1).unwrap().to_string())
+}
@@ -30,17 +30,18 @@ use near_contract_standards::non_fungible_token::NonFungibleToken;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::{
     env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, Promise,
-    Balance, serde_json::json, assert_one_yocto, Gas, ext_contract, PromiseOrValue,
+    Balance, serde_json::json, assert_one_yocto, Gas, ext_contract, PromiseOrValue, PromiseResult,
 };
 
-use near_sdk::collections::{LazyOption, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LazyOption, LookupMap, TreeMap, UnorderedMap, UnorderedSet};
 
 /* custon codigo */
-use near_sdk::json_types::{/*ValidAccountId,*/ U128, U64};
+use near_sdk::json_types::{/*ValidAccountId,*/ Base64VecU8, U128, U64};
 
 use serde::Serialize;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::ops::Bound;
 use near_sdk::env::is_valid_account_id;
 pub mod event;
 pub use event::NearEvent;
@@ -55,9 +56,22 @@ pub const TITLE_DELIMETER: &str = " #";
 //const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(40_000_000_000_000); //GAS_FOR_NFT_TRANSFER_CALL(30_000_000_000_000) + GAS_FOR_RESOLVE_TRANSFER;
 //const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
 //const GAS_FOR_MINT: Gas = Gas(90_000_000_000_000);
+const GAS_FOR_SERIES_MARKET_APPROVE: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_RESOLVE_SERIES_MARKET_APPROVE: Gas = Gas(10_000_000_000_000);
 //const NO_DEPOSIT: Balance = 0;
 //const MAX_PRICE: Balance = 1_000_000_000 * 10u128.pow(24);
 const CURRENT_TRANSACTION_FEE: Balance = 200;
+const DEFAULT_ENTRY_OBJECT_DESCRIPTION: &str = "This is the let me in of the event";
+const DEFAULT_ENTRY_OBJECT_MEDIA: &str = "https://mintickt.mypinata.cloud/ipfs/QmdW7LfjTfHWmpRadqk2o5oUUFutPuqUx2dZj3C4CH2Jjr";
+const MAX_VENUE_NAME_LEN: usize = 128;
+const MAX_TIMEZONE_LEN: usize = 64;
+const MAX_CATEGORY_LEN: usize = 32;
+const MAX_EVENT_TAGS: usize = 10;
+const MAX_TAG_LEN: usize = 32;
+const MAX_AFFILIATE_CODE_LEN: usize = 32;
+const WAITLIST_MINT_DEPOSIT: u128 = 50_000_000_000_000_000_000_000; // mismo margen de storage que nft_buy
+const MAX_WAITLIST_ALLOCATIONS: usize = 10; // por llamada, para acotar el gas
+const MAX_PROMO_DISCOUNT_BPS: u32 = 9_000; // siempre queda un precio minimo para el fee de vault
 
 pub type TokenSeriesId = String;
 
@@ -82,6 +96,13 @@ trait NonFungibleTokenReceiver {
     ) -> Promise;
 }
 
+// marketplace que vende el evento (1|n) como venta primaria
+#[allow(dead_code)]
+#[ext_contract(ext_primary_market)]
+trait PrimaryMarket {
+    fn nft_on_series_approve(&mut self, token_series_id: TokenSeriesId, owner_id: AccountId, msg: String);
+}
+
 #[ext_contract(ext_self)]
 trait NonFungibleTokenResolverExt {
     fn nft_resolve_transfer(
@@ -104,7 +125,108 @@ pub struct TokenSeries {
     price: Option<f64>,
     is_mintable: bool,
     royalty: HashMap<AccountId, u32>,
-    royalty_buy: HashMap<AccountId, u32>
+    royalty_buy: HashMap<AccountId, u32>,
+    event_info: Option<EventInfo>,
+}
+
+// layout anterior a event_info, solo se usa en migrate
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenSeriesV1 {
+    metadata: TokenMetadata,
+    creator_id: AccountId,
+    tokens: UnorderedSet<TokenId>,
+    objects_mint: UnorderedSet<String>,
+    price: Option<f64>,
+    is_mintable: bool,
+    royalty: HashMap<AccountId, u32>,
+    royalty_buy: HashMap<AccountId, u32>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// datos de catalogo del evento (solo series 1|n), los tiempos van en nanosegundos
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventInfo {
+    pub venue_name: String,
+    pub coordinates: Option<GeoPoint>,
+    pub timezone: Option<String>,
+    pub starts_at: U64,
+    pub ends_at: Option<U64>,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub age_restriction: Option<u8>,
+}
+
+impl EventInfo {
+    // normaliza categoria y tags a minusculas para que las busquedas sean consistentes
+    fn normalized(mut self) -> Self {
+        self.venue_name = self.venue_name.trim().to_string();
+        self.category = self.category.trim().to_lowercase();
+        self.tags = self.tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+        self.tags.sort();
+        self.tags.dedup();
+        self
+    }
+
+    fn assert_valid(&self) {
+        assert!(!self.venue_name.is_empty(), "event_info.venue_name is required");
+        assert!(self.venue_name.len() <= MAX_VENUE_NAME_LEN, "event_info.venue_name exceeds {} characters", MAX_VENUE_NAME_LEN);
+
+        if let Some(coordinates) = &self.coordinates {
+            assert!(
+                coordinates.latitude >= -90.0 && coordinates.latitude <= 90.0,
+                "event_info.coordinates.latitude must be between -90 and 90"
+            );
+            assert!(
+                coordinates.longitude >= -180.0 && coordinates.longitude <= 180.0,
+                "event_info.coordinates.longitude must be between -180 and 180"
+            );
+        }
+
+        if let Some(timezone) = &self.timezone {
+            assert!(!timezone.is_empty() && timezone.len() <= MAX_TIMEZONE_LEN, "event_info.timezone not valid");
+        }
+
+        if let Some(ends_at) = self.ends_at {
+            assert!(ends_at.0 > self.starts_at.0, "event_info.ends_at must be after starts_at");
+        }
+
+        assert!(!self.category.is_empty(), "event_info.category is required");
+        assert!(self.category.len() <= MAX_CATEGORY_LEN, "event_info.category exceeds {} characters", MAX_CATEGORY_LEN);
+
+        assert!(self.tags.len() <= MAX_EVENT_TAGS, "event_info.tags exceeds {} tags", MAX_EVENT_TAGS);
+        for tag in self.tags.iter() {
+            assert!(!tag.is_empty() && tag.len() <= MAX_TAG_LEN, "event_info.tags not valid: {}", tag);
+        }
+    }
+}
+
+// metadata del nft canjeable (2|n) que se crea junto al evento
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EntryObjectMetadata {
+    pub title_suffix: Option<String>,
+    pub media: Option<String>,
+    pub media_hash: Option<Base64VecU8>,
+    pub description: Option<String>,
+    pub extra: Option<String>,
+}
+
+// objeto propuesto por un patrocinador, pendiente de aprobacion del creador del evento
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingObject {
+    pub proposal_id: U64,
+    pub token_event_id: TokenSeriesId,
+    pub sponsor_id: AccountId,
+    pub metadata: TokenMetadata,
+    pub deposit: U128,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -115,6 +237,115 @@ pub struct RoyaltyBuy {
     amount: String,
 }
 
+// reparto del fee de mintick, porcentajes en basis points que suman 10000
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeShare {
+    account_id: AccountId,
+    bps: u32,
+}
+
+// de que parte de la venta sale la comision del afiliado
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum CommissionSource {
+    Creator,
+    Vault,
+}
+
+// codigo de afiliado de un evento (1|n), commission_bps se aplica sobre la parte indicada en source
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AffiliateCode {
+    pub token_event_id: TokenSeriesId,
+    pub code: String,
+    pub affiliate_id: AccountId,
+    pub commission_bps: u32,
+    pub source: CommissionSource,
+    pub sales: U64,
+    pub earnings: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AffiliateStats {
+    pub sales: U64,
+    pub earnings: U128,
+}
+
+// descuento de un codigo promocional, porcentaje en basis points o monto fijo en USD
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PromoDiscount {
+    Percentage { bps: u32 },
+    FixedUsd { amount: f64 },
+}
+
+impl PromoDiscount {
+    // descuento en USD sobre el precio, acotado a MAX_PROMO_DISCOUNT_BPS del precio
+    fn discount_usd(&self, price: f64) -> f64 {
+        let max_discount = price * (MAX_PROMO_DISCOUNT_BPS as f64) / 10_000.0;
+        match self {
+            PromoDiscount::Percentage { bps } => price * (*bps as f64) / 10_000.0,
+            PromoDiscount::FixedUsd { amount } => *amount,
+        }
+        .min(max_discount)
+    }
+}
+
+// codigo promocional de un evento (1|n), solo se guarda el sha256 del codigo
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PromoCode {
+    pub token_event_id: TokenSeriesId,
+    pub code_hash: Base64VecU8,
+    pub discount: PromoDiscount,
+    pub max_uses: Option<u64>,
+    pub max_uses_per_account: Option<u64>,
+    pub expires_at: Option<U64>,
+    pub uses: u64,
+}
+
+// lugar en la lista de espera de un evento agotado, el precio y el storage del mint quedan depositados
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WaitlistEntry {
+    pub account_id: AccountId,
+    pub receiver_id: AccountId,
+    pub price: U128,
+    pub storage_deposit: U128,
+    pub joined_at: U64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WaitlistEntryJson {
+    pub entry_id: U64,
+    pub token_event_id: TokenSeriesId,
+    pub position: U64,
+    pub entry: WaitlistEntry,
+}
+
+// destino de un ticket devuelto cuando no hay nadie en la lista de espera
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnMode {
+    Burn,
+    Pool, // pasa al creador para revenderlo
+}
+
+// politica de devolucion de un evento (1|n), la devolucion cierra deadline ns antes de starts_at
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReturnPolicy {
+    pub refund_bps: u32,
+    pub deadline: U64,
+    pub mode: ReturnMode,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct TokenSeriesJson {
@@ -147,6 +378,34 @@ pub struct TokenSeriesJson3 {
 }
 
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenEventJson {
+    token_series_id: TokenSeriesId,
+    metadata: TokenMetadata,
+    creator_id: AccountId,
+    price: Option<U128>,
+    price_usd: Option<f64>,
+    is_mintable: bool,
+    royalty: HashMap<AccountId, u32>,
+    royalty_buy: HashMap<AccountId, u32>,
+    objects_mint: Vec<TokenSeriesId>,
+    supply: U64,
+    event_info: Option<EventInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenObjectJson {
+    token_series_id: TokenSeriesId,
+    token_event_id: Option<TokenSeriesId>,
+    metadata: TokenMetadata,
+    creator_id: AccountId,
+    is_mintable: bool,
+    supply: U64,
+}
+
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct TokensView {
@@ -180,6 +439,43 @@ pub struct Contract {
     token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries>,
     vault_id: AccountId,
     vault_fee: u32,
+    vault_fee_splits: Vec<FeeShare>,
+    tasa: f64,
+    events_by_creator: LookupMap<AccountId, UnorderedSet<TokenSeriesId>>,
+    series_by_type: LookupMap<String, UnorderedSet<TokenSeriesId>>,
+    events_by_category: LookupMap<String, UnorderedSet<TokenSeriesId>>,
+    events_by_date: TreeMap<(u64, TokenSeriesId), ()>,
+    entry_object_default: EntryObjectMetadata,
+    id_pending_object: u64,
+    pending_objects: UnorderedMap<u64, PendingObject>,
+    pending_objects_by_event: LookupMap<TokenSeriesId, UnorderedSet<u64>>,
+    affiliate_codes: LookupMap<String, AffiliateCode>,
+    affiliate_codes_by_account: LookupMap<AccountId, UnorderedSet<String>>,
+    affiliate_stats: LookupMap<AccountId, AffiliateStats>,
+    promo_codes: LookupMap<String, PromoCode>,
+    promo_code_uses: LookupMap<String, u64>,
+    promo_code_accounts: LookupMap<String, UnorderedSet<AccountId>>,
+    primary_markets: LookupMap<TokenSeriesId, AccountId>,
+    id_waitlist: u64,
+    waitlist: TreeMap<(TokenSeriesId, u64), WaitlistEntry>,
+    waitlist_by_account: LookupMap<String, u64>,
+    return_policies: LookupMap<TokenSeriesId, ReturnPolicy>,
+    return_escrows: LookupMap<TokenSeriesId, Balance>,
+    ticket_prices: LookupMap<TokenId, Balance>,
+}
+
+// estado del contrato antes de las colecciones nuevas, solo se usa en migrate
+#[derive(BorshDeserialize)]
+pub struct ContractV1 {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    owner_id: AccountId,
+    list_admin: UnorderedSet<AccountId>,
+    id_objects: u128,
+    id_event: u128,
+    token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeriesV1>,
+    vault_id: AccountId,
+    vault_fee: u32,
     tasa: f64,
 }
 
@@ -198,6 +494,30 @@ enum StorageKey {
     TokensBySeriesInner { token_series: String },
     TokensByObjectsInner { token_series: String },
     TokensPerOwner { account_hash: Vec<u8> },
+    EventsByCreator,
+    EventsByCreatorInner { account_hash: Vec<u8> },
+    SeriesByType,
+    SeriesByTypeInner { type_token: String },
+    EventsByCategory,
+    EventsByCategoryInner { category: String },
+    EventsByDate,
+    PendingObjects,
+    PendingObjectsByEvent,
+    PendingObjectsByEventInner { token_series: String },
+    AffiliateCodes,
+    AffiliateCodesByAccount,
+    AffiliateCodesByAccountInner { account_hash: Vec<u8> },
+    AffiliateStats,
+    PromoCodes,
+    PromoCodeUses,
+    PrimaryMarkets,
+    Waitlist,
+    WaitlistByAccount,
+    ReturnPolicies,
+    ReturnEscrows,
+    TicketPrices,
+    PromoCodeAccounts,
+    PromoCodeAccountsInner { promo_hash: Vec<u8> },
 }
 
 #[near_bindgen]
@@ -242,8 +562,112 @@ impl Contract {
             token_series_by_id: UnorderedMap::new(StorageKey::TokenSeriesById),
             vault_id: vault_id,
             vault_fee: 300,
+            vault_fee_splits: Vec::new(),
             tasa: 0.0,
+            events_by_creator: LookupMap::new(StorageKey::EventsByCreator),
+            series_by_type: LookupMap::new(StorageKey::SeriesByType),
+            events_by_category: LookupMap::new(StorageKey::EventsByCategory),
+            events_by_date: TreeMap::new(StorageKey::EventsByDate),
+            entry_object_default: EntryObjectMetadata {
+                title_suffix: None,
+                media: Some(DEFAULT_ENTRY_OBJECT_MEDIA.to_string()),
+                media_hash: None,
+                description: Some(DEFAULT_ENTRY_OBJECT_DESCRIPTION.to_string()),
+                extra: None,
+            },
+            id_pending_object: 0,
+            pending_objects: UnorderedMap::new(StorageKey::PendingObjects),
+            pending_objects_by_event: LookupMap::new(StorageKey::PendingObjectsByEvent),
+            affiliate_codes: LookupMap::new(StorageKey::AffiliateCodes),
+            affiliate_codes_by_account: LookupMap::new(StorageKey::AffiliateCodesByAccount),
+            affiliate_stats: LookupMap::new(StorageKey::AffiliateStats),
+            promo_codes: LookupMap::new(StorageKey::PromoCodes),
+            promo_code_uses: LookupMap::new(StorageKey::PromoCodeUses),
+            promo_code_accounts: LookupMap::new(StorageKey::PromoCodeAccounts),
+            primary_markets: LookupMap::new(StorageKey::PrimaryMarkets),
+            id_waitlist: 0,
+            waitlist: TreeMap::new(StorageKey::Waitlist),
+            waitlist_by_account: LookupMap::new(StorageKey::WaitlistByAccount),
+            return_policies: LookupMap::new(StorageKey::ReturnPolicies),
+            return_escrows: LookupMap::new(StorageKey::ReturnEscrows),
+            ticket_prices: LookupMap::new(StorageKey::TicketPrices),
+        }
+    }
+
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut prev: ContractV1 = env::state_read().expect("ERR_NOT_INITIALIZED");
+
+        // las series se reescriben con el layout nuevo (event_info: None)
+        let series_v1 = prev.token_series_by_id.to_vec();
+        prev.token_series_by_id.clear();
+        let mut token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries> =
+            UnorderedMap::new(StorageKey::TokenSeriesById);
+        for (token_series_id, series) in series_v1 {
+            token_series_by_id.insert(
+                &token_series_id,
+                &TokenSeries {
+                    metadata: series.metadata,
+                    creator_id: series.creator_id,
+                    tokens: series.tokens,
+                    objects_mint: series.objects_mint,
+                    price: series.price,
+                    is_mintable: series.is_mintable,
+                    royalty: series.royalty,
+                    royalty_buy: series.royalty_buy,
+                    event_info: None,
+                },
+            );
+        }
+
+        let mut this = Self {
+            tokens: prev.tokens,
+            metadata: prev.metadata,
+            owner_id: prev.owner_id,
+            list_admin: prev.list_admin,
+            id_objects: prev.id_objects,
+            id_event: prev.id_event,
+            token_series_by_id,
+            vault_id: prev.vault_id,
+            vault_fee: prev.vault_fee,
+            vault_fee_splits: Vec::new(),
+            tasa: prev.tasa,
+            events_by_creator: LookupMap::new(StorageKey::EventsByCreator),
+            series_by_type: LookupMap::new(StorageKey::SeriesByType),
+            events_by_category: LookupMap::new(StorageKey::EventsByCategory),
+            events_by_date: TreeMap::new(StorageKey::EventsByDate),
+            entry_object_default: EntryObjectMetadata {
+                title_suffix: None,
+                media: Some(DEFAULT_ENTRY_OBJECT_MEDIA.to_string()),
+                media_hash: None,
+                description: Some(DEFAULT_ENTRY_OBJECT_DESCRIPTION.to_string()),
+                extra: None,
+            },
+            id_pending_object: 0,
+            pending_objects: UnorderedMap::new(StorageKey::PendingObjects),
+            pending_objects_by_event: LookupMap::new(StorageKey::PendingObjectsByEvent),
+            affiliate_codes: LookupMap::new(StorageKey::AffiliateCodes),
+            affiliate_codes_by_account: LookupMap::new(StorageKey::AffiliateCodesByAccount),
+            affiliate_stats: LookupMap::new(StorageKey::AffiliateStats),
+            promo_codes: LookupMap::new(StorageKey::PromoCodes),
+            promo_code_uses: LookupMap::new(StorageKey::PromoCodeUses),
+            promo_code_accounts: LookupMap::new(StorageKey::PromoCodeAccounts),
+            primary_markets: LookupMap::new(StorageKey::PrimaryMarkets),
+            id_waitlist: 0,
+            waitlist: TreeMap::new(StorageKey::Waitlist),
+            waitlist_by_account: LookupMap::new(StorageKey::WaitlistByAccount),
+            return_policies: LookupMap::new(StorageKey::ReturnPolicies),
+            return_escrows: LookupMap::new(StorageKey::ReturnEscrows),
+            ticket_prices: LookupMap::new(StorageKey::TicketPrices),
+        };
+
+        // indices de las series existentes
+        for (token_series_id, series) in this.token_series_by_id.to_vec() {
+            this.internal_add_series_index(&token_series_id, &series.creator_id);
         }
+
+        this
     }
 
     /* codigo original */
@@ -308,6 +732,162 @@ impl Contract {
         self.tasa
     }
 
+    // metadata por defecto del nft canjeable (2|n), solo el owner
+    #[payable]
+    pub fn set_entry_object_default(&mut self, entry_object: EntryObjectMetadata) {
+        assert_one_yocto();
+        assert!(self.owner_id == env::predecessor_account_id(), "Only owner");
+
+        self.entry_object_default = entry_object;
+
+        env::log_str(
+            &json!({
+                "type": "set_entry_object_default",
+                "params": {
+                    "entry_object": self.entry_object_default.clone()
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn get_entry_object_default(&self) -> EntryObjectMetadata {
+        self.entry_object_default.clone()
+    }
+
+    // reparto del fee de venta primaria entre varias wallets, vacio = todo al vault_id
+    #[payable]
+    pub fn set_vault_fee_splits(&mut self, splits: Vec<FeeShare>) {
+        assert_one_yocto();
+        assert!(self.owner_id == env::predecessor_account_id(), "Only owner");
+
+        if !splits.is_empty() {
+            let mut total_bps: u32 = 0;
+            for (index, share) in splits.iter().enumerate() {
+                assert!(share.bps > 0, "share of {} is zero", share.account_id);
+                assert!(
+                    splits[..index].iter().all(|other| other.account_id != share.account_id),
+                    "{} is listed twice",
+                    share.account_id
+                );
+                total_bps += share.bps;
+            }
+            assert!(total_bps == 10_000, "shares must sum to 10000");
+        }
+
+        self.vault_fee_splits = splits;
+
+        env::log_str(
+            &json!({
+                "type": "set_vault_fee_splits",
+                "params": {
+                    "splits": self.vault_fee_splits.clone()
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn get_vault_fee_splits(&self) -> Vec<FeeShare> {
+        self.vault_fee_splits.clone()
+    }
+
+    // valida el codigo y registra su uso, retorna el hash y el descuento en USD
+    fn internal_use_promo_code(&mut self, token_event_id: &TokenSeriesId, code: &str, price: f64) -> (Base64VecU8, f64) {
+        let code_hash = env::sha256(code.trim().to_lowercase().as_bytes());
+        let key = promo_code_key(token_event_id, &code_hash);
+        let mut promo = self.promo_codes.get(&key).expect("promo code not valid");
+
+        if let Some(expires_at) = promo.expires_at {
+            assert!(env::block_timestamp() < expires_at.0, "promo code expired");
+        }
+        if let Some(max_uses) = promo.max_uses {
+            assert!(promo.uses < max_uses, "promo code sold out");
+        }
+        let account_key = format!("{}#{}", key, env::predecessor_account_id());
+        let account_uses = self.promo_code_uses.get(&account_key).unwrap_or(0);
+        if let Some(max_uses_per_account) = promo.max_uses_per_account {
+            assert!(account_uses < max_uses_per_account, "promo code limit reached for this account");
+        }
+
+        promo.uses += 1;
+        self.promo_codes.insert(&key, &promo);
+        self.promo_code_uses.insert(&account_key, &(account_uses + 1));
+        if account_uses == 0 {
+            let mut accounts = self.promo_code_accounts.get(&key).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::PromoCodeAccountsInner {
+                    promo_hash: env::sha256(key.as_bytes()),
+                })
+            });
+            accounts.insert(&env::predecessor_account_id());
+            self.promo_code_accounts.insert(&key, &accounts);
+        }
+
+        (promo.code_hash.clone(), promo.discount.discount_usd(price))
+    }
+
+    // paga la comision y acumula ventas del codigo y del afiliado
+    fn internal_pay_affiliate(&mut self, mut affiliate_code: AffiliateCode, commission: u128) -> RoyaltyBuy {
+        if commission > 0 {
+            Promise::new(affiliate_code.affiliate_id.clone()).transfer(commission);
+        }
+
+        affiliate_code.sales = U64(affiliate_code.sales.0 + 1);
+        affiliate_code.earnings = U128(affiliate_code.earnings.0 + commission);
+        self.affiliate_codes.insert(
+            &affiliate_code_key(&affiliate_code.token_event_id, &affiliate_code.code),
+            &affiliate_code,
+        );
+
+        let mut stats = self.get_affiliate_stats(affiliate_code.affiliate_id.clone());
+        stats.sales = U64(stats.sales.0 + 1);
+        stats.earnings = U128(stats.earnings.0 + commission);
+        self.affiliate_stats.insert(&affiliate_code.affiliate_id, &stats);
+
+        RoyaltyBuy {
+            wallet: affiliate_code.affiliate_id,
+            porcentaje: affiliate_code.commission_bps.to_string(),
+            amount: commission.to_string(),
+        }
+    }
+
+    // parte de cada wallet del fee, el resto por redondeo va a la ultima
+    fn internal_vault_fee_shares(&self, for_vault: u128) -> Vec<(AccountId, u32, u128)> {
+        if self.vault_fee_splits.is_empty() {
+            return vec![(self.vault_id.clone(), 10_000, for_vault)];
+        }
+
+        let mut remaining: u128 = for_vault;
+        let mut shares: Vec<(AccountId, u32, u128)> = Vec::new();
+        for (index, share) in self.vault_fee_splits.iter().enumerate() {
+            let amount: u128 = if index == self.vault_fee_splits.len() - 1 {
+                remaining
+            } else {
+                for_vault * (share.bps as u128) / 10_000u128
+            };
+            remaining -= amount;
+            shares.push((share.account_id.clone(), share.bps, amount));
+        }
+        shares
+    }
+
+    // transfiere a cada wallet su parte
+    fn internal_pay_vault_fee(&self, for_vault: u128) -> Vec<RoyaltyBuy> {
+        self.internal_vault_fee_shares(for_vault)
+            .into_iter()
+            .map(|(wallet, bps, amount)| {
+                if amount > 0 {
+                    Promise::new(wallet.clone()).transfer(amount);
+                }
+                RoyaltyBuy {
+                    wallet,
+                    porcentaje: bps.to_string(),
+                    amount: amount.to_string(),
+                }
+            })
+            .collect()
+    }
+
    #[payable]
     pub fn update_nft_event(&mut self, 
         token_event_id: TokenSeriesId, 
@@ -317,9 +897,11 @@ impl Contract {
         price: Option<f64>,
         copies: Option<u64>,
         is_mintable: Option<bool>,
-        //objects_ids: Option<Vec<String>>,
+        objects_ids: Option<Vec<String>>,
         royalty: Option<HashMap<AccountId, u32>>,
         royalty_buy: Option<HashMap<AccountId, u32>>,
+        event_info: Option<EventInfo>,
+        entry_object: Option<EntryObjectMetadata>,
     ) {
         let mut nft_serie = self.token_series_by_id.get(&token_event_id).expect("tonken serie id not exist");
         assert!(self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || nft_serie.creator_id == env::predecessor_account_id(), "Only creator or administrator");
@@ -413,11 +995,67 @@ impl Contract {
             nft_serie.royalty = royalty_res;
         }
 
-        /*if objects_ids.is_some() {
-            for item in objects_ids.unwrap().iter() {
-                nft_serie.objects_mint.insert(&item.to_string());
-            } 
-        }*/
+        if let Some(event_info) = event_info {
+            let event_info = event_info.normalized();
+            event_info.assert_valid();
+
+            if let Some(old_event_info) = nft_serie.event_info.as_ref() {
+                self.internal_remove_event_info_index(&token_event_id, old_event_info);
+            }
+            self.internal_add_event_info_index(&token_event_id, &event_info);
+            nft_serie.event_info = Some(event_info);
+        }
+
+        let mut entry_object_res: Option<(TokenSeriesId, TokenMetadata)> = None;
+        if let Some(entry_object) = entry_object {
+            let token_object_id = nft_serie.objects_mint
+                .iter()
+                .find(|object_id| object_id.starts_with("2|"))
+                .expect("entry object not exist");
+            let mut token_object = self.token_series_by_id.get(&token_object_id).expect("token series id no existe");
+
+            if let Some(title_suffix) = entry_object.title_suffix {
+                token_object.metadata.title = Some(format!("{}{}", nft_serie.metadata.title.clone().unwrap_or_default(), title_suffix));
+            }
+            if entry_object.media.is_some() {
+                token_object.metadata.media = entry_object.media;
+                token_object.metadata.media_hash = entry_object.media_hash;
+            } else if entry_object.media_hash.is_some() {
+                token_object.metadata.media_hash = entry_object.media_hash;
+            }
+            if entry_object.description.is_some() { token_object.metadata.description = entry_object.description; }
+            if entry_object.extra.is_some() { token_object.metadata.extra = entry_object.extra; }
+
+            self.token_series_by_id.insert(&token_object_id, &token_object);
+            entry_object_res = Some((token_object_id, token_object.metadata));
+        }
+
+        // reordena los objetos que se mintean en cada compra, solo objetos de este evento
+        if let Some(objects_ids) = objects_ids {
+            assert!(
+                objects_ids.iter().any(|item| item.starts_with("2|")),
+                "objects_ids must include the entry object"
+            );
+            for item in objects_ids.iter() {
+                let token_object = self.token_series_by_id.get(item).expect("token series id no existe");
+                assert!(
+                    token_object.metadata.reference == Some(token_event_id.clone()),
+                    "{} does not belong to the event", item
+                );
+            }
+
+            // los objetos que salen de la lista dejan de mintearse, los que entran vuelven a ser minteables
+            for item in nft_serie.objects_mint.to_vec() {
+                if !objects_ids.contains(&item) {
+                    self.internal_set_object_mintable(&item, false);
+                }
+            }
+            nft_serie.objects_mint.clear();
+            for item in objects_ids.iter() {
+                self.internal_set_object_mintable(item, true);
+                nft_serie.objects_mint.insert(item);
+            }
+        }
 
         self.token_series_by_id.insert(&token_event_id, &nft_serie);
 
@@ -440,12 +1078,31 @@ impl Contract {
                     "royalty": nft_serie.royalty.clone(),
                     "royalty_buy": nft_serie.royalty_buy.clone(),
                     "is_mintable": nft_serie.is_mintable,
+                    "event_info": nft_serie.event_info.clone(),
                 }
             })
             .to_string(),
         );
 
+        if let Some((token_object_id, object_metadata)) = entry_object_res {
+            env::log_str(
+                &json!({
+                    "type": "update_nft_object",
+                    "params": {
+                        "token_series_id": token_object_id,
+                        "token_metadata": object_metadata,
+                    }
+                })
+                .to_string(),
+            );
+        }
+
         //refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        // nuevas copias van primero a la lista de espera
+        if copies.is_some() {
+            self.internal_allocate_waitlist(&token_event_id);
+        }
     }
  
 
@@ -456,6 +1113,8 @@ impl Contract {
         price: Option<f64>,
         royalty: Option<HashMap<AccountId, u32>>,
         royalty_buy: Option<HashMap<AccountId, u32>>,
+        event_info: Option<EventInfo>,
+        entry_object: Option<EntryObjectMetadata>,
     ) -> String {
         assert!((self.tasa > 0.0), "Tasa debe ser mayor a 0");
         
@@ -519,6 +1178,16 @@ impl Contract {
             "Exceeds maximum royalty -> 9000",
         );
 
+        let event_info_res: Option<EventInfo> = event_info.map(|event_info| {
+            let event_info = event_info.normalized();
+            event_info.assert_valid();
+            assert!(
+                event_info.starts_at.0 > env::block_timestamp(),
+                "event_info.starts_at must be in the future"
+            );
+            event_info
+        });
+
         let price_res: Option<f64> = if price.is_some() {
             let price_final: f64 = price.unwrap(); 
             Some(price_final)
@@ -548,12 +1217,35 @@ impl Contract {
             is_mintable: true,
             royalty: royalty_res.clone(),
             royalty_buy: royalty_res_buy.clone(),
+            event_info: event_info_res.clone(),
         });
 
         //creacion de nft cangeable
+        // los campos que no envie el organizador se toman del default del contrato
+        let entry_object_default = self.entry_object_default.clone();
+        let entry_object = entry_object.unwrap_or(EntryObjectMetadata {
+            title_suffix: None,
+            media: None,
+            media_hash: None,
+            description: None,
+            extra: None,
+        });
+
         let mut object_metadata: TokenMetadata = event_metadata.clone();
-        object_metadata.description = Some("This is the let me in of the event".to_string());
-        object_metadata.media = Some("https://mintickt.mypinata.cloud/ipfs/QmdW7LfjTfHWmpRadqk2o5oUUFutPuqUx2dZj3C4CH2Jjr".to_string());
+        if let Some(title_suffix) = entry_object.title_suffix.or(entry_object_default.title_suffix) {
+            object_metadata.title = Some(format!("{}{}", title.clone().unwrap(), title_suffix));
+        }
+        object_metadata.description = entry_object.description.or(entry_object_default.description);
+        if entry_object.media.is_some() {
+            object_metadata.media = entry_object.media;
+            object_metadata.media_hash = entry_object.media_hash;
+        } else {
+            object_metadata.media = entry_object_default.media;
+            object_metadata.media_hash = entry_object_default.media_hash;
+        }
+        if let Some(extra) = entry_object.extra.or(entry_object_default.extra) {
+            object_metadata.extra = Some(extra);
+        }
         object_metadata.copies = None;
         object_metadata.reference = Some(token_event_id.to_string());
 
@@ -578,6 +1270,7 @@ impl Contract {
             is_mintable: true,
             royalty: HashMap::new(),
             royalty_buy: HashMap::new(),
+            event_info: None,
         });
 
         //agregando el nft camgeable al evento
@@ -585,6 +1278,12 @@ impl Contract {
         data_serie.objects_mint.insert(&token_object_id.clone()); 
         self.token_series_by_id.insert(&token_event_id, &data_serie);
 
+        self.internal_add_series_index(&token_event_id, &caller_id);
+        self.internal_add_series_index(&token_object_id, &caller_id);
+        if let Some(event_info) = event_info_res.as_ref() {
+            self.internal_add_event_info_index(&token_event_id, event_info);
+        }
+
         refund_deposit(env::storage_usage() - initial_storage_usage, 0);
 
         env::log_str(
@@ -598,7 +1297,8 @@ impl Contract {
                     "object_event": false,
                     "price": price_res,
                     "royalty": royalty_res,
-                    "royalty_buy": royalty_res_buy
+                    "royalty_buy": royalty_res_buy,
+                    "event_info": event_info_res,
                 }
             })
             .to_string(),
@@ -646,6 +1346,25 @@ impl Contract {
         let type_token = token_series_id_assignment.split("|").next().unwrap().to_string();
         assert!(type_token == "1", "token_series_id_assignment not valid!");
         
+        let data_serie = self.token_series_by_id.get(&token_series_id_assignment.clone()).expect("token series id no existe");
+        assert!(
+            self.owner_id == caller_id || self.list_admin.contains(&env::signer_account_id()) || data_serie.creator_id == caller_id,
+            "Only creator or administrator"
+        );
+
+        let token_object_id = self.internal_create_object(token_metadata, token_series_id_assignment, caller_id);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+		
+        token_object_id
+    }
+
+    fn internal_create_object(
+        &mut self,
+        token_metadata: TokenMetadata,
+        token_series_id_assignment: TokenSeriesId,
+        creator_id: AccountId,
+    ) -> String {
         let mut data_serie = self.token_series_by_id.get(&token_series_id_assignment.clone()).expect("token series id no existe");
 
         self.id_objects += 1;
@@ -657,7 +1376,7 @@ impl Contract {
 
         self.token_series_by_id.insert(&token_object_id, &TokenSeries{
             metadata: object_metadata.clone(),
-            creator_id: caller_id.clone(),
+            creator_id: creator_id.clone(),
             tokens: UnorderedSet::new(
                 StorageKey::TokensBySeriesInner {
                     token_series: token_object_id.clone(),
@@ -676,6 +1395,7 @@ impl Contract {
             is_mintable: true,
             royalty: HashMap::new(),
             royalty_buy: HashMap::new(),
+            event_info: None,
         });
 
         
@@ -683,9 +1403,8 @@ impl Contract {
         
         data_serie.objects_mint.insert(&token_object_id.clone()); 
         self.token_series_by_id.insert(&token_series_id_assignment.clone(), &data_serie);
-        
-        
-        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        self.internal_add_series_index(&token_object_id, &creator_id);
 
         env::log_str(
             &json!({
@@ -693,7 +1412,7 @@ impl Contract {
                 "params": {
                     "token_series_id": token_object_id.clone(),
                     "token_metadata": object_metadata.clone(),
-                    "creator_id": caller_id.to_string(),
+                    "creator_id": creator_id.to_string(),
                     "list_objects": [],
                     "object_event": false,
                     "price": 0,
@@ -703,101 +1422,1389 @@ impl Contract {
             })
             .to_string(),
         );
-		
-        token_object_id.to_string()
+
+        token_object_id
     }
 
 
+    // un patrocinador propone un objeto para el evento, el creador lo acepta o rechaza
+    // el deposito queda retenido hasta entonces y cubre el storage del objeto
     #[payable]
-    pub fn nft_buy(
-        &mut self, 
-        token_series_id: TokenSeriesId,
-        receiver_id: Option<AccountId>
-    ) {
+    pub fn propose_nft_object(
+        &mut self,
+        token_metadata: TokenMetadata,
+        token_series_id_assignment: TokenSeriesId
+    ) -> U64 {
         let initial_storage_usage = env::storage_usage();
-        let type_token = token_series_id.split("|").next().unwrap().to_string();
-        assert!(type_token == "1", "token_series_id not valid!");
-        //token_series_id.split("|").collect::<Vec<&str>>()[2];
-        
+        let sponsor_id = env::predecessor_account_id();
+
+        let type_token = token_series_id_assignment.split("|").next().unwrap().to_string();
+        assert!(type_token == "1", "token_series_id_assignment not valid!");
+        self.token_series_by_id.get(&token_series_id_assignment).expect("token series id no existe");
+
+        self.id_pending_object += 1;
+        let proposal_id = self.id_pending_object;
+
+        self.pending_objects.insert(&proposal_id, &PendingObject {
+            proposal_id: U64(proposal_id),
+            token_event_id: token_series_id_assignment.clone(),
+            sponsor_id: sponsor_id.clone(),
+            metadata: token_metadata.clone(),
+            deposit: U128(env::attached_deposit()),
+        });
+
+        let mut proposals = self.pending_objects_by_event.get(&token_series_id_assignment).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::PendingObjectsByEventInner {
+                token_series: token_series_id_assignment.clone(),
+            })
+        });
+        proposals.insert(&proposal_id);
+        self.pending_objects_by_event.insert(&token_series_id_assignment, &proposals);
+
+        let required_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            required_cost <= env::attached_deposit(),
+            "Must attach {} yoctoNEAR to cover storage",
+            required_cost,
+        );
+
+        env::log_str(
+            &json!({
+                "type": "propose_nft_object",
+                "params": {
+                    "proposal_id": U64(proposal_id),
+                    "token_event_id": token_series_id_assignment,
+                    "sponsor_id": sponsor_id,
+                    "token_metadata": token_metadata,
+                    "deposit": U128(env::attached_deposit()),
+                }
+            })
+            .to_string(),
+        );
+
+        U64(proposal_id)
+    }
+
+    pub fn accept_nft_object(&mut self, proposal_id: U64) -> String {
+        let pending_object = self.pending_objects.get(&proposal_id.0).expect("proposal not exist");
+        let token_event = self.token_series_by_id.get(&pending_object.token_event_id).expect("token series id no existe");
+        let caller_id = env::predecessor_account_id();
+        assert!(
+            self.owner_id == caller_id || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == caller_id,
+            "Only creator or administrator"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let token_object_id = self.internal_create_object(
+            pending_object.metadata.clone(),
+            pending_object.token_event_id.clone(),
+            pending_object.sponsor_id.clone(),
+        );
+        let required_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            required_cost <= pending_object.deposit.0,
+            "Sponsor deposit does not cover storage: {}",
+            required_cost,
+        );
+
+        self.internal_remove_pending_object(&pending_object);
+
+        let refund = pending_object.deposit.0 - required_cost;
+        if refund > 1 {
+            Promise::new(pending_object.sponsor_id.clone()).transfer(refund);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "accept_nft_object",
+                "params": {
+                    "proposal_id": proposal_id,
+                    "token_event_id": pending_object.token_event_id,
+                    "sponsor_id": pending_object.sponsor_id,
+                    "token_series_id": token_object_id.clone(),
+                }
+            })
+            .to_string(),
+        );
+
+        token_object_id
+    }
+
+    pub fn reject_nft_object(&mut self, proposal_id: U64) {
+        let pending_object = self.pending_objects.get(&proposal_id.0).expect("proposal not exist");
+        let token_event = self.token_series_by_id.get(&pending_object.token_event_id).expect("token series id no existe");
+        let caller_id = env::predecessor_account_id();
+        assert!(
+            self.owner_id == caller_id || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == caller_id,
+            "Only creator or administrator"
+        );
+
+        self.internal_remove_pending_object(&pending_object);
+        if pending_object.deposit.0 > 0 {
+            Promise::new(pending_object.sponsor_id.clone()).transfer(pending_object.deposit.0);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "reject_nft_object",
+                "params": {
+                    "proposal_id": proposal_id,
+                    "token_event_id": pending_object.token_event_id,
+                    "sponsor_id": pending_object.sponsor_id,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    #[payable]
+    pub fn cancel_nft_object_proposal(&mut self, proposal_id: U64) {
+        assert_one_yocto();
+        let pending_object = self.pending_objects.get(&proposal_id.0).expect("proposal not exist");
+        assert_eq!(pending_object.sponsor_id, env::predecessor_account_id(), "Only sponsor");
+
+        self.internal_remove_pending_object(&pending_object);
+        Promise::new(pending_object.sponsor_id.clone()).transfer(pending_object.deposit.0 + 1);
+
+        env::log_str(
+            &json!({
+                "type": "cancel_nft_object_proposal",
+                "params": {
+                    "proposal_id": proposal_id,
+                    "token_event_id": pending_object.token_event_id,
+                    "sponsor_id": pending_object.sponsor_id,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    fn internal_remove_pending_object(&mut self, pending_object: &PendingObject) {
+        self.pending_objects.remove(&pending_object.proposal_id.0);
+        if let Some(mut proposals) = self.pending_objects_by_event.get(&pending_object.token_event_id) {
+            proposals.remove(&pending_object.proposal_id.0);
+            if proposals.is_empty() {
+                self.pending_objects_by_event.remove(&pending_object.token_event_id);
+            } else {
+                self.pending_objects_by_event.insert(&pending_object.token_event_id, &proposals);
+            }
+        }
+    }
+
+    pub fn get_pending_objects(
+        &self,
+        token_event_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<PendingObject> {
+        let proposals = if let Some(proposals) = self.pending_objects_by_event.get(&token_event_id) {
+            proposals
+        } else {
+            return vec![];
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (proposals.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        proposals
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|proposal_id| self.pending_objects.get(&proposal_id).unwrap())
+            .collect()
+    }
+
+
+    pub fn update_nft_object(
+        &mut self,
+        token_object_id: TokenSeriesId,
+        title: Option<String>,
+        description: Option<String>,
+        media: Option<String>,
+        media_hash: Option<Base64VecU8>,
+        extra: Option<String>,
+    ) {
+        let type_token = token_object_id.split("|").next().unwrap().to_string();
+        assert!(type_token == "2" || type_token == "3", "token_object_id not valid!");
+
+        let mut token_object = self.token_series_by_id.get(&token_object_id).expect("token series id no existe");
+        self.assert_object_creator_or_admin(&token_object);
+
+        if title.is_some() { token_object.metadata.title = title; }
+        if description.is_some() { token_object.metadata.description = description; }
+        if media.is_some() {
+            token_object.metadata.media = media;
+            token_object.metadata.media_hash = media_hash;
+        } else if media_hash.is_some() {
+            token_object.metadata.media_hash = media_hash;
+        }
+        if extra.is_some() { token_object.metadata.extra = extra; }
+
+        self.token_series_by_id.insert(&token_object_id, &token_object);
+
+        env::log_str(
+            &json!({
+                "type": "update_nft_object",
+                "params": {
+                    "token_series_id": token_object_id,
+                    "token_metadata": token_object.metadata,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // desvincula un objeto del evento, solo afecta a las compras futuras
+    // los tokens ya minteados conservan su serie y siguen funcionando
+    pub fn remove_nft_object(&mut self, token_object_id: TokenSeriesId) {
+        let type_token = token_object_id.split("|").next().unwrap().to_string();
+        assert!(type_token == "3", "only extra objects can be removed");
+
+        let token_object = self.token_series_by_id.get(&token_object_id).expect("token series id no existe");
+        self.assert_object_creator_or_admin(&token_object);
+
+        let token_event_id = token_object.metadata.reference.clone().expect("object without event");
+        let mut token_event = self.token_series_by_id.get(&token_event_id).expect("token series id no existe");
+        assert!(token_event.objects_mint.remove(&token_object_id), "object is not attached to the event");
+        self.token_series_by_id.insert(&token_event_id, &token_event);
+
+        self.internal_set_object_mintable(&token_object_id, false);
+
+        env::log_str(
+            &json!({
+                "type": "remove_nft_object",
+                "params": {
+                    "token_series_id": token_object_id,
+                    "token_event_id": token_event_id,
+                    "list_objects": token_event.objects_mint.to_vec(),
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    fn internal_set_object_mintable(&mut self, token_object_id: &TokenSeriesId, is_mintable: bool) {
+        let mut token_object = self.token_series_by_id.get(token_object_id).expect("token series id no existe");
+        if token_object.is_mintable != is_mintable {
+            token_object.is_mintable = is_mintable;
+            self.token_series_by_id.insert(token_object_id, &token_object);
+        }
+    }
+
+    fn assert_object_creator_or_admin(&self, token_object: &TokenSeries) {
+        let predecessor_id = env::predecessor_account_id();
+        let event_creator_id = token_object.metadata.reference.as_ref()
+            .and_then(|token_event_id| self.token_series_by_id.get(token_event_id))
+            .map(|token_event| token_event.creator_id);
+
+        assert!(
+            self.owner_id == predecessor_id
+                || self.list_admin.contains(&env::signer_account_id())
+                || token_object.creator_id == predecessor_id
+                || event_creator_id == Some(predecessor_id),
+            "Only creator or administrator"
+        );
+    }
+
+
+    // indices secundarios: eventos por creador y series por tipo (1|, 2|, 3|)
+    fn internal_add_series_index(&mut self, token_series_id: &TokenSeriesId, creator_id: &AccountId) {
+        let type_token = token_series_id.split("|").next().unwrap().to_string();
+
+        if type_token == "1" {
+            let mut events = self.events_by_creator.get(creator_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::EventsByCreatorInner {
+                    account_hash: env::sha256(creator_id.as_bytes()),
+                })
+            });
+            events.insert(token_series_id);
+            self.events_by_creator.insert(creator_id, &events);
+        }
+
+        let mut series = self.series_by_type.get(&type_token).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::SeriesByTypeInner {
+                type_token: type_token.clone(),
+            })
+        });
+        series.insert(token_series_id);
+        self.series_by_type.insert(&type_token, &series);
+    }
+
+
+    fn internal_add_event_info_index(&mut self, token_event_id: &TokenSeriesId, event_info: &EventInfo) {
+        let mut events = self.events_by_category.get(&event_info.category).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::EventsByCategoryInner {
+                category: event_info.category.clone(),
+            })
+        });
+        events.insert(token_event_id);
+        self.events_by_category.insert(&event_info.category, &events);
+
+        self.events_by_date.insert(&(event_info.starts_at.0, token_event_id.clone()), &());
+    }
+
+    fn internal_remove_event_info_index(&mut self, token_event_id: &TokenSeriesId, event_info: &EventInfo) {
+        if let Some(mut events) = self.events_by_category.get(&event_info.category) {
+            events.remove(token_event_id);
+            if events.is_empty() {
+                self.events_by_category.remove(&event_info.category);
+            } else {
+                self.events_by_category.insert(&event_info.category, &events);
+            }
+        }
+
+        self.events_by_date.remove(&(event_info.starts_at.0, token_event_id.clone()));
+    }
+
+
+    // registra un codigo de afiliado para un evento (1|n), solo el creador o un administrador
+    #[payable]
+    pub fn add_affiliate_code(
+        &mut self,
+        token_event_id: TokenSeriesId,
+        code: String,
+        affiliate_id: AccountId,
+        commission_bps: u32,
+        source: CommissionSource,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert!(token_event_id.starts_with("1|"), "token_event_id not valid!");
+        assert!(
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+        assert!(commission_bps > 0 && commission_bps <= 10_000, "commission_bps must be between 1 and 10000");
+
+        let code = code.trim().to_lowercase();
+        assert!(
+            !code.is_empty()
+                && code.len() <= MAX_AFFILIATE_CODE_LEN
+                && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "code not valid: {}",
+            code
+        );
+        let key = affiliate_code_key(&token_event_id, &code);
+        assert!(self.affiliate_codes.get(&key).is_none(), "code already exists");
+
+        let affiliate_code = AffiliateCode {
+            token_event_id: token_event_id.clone(),
+            code: code.clone(),
+            affiliate_id: affiliate_id.clone(),
+            commission_bps,
+            source,
+            sales: U64(0),
+            earnings: U128(0),
+        };
+        self.affiliate_codes.insert(&key, &affiliate_code);
+
+        let mut codes = self.affiliate_codes_by_account.get(&affiliate_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::AffiliateCodesByAccountInner {
+                account_hash: env::sha256(affiliate_id.as_bytes()),
+            })
+        });
+        codes.insert(&key);
+        self.affiliate_codes_by_account.insert(&affiliate_id, &codes);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        env::log_str(
+            &json!({
+                "type": "add_affiliate_code",
+                "params": affiliate_code
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn remove_affiliate_code(&mut self, token_event_id: TokenSeriesId, code: String) {
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert!(
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+
+        let code = code.trim().to_lowercase();
+        let key = affiliate_code_key(&token_event_id, &code);
+        let affiliate_code = self.affiliate_codes.remove(&key).expect("code not exist");
+
+        if let Some(mut codes) = self.affiliate_codes_by_account.get(&affiliate_code.affiliate_id) {
+            codes.remove(&key);
+            if codes.is_empty() {
+                self.affiliate_codes_by_account.remove(&affiliate_code.affiliate_id);
+            } else {
+                self.affiliate_codes_by_account.insert(&affiliate_code.affiliate_id, &codes);
+            }
+        }
+
+        env::log_str(
+            &json!({
+                "type": "remove_affiliate_code",
+                "params": {
+                    "token_event_id": token_event_id,
+                    "code": code,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn get_affiliate_code(&self, token_event_id: TokenSeriesId, code: String) -> Option<AffiliateCode> {
+        self.affiliate_codes.get(&affiliate_code_key(&token_event_id, &code.trim().to_lowercase()))
+    }
+
+    pub fn get_affiliate_codes_by_account(
+        &self,
+        affiliate_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<AffiliateCode> {
+        let codes = if let Some(codes) = self.affiliate_codes_by_account.get(&affiliate_id) {
+            codes
+        } else {
+            return vec![];
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (codes.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        codes
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|key| self.affiliate_codes.get(&key))
+            .collect()
+    }
+
+    // ventas y comisiones acumuladas de un afiliado en todos sus codigos
+    pub fn get_affiliate_stats(&self, affiliate_id: AccountId) -> AffiliateStats {
+        self.affiliate_stats.get(&affiliate_id).unwrap_or(AffiliateStats {
+            sales: U64(0),
+            earnings: U128(0),
+        })
+    }
+
+    // registra un codigo promocional, code_hash es el sha256 del codigo en minusculas
+    #[payable]
+    pub fn add_promo_code(
+        &mut self,
+        token_event_id: TokenSeriesId,
+        code_hash: Base64VecU8,
+        discount: PromoDiscount,
+        max_uses: Option<u64>,
+        max_uses_per_account: Option<u64>,
+        expires_at: Option<U64>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert!(token_event_id.starts_with("1|"), "token_event_id not valid!");
+        assert!(
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+        assert!(code_hash.0.len() == 32, "code_hash must be a sha256 hash");
+        match &discount {
+            PromoDiscount::Percentage { bps } => assert!(
+                *bps > 0 && *bps <= MAX_PROMO_DISCOUNT_BPS,
+                "discount bps must be between 1 and {}", MAX_PROMO_DISCOUNT_BPS
+            ),
+            PromoDiscount::FixedUsd { amount } => assert!(*amount > 0.0, "discount amount must be greater than 0"),
+        }
+        if let Some(expires_at) = expires_at {
+            assert!(expires_at.0 > env::block_timestamp(), "expires_at must be in the future");
+        }
+
+        let key = promo_code_key(&token_event_id, &code_hash.0);
+        assert!(self.promo_codes.get(&key).is_none(), "promo code already exists");
+
+        let promo_code = PromoCode {
+            token_event_id,
+            code_hash,
+            discount,
+            max_uses,
+            max_uses_per_account,
+            expires_at,
+            uses: 0,
+        };
+        self.promo_codes.insert(&key, &promo_code);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        env::log_str(
+            &json!({
+                "type": "add_promo_code",
+                "params": promo_code
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn remove_promo_code(&mut self, token_event_id: TokenSeriesId, code_hash: Base64VecU8) {
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert!(
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+
+        let key = promo_code_key(&token_event_id, &code_hash.0);
+        self.promo_codes.remove(&key).expect("promo code not exist");
+
+        // usos por cuenta del codigo
+        if let Some(mut accounts) = self.promo_code_accounts.remove(&key) {
+            for account_id in accounts.iter() {
+                self.promo_code_uses.remove(&format!("{}#{}", key, account_id));
+            }
+            accounts.clear();
+        }
+
+        env::log_str(
+            &json!({
+                "type": "remove_promo_code",
+                "params": {
+                    "token_event_id": token_event_id,
+                    "code_hash": code_hash,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    pub fn get_promo_code(&self, token_event_id: TokenSeriesId, code_hash: Base64VecU8) -> Option<PromoCode> {
+        self.promo_codes.get(&promo_code_key(&token_event_id, &code_hash.0))
+    }
+
+    pub fn get_promo_code_uses(&self, token_event_id: TokenSeriesId, code_hash: Base64VecU8, account_id: AccountId) -> u64 {
+        self.promo_code_uses
+            .get(&format!("{}#{}", promo_code_key(&token_event_id, &code_hash.0), account_id))
+            .unwrap_or(0)
+    }
+
+    #[payable]
+    pub fn nft_buy(
+        &mut self, 
+        token_series_id: TokenSeriesId,
+        receiver_id: Option<AccountId>,
+        referrer: Option<String>,
+        promo_code: Option<String>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let type_token = token_series_id.split("|").next().unwrap().to_string();
+        assert!(type_token == "1", "token_series_id not valid!");
+        //token_series_id.split("|").collect::<Vec<&str>>()[2];
+        
         let token_event = self.token_series_by_id.get(&token_series_id.clone()).expect("Token series not exist");
+        self.assert_waitlist_empty(&token_series_id);
+
+        let price_list: f64 = token_event.price.expect("not for sale");
+        let attached_deposit = env::attached_deposit();
+        let receiver_id: AccountId = if let Some(receiver_id) = receiver_id {
+            if !is_valid_account_id(receiver_id.as_bytes()) {
+                env::panic_str("Not valid account_id for royalty");
+            };
+            receiver_id
+        } else {
+            env::predecessor_account_id()
+        };
+        //let type_token = token_series_id.split("|").collect::<Vec<&str>>()[1].to_string();
+
+        // el descuento se aplica al precio en USD antes de convertir con la tasa
+        let promo_res = promo_code.map(|code| self.internal_use_promo_code(&token_series_id, &code, price_list));
+        let discount_usd: f64 = promo_res.as_ref().map(|(_, discount_usd)| *discount_usd).unwrap_or(0.0);
+        let price: f64 = price_list - discount_usd;
+         
+        let price_near: f64 = price / self.tasa;
+        let price_yocto: u128 = (price_near * 10u128.pow(24) as f64) as u128;
+
+        assert!(
+            attached_deposit >= (price_yocto + 50_000_000_000_000_000_000_000u128),
+            "attached deposit is less than price : {}",
+            (price_yocto + 50_000_000_000_000_000_000_000u128)
+        );
+
+        let token_id: TokenId = self._nft_mint_series(token_series_id.clone(), receiver_id.clone());
+        // precio pagado, base del reembolso si el ticket se devuelve
+        self.ticket_prices.insert(&token_id, &price_yocto);
+
+        let mut list_objects: Vec<String> = Vec::new();
+        for item in token_event.objects_mint.iter() {
+            self._nft_mint_series(item.to_string(), receiver_id.clone());
+            list_objects.push(item.to_string());
+        }
+        
+        let mut for_vault: u128 = price_yocto * (self.vault_fee as u128) / 10_000u128;
+        let price_deducted: u128 = price_yocto - for_vault;
+
+        // un codigo invalido, eliminado o del propio comprador no bloquea la compra
+        let affiliate_code: Option<AffiliateCode> = referrer.and_then(|code| {
+            let affiliate_code = self
+                .affiliate_codes
+                .get(&affiliate_code_key(&token_series_id, &code.trim().to_lowercase()))
+                .filter(|affiliate_code| affiliate_code.affiliate_id != env::predecessor_account_id());
+            if affiliate_code.is_none() {
+                env::log_str(
+                    &json!({
+                        "type": "nft_buy_referrer_ignored",
+                        "params": {
+                            "token_series_id": token_series_id,
+                            "referrer": code,
+                        }
+                    })
+                    .to_string(),
+                );
+            }
+            affiliate_code
+        });
+
+        // la comision de vault se descuenta antes del reparto del fee
+        let mut commission: u128 = 0;
+        if let Some(affiliate_code) = affiliate_code.as_ref() {
+            if affiliate_code.source == CommissionSource::Vault {
+                commission = for_vault * (affiliate_code.commission_bps as u128) / 10_000u128;
+                for_vault -= commission;
+            }
+        }
+        
+        let vault_fee_res: Vec<RoyaltyBuy> = self.internal_pay_vault_fee(for_vault);
+
+        let mut royalty_buy_res: Vec<RoyaltyBuy> = Vec::new();
+
+        let mut total_royalte: u128 = 0;
+        for (k, v) in token_event.royalty_buy.iter() {
+            let amount: u128 = (price_deducted * (*v as u128)) / 10000;
+
+            royalty_buy_res.push(RoyaltyBuy{
+                wallet: k.clone(),
+                porcentaje: v.to_string(),
+                amount: amount.to_string(),
+            });
+            Promise::new(k.clone()).transfer(amount);
+            total_royalte += amount;
+        };
+
+        let mut amount_creator: u128 = price_deducted - total_royalte;
+        if let Some(affiliate_code) = affiliate_code.as_ref() {
+            if affiliate_code.source == CommissionSource::Creator {
+                commission = amount_creator * (affiliate_code.commission_bps as u128) / 10_000u128;
+                amount_creator -= commission;
+            }
+        }
+        Promise::new(token_event.creator_id.clone()).transfer(amount_creator);
+
+        let affiliate_res = affiliate_code.map(|affiliate_code| self.internal_pay_affiliate(affiliate_code, commission));
+        
+        refund_deposit(env::storage_usage() - initial_storage_usage, price_yocto);
+        
+        let is_mintable: bool = self.token_series_by_id.get(&token_series_id.clone()).expect("Token series not exist").is_mintable;
+
+        env::log_str(
+            &json!({
+                "type": "nft_buy",
+                "params": {
+                    "token_series_id": token_series_id.clone(),
+                    "list_objects": list_objects,
+                    "tasa": self.tasa,
+                    "price_usd": price.to_string(),
+                    "promo": promo_res.map(|(code_hash, discount_usd)| json!({
+                        "code_hash": code_hash,
+                        "price_usd_list": price_list.to_string(),
+                        "discount_usd": discount_usd.to_string(),
+                    })),
+                    "price": price_yocto.to_string(),
+                    "amount_mintick": for_vault.to_string(),
+                    "mintick_splits": vault_fee_res,
+                    "amount_creator": amount_creator.to_string(),
+                    "royalty": royalty_buy_res,
+                    "affiliate": affiliate_res,
+                    "is_mintable": is_mintable,
+                }
+            })
+            .to_string(),
+        );
+        
+    }
+
+
+    // lista de espera de un evento agotado, se deposita el precio actual en NEAR mas el storage del mint
+    #[payable]
+    pub fn join_waitlist(&mut self, token_event_id: TokenSeriesId, receiver_id: Option<AccountId>) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(token_event_id.starts_with("1|"), "token_event_id not valid!");
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        let price: f64 = token_event.price.expect("not for sale");
+        // solo por falta de copias, un evento pausado no acepta depositos
+        assert!(
+            self.internal_series_sold_out(&token_event),
+            "event is not sold out, use nft_buy"
+        );
+
+        let account_id = env::predecessor_account_id();
+        let account_key = waitlist_account_key(&token_event_id, &account_id);
+        assert!(self.waitlist_by_account.get(&account_key).is_none(), "already in the waitlist");
+
+        let price_near: f64 = price / self.tasa;
+        let price_yocto: u128 = (price_near * 10u128.pow(24) as f64) as u128;
+
+        let entry_id = self.id_waitlist;
+        self.id_waitlist += 1;
+        let entry = WaitlistEntry {
+            account_id: account_id.clone(),
+            receiver_id: receiver_id.unwrap_or_else(|| account_id.clone()),
+            price: U128(price_yocto),
+            storage_deposit: U128(WAITLIST_MINT_DEPOSIT),
+            joined_at: U64(env::block_timestamp()),
+        };
+        self.waitlist.insert(&(token_event_id.clone(), entry_id), &entry);
+        self.waitlist_by_account.insert(&account_key, &entry_id);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, price_yocto + WAITLIST_MINT_DEPOSIT);
+
+        env::log_str(
+            &json!({
+                "type": "join_waitlist",
+                "params": {
+                    "entry_id": U64(entry_id),
+                    "token_event_id": token_event_id,
+                    "entry": entry,
+                    "tasa": self.tasa,
+                    "price_usd": price.to_string(),
+                }
+            })
+            .to_string(),
+        );
+
+        U64(entry_id)
+    }
+
+    // sale de la lista de espera y recupera el deposito
+    #[payable]
+    pub fn leave_waitlist(&mut self, token_event_id: TokenSeriesId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let entry_id = self
+            .waitlist_by_account
+            .remove(&waitlist_account_key(&token_event_id, &account_id))
+            .expect("not in the waitlist");
+        let entry = self.waitlist.remove(&(token_event_id.clone(), entry_id)).unwrap();
+
+        Promise::new(account_id).transfer(entry.price.0 + entry.storage_deposit.0);
+
+        env::log_str(
+            &json!({
+                "type": "leave_waitlist",
+                "params": {
+                    "entry_id": U64(entry_id),
+                    "token_event_id": token_event_id,
+                    "entry": entry,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // cualquiera puede asignar copias disponibles a la lista de espera
+    pub fn allocate_waitlist(&mut self, token_event_id: TokenSeriesId) -> u32 {
+        self.internal_allocate_waitlist(&token_event_id)
+    }
+
+    pub fn get_waitlist(
+        &self,
+        token_event_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<WaitlistEntryJson> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.waitlist
+            .range((Bound::Included((token_event_id.clone(), 0)), Bound::Included((token_event_id.clone(), u64::MAX))))
+            .enumerate()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|(index, ((token_event_id, entry_id), entry))| WaitlistEntryJson {
+                entry_id: U64(entry_id),
+                token_event_id,
+                position: U64(index as u64 + 1),
+                entry,
+            })
+            .collect()
+    }
+
+    pub fn get_waitlist_supply(&self, token_event_id: TokenSeriesId) -> U64 {
+        U64(self
+            .waitlist
+            .range((Bound::Included((token_event_id.clone(), 0)), Bound::Included((token_event_id, u64::MAX))))
+            .count() as u64)
+    }
+
+    // posicion en la cola empezando en 1
+    pub fn get_waitlist_position(&self, token_event_id: TokenSeriesId, account_id: AccountId) -> Option<U64> {
+        let entry_id = self.waitlist_by_account.get(&waitlist_account_key(&token_event_id, &account_id))?;
+        let ahead = self
+            .waitlist
+            .range((Bound::Included((token_event_id.clone(), 0)), Bound::Excluded((token_event_id, entry_id))))
+            .count();
+        Some(U64(ahead as u64 + 1))
+    }
+
+    fn internal_series_sold_out(&self, token_series: &TokenSeries) -> bool {
+        token_series.tokens.len() >= token_series.metadata.copies.unwrap_or(u64::MAX)
+    }
+
+    fn internal_series_available(&self, token_series: &TokenSeries) -> bool {
+        token_series.is_mintable && !self.internal_series_sold_out(token_series)
+    }
+
+    // los objetos del evento tambien se mintean en cada venta
+    fn internal_objects_available(&self, token_event: &TokenSeries) -> bool {
+        token_event.objects_mint.iter().all(|item| {
+            self.token_series_by_id
+                .get(&item)
+                .map(|object| self.internal_series_available(&object))
+                .unwrap_or(false)
+        })
+    }
+
+    fn internal_waitlist_head(&self, token_event_id: &TokenSeriesId) -> Option<(TokenSeriesId, u64)> {
+        self.waitlist
+            .ceil_key(&(token_event_id.clone(), 0))
+            .filter(|key| &key.0 == token_event_id)
+    }
+
+    // la cola tiene prioridad sobre las compras abiertas
+    fn assert_waitlist_empty(&self, token_event_id: &TokenSeriesId) {
+        assert!(
+            self.internal_waitlist_head(token_event_id).is_none(),
+            "waitlist pending, copies are reserved for the waitlist"
+        );
+    }
+
+    // mintea a los primeros de la cola mientras haya copias
+    fn internal_allocate_waitlist(&mut self, token_event_id: &TokenSeriesId) -> u32 {
+        let mut allocated: u32 = 0;
+        for _ in 0..MAX_WAITLIST_ALLOCATIONS {
+            let token_event = self.token_series_by_id.get(token_event_id).expect("Token series not exist");
+            if !self.internal_series_available(&token_event) {
+                break;
+            }
+            let key = match self.internal_waitlist_head(token_event_id) {
+                Some(key) => key,
+                None => break,
+            };
+            let entry = self.waitlist.remove(&key).unwrap();
+            self.waitlist_by_account.remove(&waitlist_account_key(token_event_id, &entry.account_id));
+
+            // sin objetos disponibles la entrada se reembolsa para no bloquear la cola ni las ventas
+            if !self.internal_objects_available(&token_event) {
+                Promise::new(entry.account_id.clone()).transfer(entry.price.0 + entry.storage_deposit.0);
+                env::log_str(
+                    &json!({
+                        "type": "waitlist_refund",
+                        "params": {
+                            "token_series_id": token_event_id,
+                            "entry_id": U64(key.1),
+                            "entry": entry,
+                        }
+                    })
+                    .to_string(),
+                );
+                continue;
+            }
+
+            self.internal_fill_waitlist_entry(token_event_id, token_event, key.1, entry);
+            allocated += 1;
+        }
+        allocated
+    }
+
+    fn internal_fill_waitlist_entry(&mut self, token_event_id: &TokenSeriesId, token_event: TokenSeries, entry_id: u64, entry: WaitlistEntry) {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_id: TokenId = self._nft_mint_series(token_event_id.clone(), entry.receiver_id.clone());
+        self.ticket_prices.insert(&token_id, &entry.price.0);
+        let mut list_objects: Vec<String> = Vec::new();
+        for item in token_event.objects_mint.iter() {
+            self._nft_mint_series(item.to_string(), entry.receiver_id.clone());
+            list_objects.push(item.to_string());
+        }
+
+        // el storage sale del deposito y, si no alcanza, del precio, nunca del balance del contrato
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        let mut sale_price: u128 = entry.price.0;
+        if storage_cost > entry.storage_deposit.0 {
+            let storage_shortfall = storage_cost - entry.storage_deposit.0;
+            assert!(storage_shortfall <= sale_price, "waitlist deposit does not cover the mint storage");
+            sale_price -= storage_shortfall;
+        } else {
+            let storage_refund = entry.storage_deposit.0 - storage_cost;
+            if storage_refund > 1 {
+                Promise::new(entry.account_id.clone()).transfer(storage_refund);
+            }
+        }
+
+        let sale_res = self.internal_pay_waitlist_sale(&token_event, sale_price);
+
+        env::log_str(
+            &json!({
+                "type": "waitlist_allocate",
+                "params": {
+                    "entry_id": U64(entry_id),
+                    "token_series_id": token_event_id,
+                    "token_id": token_id,
+                    "account_id": entry.account_id,
+                    "receiver_id": entry.receiver_id,
+                    "list_objects": list_objects,
+                    "price": entry.price.0.to_string(),
+                    "sale": sale_res,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // mismo reparto que nft_buy sobre el precio depositado
+    fn internal_pay_waitlist_sale(&self, token_event: &TokenSeries, price_yocto: u128) -> serde_json::Value {
+        let for_vault: u128 = price_yocto * (self.vault_fee as u128) / 10_000u128;
+        let price_deducted: u128 = price_yocto - for_vault;
+        let vault_fee_res: Vec<RoyaltyBuy> = self.internal_pay_vault_fee(for_vault);
+
+        let mut royalty_buy_res: Vec<RoyaltyBuy> = Vec::new();
+        let mut total_royalte: u128 = 0;
+        for (k, v) in token_event.royalty_buy.iter() {
+            let amount: u128 = (price_deducted * (*v as u128)) / 10000;
+            royalty_buy_res.push(RoyaltyBuy{
+                wallet: k.clone(),
+                porcentaje: v.to_string(),
+                amount: amount.to_string(),
+            });
+            Promise::new(k.clone()).transfer(amount);
+            total_royalte += amount;
+        }
+        let amount_creator: u128 = price_deducted - total_royalte;
+        Promise::new(token_event.creator_id.clone()).transfer(amount_creator);
+
+        json!({
+            "amount_mintick": for_vault.to_string(),
+            "mintick_splits": vault_fee_res,
+            "amount_creator": amount_creator.to_string(),
+            "royalty": royalty_buy_res,
+        })
+    }
+
+    // politica de devolucion del evento, None la desactiva
+    pub fn set_return_policy(&mut self, token_event_id: TokenSeriesId, policy: Option<ReturnPolicy>) {
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert!(token_event_id.starts_with("1|"), "token_event_id not valid!");
+        assert!(
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+
+        // con tickets vendidos la politica solo puede mejorar para el comprador
+        let current = self.return_policies.get(&token_event_id);
+        if let (Some(current), false) = (current.as_ref(), token_event.tokens.is_empty()) {
+            let policy = policy.as_ref().expect("return policy cannot be removed after tickets are sold");
+            assert!(policy.refund_bps >= current.refund_bps, "refund_bps cannot decrease after tickets are sold");
+            assert!(policy.deadline.0 <= current.deadline.0, "deadline cannot tighten after tickets are sold");
+        }
+
+        match policy.as_ref() {
+            Some(policy) => {
+                assert!(token_event.event_info.is_some(), "event_info is required for a return policy");
+                assert!(policy.refund_bps > 0 && policy.refund_bps <= 10_000, "refund_bps must be between 1 and 10000");
+                self.return_policies.insert(&token_event_id, policy);
+            }
+            None => {
+                self.return_policies.remove(&token_event_id);
+            }
+        }
+
+        env::log_str(
+            &json!({
+                "type": "set_return_policy",
+                "params": {
+                    "token_event_id": token_event_id,
+                    "policy": policy,
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    // deposito del organizador del que salen los reembolsos
+    #[payable]
+    pub fn deposit_return_escrow(&mut self, token_event_id: TokenSeriesId) -> U128 {
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert_eq!(env::predecessor_account_id(), token_event.creator_id, "not creator");
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "attached deposit is zero");
+
+        let escrow = self.return_escrows.get(&token_event_id).unwrap_or(0) + amount;
+        self.return_escrows.insert(&token_event_id, &escrow);
+
+        env::log_str(
+            &json!({
+                "type": "deposit_return_escrow",
+                "params": {
+                    "token_event_id": token_event_id,
+                    "amount": U128(amount),
+                    "escrow": U128(escrow),
+                }
+            })
+            .to_string(),
+        );
+        U128(escrow)
+    }
+
+    // el organizador recupera el deposito cuando la ventana de devolucion ya cerro (starts_at - deadline)
+    #[payable]
+    pub fn withdraw_return_escrow(&mut self, token_event_id: TokenSeriesId, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        assert_eq!(env::predecessor_account_id(), token_event.creator_id, "not creator");
+        if let Some(closes_at) = self.internal_return_closes_at(&token_event_id, &token_event) {
+            assert!(env::block_timestamp() >= closes_at, "escrow is locked until {}", closes_at);
+        }
+
+        let escrow = self.return_escrows.get(&token_event_id).unwrap_or(0);
+        let amount = amount.map(|amount| amount.0).unwrap_or(escrow);
+        assert!(amount > 0 && amount <= escrow, "Insufficient escrow: {}, requested {}", escrow, amount);
+        if amount == escrow {
+            self.return_escrows.remove(&token_event_id);
+        } else {
+            self.return_escrows.insert(&token_event_id, &(escrow - amount));
+        }
+        Promise::new(token_event.creator_id).transfer(amount);
+
+        env::log_str(
+            &json!({
+                "type": "withdraw_return_escrow",
+                "params": {
+                    "token_event_id": token_event_id,
+                    "amount": U128(amount),
+                    "escrow": U128(escrow - amount),
+                }
+            })
+            .to_string(),
+        );
+        U128(escrow - amount)
+    }
+
+    // devuelve el ticket y sus objetos 2|n y 3|n al organizador a cambio del reembolso
+    // si hay lista de espera el ticket pasa al primero de la cola
+    #[payable]
+    pub fn return_ticket(&mut self, token_id: TokenId) -> U128 {
+        assert_one_yocto();
+        let token_event_id: TokenSeriesId = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        assert!(token_event_id.starts_with("1|"), "token_id not valid for return_ticket!");
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        assert_eq!(owner_id, env::predecessor_account_id(), "Token owner only");
+
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        let policy = self.return_policies.get(&token_event_id).expect("event does not accept returns");
+        assert!(self.internal_return_open(&token_event_id, &token_event), "return window is closed");
+
+        // objetos del holder que pertenecen al evento, uno por serie
+        let owner_tokens = self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|tokens_per_owner| tokens_per_owner.get(&owner_id))
+            .map(|token_ids| token_ids.to_vec())
+            .unwrap_or_default();
+        let mut linked_objects: Vec<TokenId> = Vec::new();
+        for object_series_id in token_event.objects_mint.iter() {
+            let prefix = format!("{}{}", object_series_id, TOKEN_DELIMETER);
+            let object_token_id = owner_tokens.iter().find(|owner_token_id| owner_token_id.starts_with(&prefix));
+            if object_series_id.starts_with("2|") {
+                assert!(object_token_id.is_some(), "ticket already used, entry object not found");
+            }
+            if let Some(object_token_id) = object_token_id {
+                linked_objects.push(object_token_id.clone());
+            }
+        }
+
+        let price_yocto = self.ticket_prices.get(&token_id).unwrap_or_else(|| {
+            let price: f64 = token_event.price.expect("not for sale");
+            (price / self.tasa * 10u128.pow(24) as f64) as u128
+        });
+        let refund: u128 = price_yocto * (policy.refund_bps as u128) / 10_000u128;
+        let escrow = self.return_escrows.get(&token_event_id).unwrap_or(0);
+        assert!(refund <= escrow, "return escrow is not enough for the refund: {}", refund);
+        self.return_escrows.insert(&token_event_id, &(escrow - refund));
+        if refund > 0 {
+            Promise::new(owner_id.clone()).transfer(refund);
+        }
+
+        let mut token_ids = vec![token_id.clone()];
+        token_ids.extend(linked_objects);
+
+        let waitlist_key = self.internal_waitlist_head(&token_event_id);
+        let destination: serde_json::Value = if let Some(waitlist_key) = waitlist_key {
+            let entry = self.waitlist.remove(&waitlist_key).unwrap();
+            self.waitlist_by_account.remove(&waitlist_account_key(&token_event_id, &entry.account_id));
+
+            for item in token_ids.iter() {
+                self.tokens.internal_transfer(&owner_id, &entry.receiver_id, item, None, None);
+            }
+            NearEvent::log_nft_transfer(owner_id.to_string(), entry.receiver_id.to_string(), token_ids.clone(), None, None);
+            self.ticket_prices.insert(&token_id, &entry.price.0);
+
+            // no hay mint, todo el deposito de storage vuelve
+            Promise::new(entry.account_id.clone()).transfer(entry.storage_deposit.0);
+            let sale_res = self.internal_pay_waitlist_sale(&token_event, entry.price.0);
+            json!({
+                "waitlist_entry_id": U64(waitlist_key.1),
+                "receiver_id": entry.receiver_id,
+                "sale": sale_res,
+            })
+        } else if policy.mode == ReturnMode::Pool {
+            for item in token_ids.iter() {
+                self.tokens.internal_transfer(&owner_id, &token_event.creator_id, item, None, None);
+            }
+            NearEvent::log_nft_transfer(owner_id.to_string(), token_event.creator_id.to_string(), token_ids.clone(), None, None);
+            self.ticket_prices.remove(&token_id);
+            json!({ "receiver_id": token_event.creator_id })
+        } else {
+            for item in token_ids.iter() {
+                self.internal_burn_token(&owner_id, item);
+            }
+            NearEvent::log_nft_burn(owner_id.to_string(), token_ids.clone(), None, None);
+            self.ticket_prices.remove(&token_id);
+            json!({ "burned": true })
+        };
+
+        env::log_str(
+            &json!({
+                "type": "return_ticket",
+                "params": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "token_ids": token_ids,
+                    "price": price_yocto.to_string(),
+                    "refund": refund.to_string(),
+                    "destination": destination,
+                }
+            })
+            .to_string(),
+        );
+        U128(refund)
+    }
+
+    pub fn get_return_policy(&self, token_event_id: TokenSeriesId) -> Option<ReturnPolicy> {
+        self.return_policies.get(&token_event_id)
+    }
+
+    pub fn get_return_escrow(&self, token_event_id: TokenSeriesId) -> U128 {
+        U128(self.return_escrows.get(&token_event_id).unwrap_or(0))
+    }
+
+    // reembolso que recibiria el ticket si se devuelve ahora, None si no se puede devolver
+    pub fn get_ticket_refund(&self, token_id: TokenId) -> Option<U128> {
+        let token_event_id: TokenSeriesId = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_event = self.token_series_by_id.get(&token_event_id)?;
+        let policy = self.return_policies.get(&token_event_id)?;
+        if !self.internal_return_open(&token_event_id, &token_event) {
+            return None;
+        }
+        let price_yocto = self.ticket_prices.get(&token_id)?;
+        Some(U128(price_yocto * (policy.refund_bps as u128) / 10_000u128))
+    }
+
+    fn internal_return_open(&self, token_event_id: &TokenSeriesId, token_event: &TokenSeries) -> bool {
+        self.internal_return_closes_at(token_event_id, token_event)
+            .map(|closes_at| env::block_timestamp() < closes_at)
+            .unwrap_or(false)
+    }
+
+    fn internal_return_closes_at(&self, token_event_id: &TokenSeriesId, token_event: &TokenSeries) -> Option<u64> {
+        let policy = self.return_policies.get(token_event_id)?;
+        let event_info = token_event.event_info.as_ref()?;
+        Some(event_info.starts_at.0.saturating_sub(policy.deadline.0))
+    }
+
+    // autoriza un marketplace a vender el evento (1|n) en venta primaria, solo el creador
+    // msg se reenvia al marketplace para crear el listado (precio)
+    #[payable]
+    pub fn nft_series_market_approve(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        market_id: AccountId,
+        msg: String,
+    ) -> Promise {
+        let initial_storage_usage = env::storage_usage();
+        let token_event = self.token_series_by_id.get(&token_series_id).expect("Token series not exist");
+        assert!(token_series_id.starts_with("1|"), "token_series_id not valid!");
+        assert_eq!(env::predecessor_account_id(), token_event.creator_id, "not creator");
+        assert!(token_event.is_mintable, "Token series is not mintable");
+
+        self.primary_markets.insert(&token_series_id, &market_id);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
 
-        let price: f64 = token_event.price.expect("not for sale");
-        let attached_deposit = env::attached_deposit();
-        let receiver_id: AccountId = if let Some(receiver_id) = receiver_id {
-            if !is_valid_account_id(receiver_id.as_bytes()) {
-                env::panic_str("Not valid account_id for royalty");
-            };
-            receiver_id
-        } else {
-            env::predecessor_account_id()
-        };
-        //let type_token = token_series_id.split("|").collect::<Vec<&str>>()[1].to_string();
-         
-        let price_near: f64 = price / self.tasa;
-        let price_yocto: u128 = (price_near * 10u128.pow(24) as f64) as u128;
+        env::log_str(
+            &json!({
+                "type": "nft_series_market_approve",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "market_id": market_id,
+                    "msg": msg,
+                }
+            })
+            .to_string(),
+        );
+
+        ext_primary_market::ext(market_id.clone())
+            .with_static_gas(GAS_FOR_SERIES_MARKET_APPROVE)
+            .nft_on_series_approve(token_series_id.clone(), token_event.creator_id, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SERIES_MARKET_APPROVE)
+                    .resolve_series_market_approve(token_series_id, market_id),
+            )
+    }
+
+    // si el marketplace rechaza el listado se quita la autorizacion
+    #[private]
+    pub fn resolve_series_market_approve(&mut self, token_series_id: TokenSeriesId, market_id: AccountId) -> bool {
+        if is_promise_success() {
+            return true;
+        }
+        if self.primary_markets.get(&token_series_id) == Some(market_id.clone()) {
+            self.primary_markets.remove(&token_series_id);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "nft_series_market_approve_fail",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "market_id": market_id,
+                }
+            })
+            .to_string(),
+        );
+        false
+    }
 
+    pub fn nft_series_market_revoke(&mut self, token_series_id: TokenSeriesId) {
+        let token_event = self.token_series_by_id.get(&token_series_id).expect("Token series not exist");
         assert!(
-            attached_deposit >= (price_yocto + 50_000_000_000_000_000_000_000u128),
-            "attached deposit is less than price : {}",
-            (price_yocto + 50_000_000_000_000_000_000_000u128)
+            self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || token_event.creator_id == env::predecessor_account_id(),
+            "Only creator or administrator"
+        );
+        self.primary_markets.remove(&token_series_id).expect("market not approved");
+
+        env::log_str(
+            &json!({
+                "type": "nft_series_market_revoke",
+                "params": {
+                    "token_series_id": token_series_id,
+                }
+            })
+            .to_string(),
         );
+    }
+
+    pub fn get_primary_market(&self, token_series_id: TokenSeriesId) -> Option<AccountId> {
+        self.primary_markets.get(&token_series_id)
+    }
+
+    // mint perezoso desde el marketplace autorizado, retorna el reparto del pago como nft_transfer_payout
+    // el fee de vault se descuenta igual que en nft_buy
+    // el deposito cubre el storage del mint, el sobrante vuelve al comprador que indica el market
+    #[payable]
+    pub fn nft_mint_primary(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        buyer_id: AccountId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let initial_storage_usage = env::storage_usage();
+        assert_eq!(
+            self.primary_markets.get(&token_series_id),
+            Some(env::predecessor_account_id()),
+            "market not approved for this series"
+        );
+        let token_event = self.token_series_by_id.get(&token_series_id).expect("Token series not exist");
+        self.assert_waitlist_empty(&token_series_id);
+        let for_vault: u128 = balance.0 * (self.vault_fee as u128) / 10_000u128;
+        let price_deducted: u128 = balance.0 - for_vault;
+        let vault_shares = self.internal_vault_fee_shares(for_vault);
+        if let Some(max_len_payout) = max_len_payout {
+            assert!(
+                token_event.royalty_buy.len() + vault_shares.len() < max_len_payout as usize,
+                "Market cannot payout to that many receivers"
+            );
+        }
 
-        //let token_id: TokenId = 
-        self._nft_mint_series(token_series_id.clone(), receiver_id.clone());
+        let token_id: TokenId = self._nft_mint_series(token_series_id.clone(), receiver_id.clone());
+        self.ticket_prices.insert(&token_id, &balance.0);
 
         let mut list_objects: Vec<String> = Vec::new();
         for item in token_event.objects_mint.iter() {
             self._nft_mint_series(item.to_string(), receiver_id.clone());
             list_objects.push(item.to_string());
         }
-        
-        let for_vault: u128 = price_yocto * (self.vault_fee as u128) / 10_000u128;
-        let price_deducted: u128 = price_yocto - for_vault;
-        
-        Promise::new(self.vault_id.clone()).transfer(for_vault);
-
-        let mut royalty_buy_res: Vec<RoyaltyBuy> = Vec::new();
 
+        let mut payout = Payout {
+            payout: HashMap::new(),
+        };
         let mut total_royalte: u128 = 0;
         for (k, v) in token_event.royalty_buy.iter() {
             let amount: u128 = (price_deducted * (*v as u128)) / 10000;
-
-            royalty_buy_res.push(RoyaltyBuy{
-                wallet: k.clone(),
-                porcentaje: v.to_string(),
-                amount: amount.to_string(),
-            });
-            Promise::new(k.clone()).transfer(amount);
+            payout.payout.insert(k.clone(), U128(amount));
             total_royalte += amount;
-        };
-
+        }
         let amount_creator: u128 = price_deducted - total_royalte;
-        Promise::new(token_event.creator_id.clone()).transfer(amount_creator);
-        
-        refund_deposit(env::storage_usage() - initial_storage_usage, price_yocto);
-        
-        let is_mintable: bool = self.token_series_by_id.get(&token_series_id.clone()).expect("Token series not exist").is_mintable;
+        let mut receivers: Vec<(AccountId, u128)> = vec![(token_event.creator_id.clone(), amount_creator)];
+        receivers.extend(vault_shares.into_iter().map(|(wallet, _, amount)| (wallet, amount)));
+        for (wallet, amount) in receivers {
+            let current = payout.payout.get(&wallet).map(|amount| amount.0).unwrap_or(0);
+            payout.payout.insert(wallet, U128(current + amount));
+        }
+
+        let required_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        let attached_deposit = env::attached_deposit();
+        assert!(
+            required_cost <= attached_deposit,
+            "Must attach {} yoctoNEAR to cover storage",
+            required_cost,
+        );
+        let refund = attached_deposit - required_cost;
+        if refund > 1 {
+            Promise::new(buyer_id.clone()).transfer(refund);
+        }
 
         env::log_str(
             &json!({
-                "type": "nft_buy",
+                "type": "nft_mint_primary",
                 "params": {
-                    "token_series_id": token_series_id.clone(),
+                    "token_series_id": token_series_id,
+                    "token_id": token_id,
+                    "receiver_id": receiver_id,
+                    "buyer_id": buyer_id,
+                    "market_id": env::predecessor_account_id(),
                     "list_objects": list_objects,
-                    "tasa": self.tasa,
-                    "price_usd": price.to_string(),
-                    "price": price_yocto.to_string(),
-                    "amount_mintick": for_vault.to_string(),
-                    "amount_creator": amount_creator.to_string(),
-                    "royalty": royalty_buy_res,
-                    "is_mintable": is_mintable,
+                    "price": balance,
+                    "payout": payout.payout,
                 }
             })
             .to_string(),
         );
-        
-    }
 
+        payout
+    }
 
     #[payable]
     pub fn nft_mint(
@@ -912,37 +2919,41 @@ impl Contract {
 
 
 
-    #[payable]
-    pub fn nft_burn(&mut self, token_id: TokenId) {
-        assert_one_yocto();
-
-        let owner_id = self.tokens.owner_by_id.get(&token_id).unwrap();
-        
-        assert_eq!(
-            owner_id,
-            env::predecessor_account_id(),
-            "Token owner only"
-        );
-
+    fn internal_burn_token(&mut self, owner_id: &AccountId, token_id: &TokenId) {
         if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
-            next_approval_id_by_id.remove(&token_id);
+            next_approval_id_by_id.remove(token_id);
         }
 
         if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
-            approvals_by_id.remove(&token_id);
+            approvals_by_id.remove(token_id);
         }
 
         if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
-            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap();
-            token_ids.remove(&token_id);
-            tokens_per_owner.insert(&owner_id, &token_ids);
+            let mut token_ids = tokens_per_owner.get(owner_id).unwrap();
+            token_ids.remove(token_id);
+            tokens_per_owner.insert(owner_id, &token_ids);
         }
 
         if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
-            token_metadata_by_id.remove(&token_id);
+            token_metadata_by_id.remove(token_id);
         }
 
-        self.tokens.owner_by_id.remove(&token_id);
+        self.tokens.owner_by_id.remove(token_id);
+    }
+
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+
+        let owner_id = self.tokens.owner_by_id.get(&token_id).unwrap();
+        
+        assert_eq!(
+            owner_id,
+            env::predecessor_account_id(),
+            "Token owner only"
+        );
+
+        self.internal_burn_token(&owner_id, &token_id);
 
         NearEvent::log_nft_burn(
             owner_id.to_string(),
@@ -979,25 +2990,7 @@ impl Contract {
         assert!(self.owner_id == env::predecessor_account_id() || self.list_admin.contains(&env::signer_account_id()) || owner_id == env::predecessor_account_id(), "Only creator or administrator");
 
 
-        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
-            next_approval_id_by_id.remove(&token_id);
-        }
-
-        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
-            approvals_by_id.remove(&token_id);
-        }
-
-        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
-            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap();
-            token_ids.remove(&token_id);
-            tokens_per_owner.insert(&owner_id, &token_ids);
-        }
-
-        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
-            token_metadata_by_id.remove(&token_id);
-        }
-
-        self.tokens.owner_by_id.remove(&token_id);
+        self.internal_burn_token(&owner_id, &token_id);
 
         NearEvent::log_nft_burn(
             owner_id.to_string(),
@@ -1031,64 +3024,237 @@ impl Contract {
 
         let signer_id: AccountId = env::predecessor_account_id();
 
-        assert!(serie.creator_id == signer_id || self.owner_id == signer_id || self.list_admin.contains(&signer_id), "Only administrator");
+        assert!(serie.creator_id == signer_id || self.owner_id == signer_id || self.list_admin.contains(&signer_id), "Only administrator");
+
+        
+
+        if serie.tokens.contains(&token_id.clone()) {
+            env::log_str(
+                &json!({
+                    "type": "approved_object",
+                    "params": {
+                        "token_id": token_id.clone(),
+                        "user_approved": env::predecessor_account_id()
+                    }
+                })
+                .to_string(),
+            );
+        } else {
+            env::panic_str("token id is not valid!");
+        }
+
+        
+    }
+
+
+    pub fn get_nft_series_copies_availables(&self, token_series_id: TokenSeriesId) -> u64 {
+		let token_series = self.token_series_by_id.get(&token_series_id).expect("Series does not exist");
+        let copies_availables = token_series.metadata.copies.unwrap() - token_series.tokens.len() as u64 ;
+        copies_availables    
+	}
+
+    pub fn get_nft_series(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenSeriesJson2> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (self.token_series_by_id.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.token_series_by_id
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|(token_series_id, token_series)| {
+                let mut price_yocto: Option<u128> = None;
+                if token_series.price.is_some() {
+                    let price_near: f64 = token_series.price.unwrap() / self.tasa;
+                    price_yocto = Some((price_near * 10u128.pow(24) as f64) as u128);
+                }
+                
+                TokenSeriesJson2 {
+                    token_series_id: token_series_id.clone(),
+                    metadata: token_series.metadata,
+                    creator_id: token_series.creator_id,
+                    price: price_yocto,
+                    price_usd: token_series.price,
+                    is_mintable: token_series.is_mintable,
+                    royalty: token_series.royalty
+                }
+            })
+            .collect()
+    }
+
+
+    pub fn get_events_by_creator(
+        &self,
+        creator_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenEventJson> {
+        let events = if let Some(events) = self.events_by_creator.get(&creator_id) {
+            events
+        } else {
+            return vec![];
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (events.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        events
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|token_event_id| self.internal_event_json(token_event_id))
+            .collect()
+    }
+
+    pub fn get_events_by_creator_supply(&self, creator_id: AccountId) -> U64 {
+        self.events_by_creator
+            .get(&creator_id)
+            .map(|events| events.len())
+            .unwrap_or(0)
+            .into()
+    }
+
+    pub fn get_events_by_category(
+        &self,
+        category: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenEventJson> {
+        let events = if let Some(events) = self.events_by_category.get(&category.trim().to_lowercase()) {
+            events
+        } else {
+            return vec![];
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (events.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        events
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|token_event_id| self.internal_event_json(token_event_id))
+            .collect()
+    }
+
+    // eventos ordenados por fecha de inicio, from_date inclusivo y to_date exclusivo
+    pub fn get_events_by_date(
+        &self,
+        from_date: Option<U64>,
+        to_date: Option<U64>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenEventJson> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        let lower = Bound::Included((from_date.map(|date| date.0).unwrap_or(0), String::new()));
+        let upper = match to_date {
+            Some(to_date) => Bound::Excluded((to_date.0, String::new())),
+            None => Bound::Unbounded,
+        };
 
-        
+        self.events_by_date
+            .range((lower, upper))
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|((_, token_event_id), _)| self.internal_event_json(token_event_id))
+            .collect()
+    }
 
-        if serie.tokens.contains(&token_id.clone()) {
-            env::log_str(
-                &json!({
-                    "type": "approved_object",
-                    "params": {
-                        "token_id": token_id.clone(),
-                        "user_approved": env::predecessor_account_id()
-                    }
-                })
-                .to_string(),
-            );
-        } else {
-            env::panic_str("token id is not valid!");
-        }
+    pub fn nft_get_event(&self, token_event_id: TokenSeriesId) -> TokenEventJson {
+        let type_token = token_event_id.split("|").next().unwrap().to_string();
+        assert!(type_token == "1", "token_event_id not valid!");
+        self.internal_event_json(token_event_id)
+    }
 
-        
+    pub fn get_event_info(&self, token_event_id: TokenSeriesId) -> Option<EventInfo> {
+        self.token_series_by_id.get(&token_event_id).expect("Token series not exist").event_info
     }
 
+    pub fn get_event_objects(
+        &self,
+        token_event_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenObjectJson> {
+        let type_token = token_event_id.split("|").next().unwrap().to_string();
+        assert!(type_token == "1", "token_event_id not valid!");
 
-    pub fn get_nft_series_copies_availables(&self, token_series_id: TokenSeriesId) -> u64 {
-		let token_series = self.token_series_by_id.get(&token_series_id).expect("Series does not exist");
-        let copies_availables = token_series.metadata.copies.unwrap() - token_series.tokens.len() as u64 ;
-        copies_availables    
-	}
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (token_event.objects_mint.len() as u128) > start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
 
-    pub fn get_nft_series(
+        token_event.objects_mint
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|token_object_id| {
+                let token_object = self.token_series_by_id.get(&token_object_id).expect("Token series not exist");
+                TokenObjectJson {
+                    token_series_id: token_object_id,
+                    token_event_id: token_object.metadata.reference.clone(),
+                    supply: token_object.tokens.len().into(),
+                    metadata: token_object.metadata,
+                    creator_id: token_object.creator_id,
+                    is_mintable: token_object.is_mintable,
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_nft_series_by_type(
         &self,
+        type_token: String,
         from_index: Option<U128>,
         limit: Option<u64>,
     ) -> Vec<TokenSeriesJson2> {
+        let series = if let Some(series) = self.series_by_type.get(&type_token) {
+            series
+        } else {
+            return vec![];
+        };
         let start_index: u128 = from_index.map(From::from).unwrap_or_default();
         assert!(
-            (self.token_series_by_id.len() as u128) > start_index,
+            (series.len() as u128) > start_index,
             "Out of bounds, please use a smaller from_index."
         );
         let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
         assert_ne!(limit, 0, "Cannot provide limit of 0.");
 
-        self.token_series_by_id
+        series
             .iter()
             .skip(start_index as usize)
             .take(limit)
-            .map(|(token_series_id, token_series)| {
-                let mut price_yocto: Option<u128> = None;
-                if token_series.price.is_some() {
-                    let price_near: f64 = token_series.price.unwrap() / self.tasa;
-                    price_yocto = Some((price_near * 10u128.pow(24) as f64) as u128);
-                }
-                
+            .map(|token_series_id| {
+                let token_series = self.token_series_by_id.get(&token_series_id).expect("Token series not exist");
                 TokenSeriesJson2 {
-                    token_series_id: token_series_id.clone(),
+                    token_series_id,
                     metadata: token_series.metadata,
                     creator_id: token_series.creator_id,
-                    price: price_yocto,
+                    price: token_series.price.and_then(|price| self.internal_price_yocto(price)).map(|price| price.0),
                     price_usd: token_series.price,
                     is_mintable: token_series.is_mintable,
                     royalty: token_series.royalty
@@ -1097,6 +3263,42 @@ impl Contract {
             .collect()
     }
 
+    pub fn get_nft_series_by_type_supply(&self, type_token: String) -> U64 {
+        self.series_by_type
+            .get(&type_token)
+            .map(|series| series.len())
+            .unwrap_or(0)
+            .into()
+    }
+
+    fn internal_event_json(&self, token_event_id: TokenSeriesId) -> TokenEventJson {
+        let token_event = self.token_series_by_id.get(&token_event_id).expect("Token series not exist");
+
+        TokenEventJson {
+            token_series_id: token_event_id,
+            price: token_event.price.and_then(|price| self.internal_price_yocto(price)),
+            price_usd: token_event.price,
+            is_mintable: token_event.is_mintable,
+            objects_mint: token_event.objects_mint.to_vec(),
+            supply: token_event.tokens.len().into(),
+            metadata: token_event.metadata,
+            creator_id: token_event.creator_id,
+            royalty: token_event.royalty,
+            royalty_buy: token_event.royalty_buy,
+            event_info: token_event.event_info,
+        }
+    }
+
+    // precio en usd convertido a yocto con la tasa actual
+    // None mientras no haya tasa configurada
+    fn internal_price_yocto(&self, price_usd: f64) -> Option<U128> {
+        if self.tasa <= 0.0 {
+            return None;
+        }
+        let price_near: f64 = price_usd / self.tasa;
+        Some(U128((price_near * 10u128.pow(24) as f64) as u128))
+    }
+
 
     pub fn nft_token(&self, token_id: TokenId) -> Option<TokenCustom> {
         let owner_id = self.tokens.owner_by_id.get(&token_id)?;
@@ -1472,6 +3674,24 @@ impl NonFungibleTokenResolver for Contract {
 }
 
 
+fn affiliate_code_key(token_event_id: &TokenSeriesId, code: &str) -> String {
+    format!("{}#{}", token_event_id, code)
+}
+
+fn waitlist_account_key(token_event_id: &TokenSeriesId, account_id: &AccountId) -> String {
+    format!("{}#{}", token_event_id, account_id)
+}
+
+fn promo_code_key(token_event_id: &TokenSeriesId, code_hash: &[u8]) -> String {
+    let code_hash: String = code_hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}#{}", token_event_id, code_hash)
+}
+
+fn is_promise_success() -> bool {
+    assert_eq!(env::promise_results_count(), 1, "Contract expected a result on the callback");
+    matches!(env::promise_result(0), PromiseResult::Successful(_))
+}
+
 fn refund_deposit(storage_used: u64, extra_spend: Balance) {
     let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
     let attached_deposit = env::attached_deposit() - extra_spend;
@@ -1499,7 +3719,9 @@ mod tests {
 
     use super::*;
 
-    const MINT_STORAGE_COST: u128 = 5870000000000000000000;
+    const ONE_NEAR: u128 = 10u128.pow(24);
+    const EVENT_STARTS_AT: u64 = 1_000_000_000_000;
+    const RETURN_DEADLINE: u64 = 1_000_000;
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
@@ -1510,6 +3732,15 @@ mod tests {
         builder
     }
 
+    fn set_caller(context: &mut VMContextBuilder, account_id: AccountId, attached_deposit: Balance) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(attached_deposit)
+            .signer_account_id(account_id.clone())
+            .predecessor_account_id(account_id)
+            .build());
+    }
+
     fn sample_token_metadata() -> TokenMetadata {
         TokenMetadata {
             title: Some("Olympus Mons".into()),
@@ -1527,74 +3758,95 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_new() {
-        let mut context = get_context(accounts(1));
+    fn sample_event_info() -> EventInfo {
+        EventInfo {
+            venue_name: "Teatro".into(),
+            coordinates: None,
+            timezone: None,
+            starts_at: U64(EVENT_STARTS_AT),
+            ends_at: None,
+            category: "Music".into(),
+            tags: vec![],
+            age_restriction: None,
+        }
+    }
+
+    // contrato con tasa 1.0 (1 USD = 1 NEAR), owner accounts(0) y vault accounts(5)
+    fn setup_contract() -> (VMContextBuilder, Contract) {
+        let context = get_context(accounts(0));
         testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into());
-        testing_env!(context.is_view(true).build());
-        assert_eq!(contract.nft_token("1".to_string()), None);
+        let mut contract = Contract::new_default_meta(accounts(0), accounts(5));
+        contract.update_tasa(1.0);
+        (context, contract)
+    }
+
+    // evento de accounts(1) a 1 USD con `copies` copias, retorna el id del evento
+    fn create_event(context: &mut VMContextBuilder, contract: &mut Contract, copies: u64, event_info: Option<EventInfo>) -> TokenSeriesId {
+        set_caller(context, accounts(1), ONE_NEAR);
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(copies);
+        contract.nft_event(metadata, Some(1.0), None, None, event_info, None)
+    }
+
+    fn buy(context: &mut VMContextBuilder, contract: &mut Contract, buyer_id: AccountId, token_series_id: &TokenSeriesId) {
+        set_caller(context, buyer_id, 2 * ONE_NEAR);
+        contract.nft_buy(token_series_id.clone(), None, None, None);
+    }
+
+    fn add_copies(context: &mut VMContextBuilder, contract: &mut Contract, token_series_id: &TokenSeriesId, copies: u64) {
+        set_caller(context, accounts(1), 0);
+        contract.update_nft_event(
+            token_series_id.clone(), None, None, None, None, Some(copies), None, None, None, None, None, None,
+        );
+    }
+
+    // mismo redondeo que la conversion del contrato
+    fn usd_to_yocto(price_usd: f64) -> u128 {
+        (price_usd * 10u128.pow(24) as f64) as u128
+    }
+
+    fn owner_of(contract: &Contract, token_id: &str) -> Option<AccountId> {
+        contract.tokens.owner_by_id.get(&token_id.to_string())
     }
 
     #[test]
-    #[should_panic(expected = "The contract is not initialized")]
-    fn test_default() {
-        let context = get_context(accounts(1));
+    fn test_new() {
+        let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let _contract = Contract::default();
+        let contract = Contract::new_default_meta(accounts(1), accounts(5));
+        testing_env!(context.is_view(true).build());
+        assert!(contract.nft_token("1".to_string()).is_none());
     }
 
     #[test]
     fn test_mint() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 1, None);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_id = contract.nft_mint(token_series_id.clone(), accounts(1));
+        assert_eq!(token_id, format!("{}{}1", token_series_id, TOKEN_DELIMETER));
 
-        let token_id = "0".to_string();
-        let token = contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        let token = contract.nft_token(token_id.clone()).unwrap();
         assert_eq!(token.token_id, token_id);
-        assert_eq!(token.owner_id.to_string(), accounts(0).to_string());
-        assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+        assert_eq!(token.owner_id, accounts(1));
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        assert!(!contract.token_series_by_id.get(&token_series_id).unwrap().is_mintable);
     }
 
     #[test]
     fn test_transfer() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
-
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 1, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_id = contract.nft_mint(token_series_id, accounts(1));
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+        set_caller(&mut context, accounts(1), 1);
+        contract.nft_transfer(accounts(2), token_id.clone(), None, None);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
-            .build());
         if let Some(token) = contract.nft_token(token_id.clone()) {
             assert_eq!(token.token_id, token_id);
-            assert_eq!(token.owner_id.to_string(), accounts(1).to_string());
-            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+            assert_eq!(token.owner_id, accounts(2));
             assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
         } else {
             panic!("token not correctly created, or not found by nft_token");
@@ -1603,108 +3855,442 @@ mod tests {
 
     #[test]
     fn test_approve() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
-
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 1, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_id = contract.nft_mint(token_series_id, accounts(1));
 
-        // alice approves bob
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+        set_caller(&mut context, accounts(1), ONE_NEAR / 100);
+        contract.nft_approve(token_id.clone(), accounts(2), None);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
-            .build());
-        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        assert!(contract.nft_is_approved(token_id, accounts(2), Some(1)));
     }
 
     #[test]
     fn test_revoke() {
-        let mut context = get_context(accounts(0));
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 1, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_id = contract.nft_mint(token_series_id, accounts(1));
+
+        set_caller(&mut context, accounts(1), ONE_NEAR / 100);
+        contract.nft_approve(token_id.clone(), accounts(2), None);
+
+        set_caller(&mut context, accounts(1), 1);
+        contract.nft_revoke(token_id.clone(), accounts(2));
+        assert!(!contract.nft_is_approved(token_id, accounts(2), None));
+    }
+
+    #[test]
+    fn test_revoke_all() {
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 1, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_id = contract.nft_mint(token_series_id, accounts(1));
+
+        set_caller(&mut context, accounts(1), ONE_NEAR / 100);
+        contract.nft_approve(token_id.clone(), accounts(2), None);
+
+        set_caller(&mut context, accounts(1), 1);
+        contract.nft_revoke_all(token_id.clone());
+        assert!(!contract.nft_is_approved(token_id, accounts(2), Some(1)));
+    }
+
+    #[test]
+    fn test_event_indexes() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, Some(sample_event_info()));
+
+        assert_eq!(contract.get_events_by_creator_supply(accounts(1)), U64(1));
+        assert_eq!(contract.get_nft_series_by_type_supply("1".to_string()), U64(1));
+        assert_eq!(contract.get_nft_series_by_type_supply("2".to_string()), U64(1));
+        let event = contract.nft_get_event(token_event_id);
+        assert_eq!(event.price, Some(U128(usd_to_yocto(1.0))));
+    }
+
+    #[test]
+    fn test_price_yocto_without_tasa() {
+        let context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let contract = Contract::new_default_meta(accounts(0), accounts(5));
+        assert!(contract.internal_price_yocto(1.0).is_none());
+    }
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_set_entry_object_default_requires_one_yocto() {
+        let (mut context, mut contract) = setup_contract();
+        set_caller(&mut context, accounts(0), 0);
+        contract.set_entry_object_default(contract.get_entry_object_default());
+    }
 
-        // alice approves bob
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_set_vault_fee_splits_requires_one_yocto() {
+        let (mut context, mut contract) = setup_contract();
+        set_caller(&mut context, accounts(0), 0);
+        contract.set_vault_fee_splits(vec![]);
+    }
+
+    #[test]
+    fn test_nft_mint_primary_takes_vault_fee() {
+        let (mut context, mut contract) = setup_contract();
+        let token_series_id = create_event(&mut context, &mut contract, 10, None);
+        contract.primary_markets.insert(&token_series_id, &accounts(3));
+
+        set_caller(&mut context, accounts(3), ONE_NEAR);
+        let payout = contract.nft_mint_primary(token_series_id, accounts(2), accounts(4), U128(10_000), Some(50));
+        // vault_fee 300 bps al vault_id, el resto al creador
+        assert_eq!(payout.payout.get(&accounts(5)), Some(&U128(300)));
+        assert_eq!(payout.payout.get(&accounts(1)), Some(&U128(9_700)));
+        assert_eq!(payout.payout.values().map(|amount| amount.0).sum::<u128>(), 10_000);
+    }
+
+    #[test]
+    fn test_vault_fee_splits() {
+        let (mut context, mut contract) = setup_contract();
+        set_caller(&mut context, accounts(0), 1);
+        contract.set_vault_fee_splits(vec![
+            FeeShare { account_id: accounts(5), bps: 7_000 },
+            FeeShare { account_id: accounts(4), bps: 3_000 },
+        ]);
+
+        let res = contract.internal_pay_vault_fee(1_000);
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].amount, "700");
+        assert_eq!(res[1].amount, "300");
+    }
+
+    #[test]
+    fn test_buy_ignores_invalid_referrer() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+
+        set_caller(&mut context, accounts(2), 2 * ONE_NEAR);
+        contract.nft_buy(token_event_id.clone(), None, Some("unknown".to_string()), None);
+        assert_eq!(owner_of(&contract, &format!("{}:1", token_event_id)), Some(accounts(2)));
+    }
+
+    #[test]
+    fn test_affiliate_commission() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.add_affiliate_code(token_event_id.clone(), "friends".to_string(), accounts(3), 1_000, CommissionSource::Creator);
+
+        set_caller(&mut context, accounts(2), 2 * ONE_NEAR);
+        contract.nft_buy(token_event_id.clone(), None, Some("FRIENDS".to_string()), None);
+
+        let stats = contract.get_affiliate_stats(accounts(3));
+        assert_eq!(stats.sales, U64(1));
+        // 10% de lo que recibe el creador despues del fee de vault (3%)
+        let price_deducted = usd_to_yocto(1.0) - usd_to_yocto(1.0) * 300 / 10_000;
+        assert_eq!(stats.earnings, U128(price_deducted * 1_000 / 10_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "discount bps must be between 1 and 9000")]
+    fn test_promo_code_full_discount() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.add_promo_code(
+            token_event_id,
+            Base64VecU8(env::sha256(b"free")),
+            PromoDiscount::Percentage { bps: 10_000 },
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_promo_code_keeps_min_price_and_cleans_uses() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        let code_hash = Base64VecU8(env::sha256(b"promo"));
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.add_promo_code(
+            token_event_id.clone(),
+            code_hash.clone(),
+            PromoDiscount::FixedUsd { amount: 5.0 },
+            Some(2),
+            Some(1),
+            None,
+        );
+
+        set_caller(&mut context, accounts(2), 2 * ONE_NEAR);
+        contract.nft_buy(token_event_id.clone(), None, None, Some("Promo".to_string()));
+        let price = contract.ticket_prices.get(&format!("{}:1", token_event_id)).unwrap();
+        assert!(price > 0 && price < ONE_NEAR);
+        assert_eq!(contract.get_promo_code_uses(token_event_id.clone(), code_hash.clone(), accounts(2)), 1);
+        assert_eq!(contract.get_promo_code(token_event_id.clone(), code_hash.clone()).unwrap().uses, 1);
+
+        set_caller(&mut context, accounts(1), 0);
+        contract.remove_promo_code(token_event_id.clone(), code_hash.clone());
+        assert!(contract.get_promo_code(token_event_id.clone(), code_hash.clone()).is_none());
+        assert_eq!(contract.get_promo_code_uses(token_event_id, code_hash, accounts(2)), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "promo code limit reached for this account")]
+    fn test_promo_code_per_account_cap() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.add_promo_code(
+            token_event_id.clone(),
+            Base64VecU8(env::sha256(b"promo")),
+            PromoDiscount::Percentage { bps: 1_000 },
+            None,
+            Some(1),
+            None,
+        );
+
+        set_caller(&mut context, accounts(2), 2 * ONE_NEAR);
+        contract.nft_buy(token_event_id.clone(), None, None, Some("promo".to_string()));
+        set_caller(&mut context, accounts(2), 2 * ONE_NEAR);
+        contract.nft_buy(token_event_id, None, None, Some("promo".to_string()));
+    }
 
-        // alice revokes bob
+    #[test]
+    fn test_remove_extra_object() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_object_id = contract.nft_objects(sample_token_metadata(), token_event_id.clone());
+        assert!(contract.token_series_by_id.get(&token_event_id).unwrap().objects_mint.contains(&token_object_id));
+
+        set_caller(&mut context, accounts(1), 0);
+        contract.remove_nft_object(token_object_id.clone());
+        assert!(!contract.token_series_by_id.get(&token_event_id).unwrap().objects_mint.contains(&token_object_id));
+        assert!(!contract.token_series_by_id.get(&token_object_id).unwrap().is_mintable);
+
+        // la compra ya no mintea el objeto removido
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        assert!(owner_of(&contract, &format!("{}:1", token_object_id)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "objects_ids must include the entry object")]
+    fn test_reorder_objects_requires_entry_object() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        let token_object_id = contract.nft_objects(sample_token_metadata(), token_event_id.clone());
+
+        set_caller(&mut context, accounts(1), 0);
+        contract.update_nft_event(
+            token_event_id, None, None, None, None, None, None, Some(vec![token_object_id]), None, None, None, None,
+        );
+    }
+
+    #[test]
+    fn test_waitlist_allocation_on_new_copies() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 1, None);
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+        set_caller(&mut context, accounts(4), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+        assert_eq!(contract.get_waitlist_position(token_event_id.clone(), accounts(4)), Some(U64(2)));
+
+        add_copies(&mut context, &mut contract, &token_event_id, 1);
+        assert_eq!(owner_of(&contract, &format!("{}:2", token_event_id)), Some(accounts(3)));
+        assert_eq!(contract.get_waitlist_supply(token_event_id.clone()), U64(1));
+        assert_eq!(contract.get_waitlist_position(token_event_id, accounts(4)), Some(U64(1)));
+    }
+
+    #[test]
+    fn test_waitlist_refunded_when_objects_run_out() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 1, None);
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+
+        let entry_object_id = contract.token_series_by_id.get(&token_event_id).unwrap().objects_mint.to_vec()[0].clone();
+        contract.internal_set_object_mintable(&entry_object_id, false);
+
+        // sin objetos la entrada se reembolsa y la cola no bloquea las ventas
+        add_copies(&mut context, &mut contract, &token_event_id, 1);
+        assert_eq!(contract.get_waitlist_supply(token_event_id.clone()), U64(0));
+        assert!(contract.get_waitlist_position(token_event_id.clone(), accounts(3)).is_none());
+
+        contract.internal_set_object_mintable(&entry_object_id, true);
+        buy(&mut context, &mut contract, accounts(4), &token_event_id);
+        assert_eq!(owner_of(&contract, &format!("{}:2", token_event_id)), Some(accounts(4)));
+    }
+
+    #[test]
+    #[should_panic(expected = "waitlist pending, copies are reserved for the waitlist")]
+    fn test_buy_with_pending_waitlist() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 1, None);
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+
+        // copias nuevas sin pasar por la asignacion
+        let mut token_event = contract.token_series_by_id.get(&token_event_id).unwrap();
+        token_event.metadata.copies = Some(2);
+        token_event.is_mintable = true;
+        contract.token_series_by_id.insert(&token_event_id, &token_event);
+
+        buy(&mut context, &mut contract, accounts(4), &token_event_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "event is not sold out, use nft_buy")]
+    fn test_join_waitlist_paused_event() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, None);
+        let mut token_event = contract.token_series_by_id.get(&token_event_id).unwrap();
+        token_event.is_mintable = false;
+        contract.token_series_by_id.insert(&token_event_id, &token_event);
+
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id, None);
+    }
+
+    #[test]
+    fn test_leave_waitlist() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 1, None);
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+
+        set_caller(&mut context, accounts(3), 1);
+        contract.leave_waitlist(token_event_id.clone());
+        assert_eq!(contract.get_waitlist_supply(token_event_id.clone()), U64(0));
+        assert!(contract.get_waitlist_position(token_event_id, accounts(3)).is_none());
+    }
+
+    fn setup_returns(mode: ReturnMode) -> (VMContextBuilder, Contract, TokenSeriesId) {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 10, Some(sample_event_info()));
+        set_caller(&mut context, accounts(1), 0);
+        contract.set_return_policy(
+            token_event_id.clone(),
+            Some(ReturnPolicy { refund_bps: 5_000, deadline: U64(RETURN_DEADLINE), mode }),
+        );
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.deposit_return_escrow(token_event_id.clone());
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        (context, contract, token_event_id)
+    }
+
+    #[test]
+    fn test_return_ticket_pool() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
+        let token_id = format!("{}:1", token_event_id);
+        let refund = usd_to_yocto(1.0) / 2;
+        assert_eq!(contract.get_ticket_refund(token_id.clone()), Some(U128(refund)));
+
+        set_caller(&mut context, accounts(2), 1);
+        assert_eq!(contract.return_ticket(token_id.clone()), U128(refund));
+        assert_eq!(owner_of(&contract, &token_id), Some(accounts(1)));
+        assert_eq!(contract.get_return_escrow(token_event_id), U128(ONE_NEAR - refund));
+    }
+
+    #[test]
+    fn test_return_ticket_burn() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Burn);
+        let token_id = format!("{}:1", token_event_id);
+
+        set_caller(&mut context, accounts(2), 1);
+        contract.return_ticket(token_id.clone());
+        assert!(owner_of(&contract, &token_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "return window is closed")]
+    fn test_return_ticket_after_deadline() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
         testing_env!(context
             .storage_usage(env::storage_usage())
+            .block_timestamp(EVENT_STARTS_AT - RETURN_DEADLINE)
             .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_revoke(token_id.clone(), accounts(1));
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+        contract.return_ticket(format!("{}:1", token_event_id));
     }
 
     #[test]
-    fn test_revoke_all() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+    #[should_panic(expected = "return policy cannot be removed after tickets are sold")]
+    fn test_return_policy_cannot_be_removed_after_sale() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
+        set_caller(&mut context, accounts(1), 0);
+        contract.set_return_policy(token_event_id, None);
+    }
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+    #[test]
+    #[should_panic(expected = "refund_bps cannot decrease after tickets are sold")]
+    fn test_return_policy_refund_cannot_decrease_after_sale() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
+        set_caller(&mut context, accounts(1), 0);
+        contract.set_return_policy(
+            token_event_id,
+            Some(ReturnPolicy { refund_bps: 1_000, deadline: U64(RETURN_DEADLINE), mode: ReturnMode::Pool }),
+        );
+    }
 
-        // alice approves bob
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+    #[test]
+    fn test_return_policy_can_improve_after_sale() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
+        set_caller(&mut context, accounts(1), 0);
+        contract.set_return_policy(
+            token_event_id.clone(),
+            Some(ReturnPolicy { refund_bps: 8_000, deadline: U64(RETURN_DEADLINE / 2), mode: ReturnMode::Pool }),
+        );
+        assert_eq!(contract.get_return_policy(token_event_id).unwrap().refund_bps, 8_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "escrow is locked until")]
+    fn test_withdraw_return_escrow_locked() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
+        set_caller(&mut context, accounts(1), 1);
+        contract.withdraw_return_escrow(token_event_id, None);
+    }
 
-        // alice revokes bob
+    #[test]
+    fn test_withdraw_return_escrow_after_window() {
+        let (mut context, mut contract, token_event_id) = setup_returns(ReturnMode::Pool);
         testing_env!(context
             .storage_usage(env::storage_usage())
+            .block_timestamp(EVENT_STARTS_AT - RETURN_DEADLINE)
             .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_revoke_all(token_id.clone());
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        assert_eq!(contract.withdraw_return_escrow(token_event_id.clone(), None), U128(0));
+        assert_eq!(contract.get_return_escrow(token_event_id), U128(0));
+    }
+
+    #[test]
+    fn test_return_ticket_goes_to_waitlist() {
+        let (mut context, mut contract) = setup_contract();
+        let token_event_id = create_event(&mut context, &mut contract, 1, Some(sample_event_info()));
+        set_caller(&mut context, accounts(1), 0);
+        contract.set_return_policy(
+            token_event_id.clone(),
+            Some(ReturnPolicy { refund_bps: 10_000, deadline: U64(RETURN_DEADLINE), mode: ReturnMode::Burn }),
+        );
+        set_caller(&mut context, accounts(1), ONE_NEAR);
+        contract.deposit_return_escrow(token_event_id.clone());
+        buy(&mut context, &mut contract, accounts(2), &token_event_id);
+        set_caller(&mut context, accounts(3), 2 * ONE_NEAR);
+        contract.join_waitlist(token_event_id.clone(), None);
+
+        let token_id = format!("{}:1", token_event_id);
+        set_caller(&mut context, accounts(2), 1);
+        contract.return_ticket(token_id.clone());
+        assert_eq!(owner_of(&contract, &token_id), Some(accounts(3)));
+        assert_eq!(contract.get_waitlist_supply(token_event_id), U64(0));
     }
-}
\ No newline at end of file
+}